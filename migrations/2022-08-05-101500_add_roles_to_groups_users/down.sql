@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE groups_users
+    DROP COLUMN role,
+    DROP COLUMN joined_at;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE groups_users
+    ADD COLUMN role varchar(16) NOT NULL DEFAULT 'member',
+    ADD COLUMN joined_at timestamp NOT NULL DEFAULT now();
+
+UPDATE groups_users
+SET role = 'owner'
+FROM groups
+WHERE groups.id = groups_users.group_id
+  AND groups.owner_id = groups_users.user_id;
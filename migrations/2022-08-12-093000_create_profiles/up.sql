@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE profiles (
+    user_id varchar(36) PRIMARY KEY NOT NULL,
+    display_name varchar(64),
+    avatar_url varchar(512),
+    bio text,
+    status_text varchar(128),
+    status_emoji varchar(32),
+    updated_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
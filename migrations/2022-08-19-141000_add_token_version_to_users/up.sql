@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users
+    ADD COLUMN token_version integer NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE password_resets (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    user_id varchar(36) NOT NULL,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    expires_at timestamp NOT NULL,
+    used_at timestamp,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE messages (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    group_id varchar(36) NOT NULL,
+    sender_id varchar(36),
+    body text,
+    created_at timestamp NOT NULL DEFAULT now(),
+    deleted_at timestamp,
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_sender FOREIGN KEY(sender_id) REFERENCES users(id)
+);
+
+CREATE INDEX messages_group_id_created_at_idx ON messages (group_id, created_at);
+CREATE INDEX messages_sender_id_idx ON messages (sender_id);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE lockout_events (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    subject_kind varchar(16) NOT NULL,
+    subject varchar(255) NOT NULL,
+    failures integer NOT NULL,
+    locked_until timestamp NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now()
+);
+
+CREATE INDEX lockout_events_subject_idx ON lockout_events (subject_kind, subject);
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE user_totp (
+    user_id varchar(36) PRIMARY KEY NOT NULL,
+    secret varchar(64) NOT NULL,
+    enabled_at timestamp,
+    last_used_step bigint,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE TABLE recovery_codes (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    user_id varchar(36) NOT NULL,
+    code_hash varchar(64) NOT NULL,
+    used_at timestamp,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE external_identities (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    user_id varchar(36) NOT NULL,
+    issuer varchar NOT NULL,
+    subject varchar NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id),
+    CONSTRAINT unique_external_subject UNIQUE (issuer, subject)
+);
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE bots (
+    user_id varchar(36) PRIMARY KEY NOT NULL,
+    owner_id varchar(36) NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id),
+    CONSTRAINT fk_owner FOREIGN KEY(owner_id) REFERENCES users(id)
+);
+
+CREATE INDEX bots_owner_id_idx ON bots (owner_id);
+
+CREATE TABLE api_keys (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    user_id varchar(36) NOT NULL,
+    name varchar NOT NULL,
+    prefix varchar(16) NOT NULL,
+    key_hash varchar(64) NOT NULL UNIQUE,
+    scopes text NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    last_used_at timestamp,
+    revoked_at timestamp,
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
@@ -0,0 +1,46 @@
+-- Your SQL goes here
+CREATE TABLE webhooks (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    group_id varchar(36) NOT NULL,
+    url varchar NOT NULL,
+    secret varchar(64) NOT NULL,
+    events text NOT NULL,
+    created_by varchar(36),
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_user FOREIGN KEY(created_by) REFERENCES users(id)
+);
+
+CREATE INDEX webhooks_group_id_idx ON webhooks (group_id);
+
+CREATE TABLE webhook_deliveries (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    webhook_id varchar(36) NOT NULL,
+    event varchar(32) NOT NULL,
+    payload text NOT NULL,
+    status varchar(16) NOT NULL DEFAULT 'pending',
+    attempts int NOT NULL DEFAULT 0,
+    next_attempt_at timestamp NOT NULL DEFAULT now(),
+    last_status_code int,
+    last_error text,
+    created_at timestamp NOT NULL DEFAULT now(),
+    delivered_at timestamp,
+    CONSTRAINT fk_webhook FOREIGN KEY(webhook_id) REFERENCES webhooks(id)
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, created_at);
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at)
+    WHERE status = 'pending';
+
+CREATE TABLE webhook_dead_letters (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    delivery_id varchar(36) NOT NULL UNIQUE,
+    webhook_id varchar(36) NOT NULL,
+    event varchar(32) NOT NULL,
+    payload text NOT NULL,
+    attempts int NOT NULL,
+    last_error text,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_delivery FOREIGN KEY(delivery_id) REFERENCES webhook_deliveries(id),
+    CONSTRAINT fk_webhook FOREIGN KEY(webhook_id) REFERENCES webhooks(id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE messages DROP CONSTRAINT fk_integration;
+ALTER TABLE messages DROP COLUMN attachments;
+ALTER TABLE messages DROP COLUMN integration_id;
+DROP TABLE integrations;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE integrations (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    group_id varchar(36) NOT NULL,
+    name varchar NOT NULL,
+    avatar_url varchar,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    created_by varchar(36),
+    created_at timestamp NOT NULL DEFAULT now(),
+    deleted_at timestamp,
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_user FOREIGN KEY(created_by) REFERENCES users(id)
+);
+
+CREATE INDEX integrations_group_id_idx ON integrations (group_id);
+
+ALTER TABLE messages ADD COLUMN integration_id varchar(36);
+ALTER TABLE messages ADD COLUMN attachments text;
+ALTER TABLE messages ADD CONSTRAINT fk_integration
+    FOREIGN KEY(integration_id) REFERENCES integrations(id);
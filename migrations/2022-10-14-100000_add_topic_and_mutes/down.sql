@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE groups_users DROP COLUMN muted_until;
+ALTER TABLE groups DROP COLUMN topic;
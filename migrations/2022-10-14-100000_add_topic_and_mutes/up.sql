@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN topic varchar;
+ALTER TABLE groups_users ADD COLUMN muted_until timestamp;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN slow_mode_seconds INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX messages_group_id_seq_idx;
+ALTER TABLE messages DROP COLUMN seq;
+ALTER TABLE groups DROP COLUMN last_seq;
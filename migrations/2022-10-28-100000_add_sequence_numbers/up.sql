@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN last_seq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN seq BIGINT;
+CREATE UNIQUE INDEX messages_group_id_seq_idx ON messages (group_id, seq);
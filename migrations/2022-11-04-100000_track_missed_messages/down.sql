@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE groups_users DROP COLUMN last_seen_seq;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE groups_users ADD COLUMN last_seen_seq BIGINT NOT NULL DEFAULT 0;
+UPDATE groups_users SET last_seen_seq = groups.last_seq
+FROM groups WHERE groups.id = groups_users.group_id;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE mentions (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    message_id varchar(36) NOT NULL,
+    group_id varchar(36) NOT NULL,
+    user_id varchar(36) NOT NULL,
+    kind varchar(8) NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_message FOREIGN KEY(message_id) REFERENCES messages(id),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX mentions_user_id_idx ON mentions (user_id, created_at);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE notification_settings;
+ALTER TABLE groups_users DROP COLUMN notify_muted_until;
+ALTER TABLE groups_users DROP COLUMN notify_level;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+ALTER TABLE groups_users ADD COLUMN notify_level varchar(8) NOT NULL DEFAULT 'all';
+ALTER TABLE groups_users ADD COLUMN notify_muted_until timestamp;
+
+CREATE TABLE notification_settings (
+    user_id varchar(36) PRIMARY KEY NOT NULL,
+    dnd_start time,
+    dnd_end time,
+    utc_offset_minutes integer NOT NULL DEFAULT 0,
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id)
+);
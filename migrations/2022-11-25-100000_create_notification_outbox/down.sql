@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE notification_outbox;
+ALTER TABLE notification_settings DROP COLUMN digest_minutes;
+ALTER TABLE notification_settings DROP COLUMN push_token;
+ALTER TABLE notification_settings DROP COLUMN email;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+ALTER TABLE notification_settings ADD COLUMN email varchar(254);
+ALTER TABLE notification_settings ADD COLUMN push_token varchar(4096);
+ALTER TABLE notification_settings ADD COLUMN digest_minutes integer NOT NULL DEFAULT 10;
+
+CREATE TABLE notification_outbox (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    user_id varchar(36) NOT NULL,
+    group_id varchar(36) NOT NULL,
+    message_id varchar(36) NOT NULL,
+    reason varchar(8) NOT NULL,
+    title text NOT NULL,
+    body text NOT NULL,
+    status varchar(16) NOT NULL DEFAULT 'pending',
+    attempts int NOT NULL DEFAULT 0,
+    next_attempt_at timestamp NOT NULL DEFAULT now(),
+    last_error text,
+    created_at timestamp NOT NULL DEFAULT now(),
+    sent_at timestamp,
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_message FOREIGN KEY(message_id) REFERENCES messages(id)
+);
+
+CREATE INDEX notification_outbox_user_id_idx ON notification_outbox (user_id, created_at);
+CREATE INDEX notification_outbox_pending_idx ON notification_outbox (next_attempt_at)
+    WHERE status = 'pending';
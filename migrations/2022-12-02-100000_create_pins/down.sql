@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE pins;
+ALTER TABLE groups DROP COLUMN announcement_only;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN announcement_only boolean NOT NULL DEFAULT false;
+
+CREATE TABLE pins (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    group_id varchar(36) NOT NULL,
+    message_id varchar(36) NOT NULL,
+    pinned_by varchar(36),
+    pinned_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_message FOREIGN KEY(message_id) REFERENCES messages(id),
+    CONSTRAINT fk_user FOREIGN KEY(pinned_by) REFERENCES users(id),
+    UNIQUE (group_id, message_id)
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE message_purges;
+DROP INDEX messages_expires_at_idx;
+ALTER TABLE messages DROP COLUMN expires_at;
+ALTER TABLE groups DROP COLUMN retention_value;
+ALTER TABLE groups DROP COLUMN retention_mode;
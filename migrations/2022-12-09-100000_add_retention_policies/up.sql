@@ -0,0 +1,20 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN retention_mode varchar(16) NOT NULL DEFAULT 'forever';
+ALTER TABLE groups ADD COLUMN retention_value integer NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN expires_at timestamp;
+
+CREATE INDEX messages_expires_at_idx ON messages (expires_at)
+    WHERE expires_at IS NOT NULL AND body IS NOT NULL;
+
+CREATE TABLE message_purges (
+    id varchar(36) DEFAULT uuid_generate_v4() PRIMARY KEY NOT NULL,
+    group_id varchar(36) NOT NULL,
+    message_id varchar(36) NOT NULL UNIQUE,
+    reason varchar(16) NOT NULL,
+    sent_at timestamp NOT NULL,
+    purged_at timestamp NOT NULL DEFAULT now(),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES groups(id),
+    CONSTRAINT fk_message FOREIGN KEY(message_id) REFERENCES messages(id)
+);
+
+CREATE INDEX message_purges_group_id_idx ON message_purges (group_id, purged_at);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE external_identities DROP COLUMN last_login_at;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE external_identities ADD COLUMN last_login_at timestamp NOT NULL DEFAULT now();
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE notification_outbox ADD COLUMN delivered_channels varchar(64) NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE webhook_dead_letters DROP COLUMN message_id;
+ALTER TABLE webhook_deliveries DROP COLUMN message_id;
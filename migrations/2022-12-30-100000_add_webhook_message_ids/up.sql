@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE webhook_deliveries ADD COLUMN message_id varchar(36);
+ALTER TABLE webhook_dead_letters ADD COLUMN message_id varchar(36);
+
+UPDATE webhook_deliveries SET message_id = payload::json -> 'data' ->> 'id' WHERE event = 'message';
+UPDATE webhook_dead_letters SET message_id = payload::json -> 'data' ->> 'id' WHERE event = 'message';
+
+CREATE INDEX webhook_deliveries_message_id_idx ON webhook_deliveries (message_id);
+CREATE INDEX webhook_dead_letters_message_id_idx ON webhook_dead_letters (message_id);
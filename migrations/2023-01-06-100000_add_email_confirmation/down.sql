@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+UPDATE notification_settings SET email = pending_email WHERE email IS NULL;
+ALTER TABLE notification_settings DROP COLUMN email_token_expires_at;
+ALTER TABLE notification_settings DROP COLUMN email_token_hash;
+ALTER TABLE notification_settings DROP COLUMN pending_email;
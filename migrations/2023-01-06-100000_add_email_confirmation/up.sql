@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE notification_settings ADD COLUMN pending_email varchar(254);
+ALTER TABLE notification_settings ADD COLUMN email_token_hash varchar(64);
+ALTER TABLE notification_settings ADD COLUMN email_token_expires_at timestamp;
+
+-- addresses were never confirmed, they are used again once their owners confirm them
+UPDATE notification_settings SET pending_email = email, email = NULL WHERE email IS NOT NULL;
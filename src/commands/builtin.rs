@@ -0,0 +1,577 @@
+//! Commands available in every group
+use super::{Command, CommandContext, CommandError, CommandRegistry, Effect};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::models::chat_message::ChatMessage;
+use crate::models::events::{ChatEvent, Sender};
+use crate::models::group::GroupRole;
+use crate::models::member::GroupMember;
+use crate::models::profile::{ProfileUpdate, MAX_DISPLAY_NAME_LENGTH};
+use crate::models::user::User;
+use crate::models::webhook::{WebhookData, WebhookDelivery, WebhookEvent};
+use crate::schema::{groups, groups_users};
+use actix_web_actors::ws::CloseCode;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+pub const MAX_TOPIC_LENGTH: usize = 256;
+/// Longest slow mode interval, one hour
+pub const MAX_SLOW_MODE_SECONDS: i32 = 3600;
+/// Mute without explicit duration, in minutes
+const DEFAULT_MUTE_MINUTES: i64 = 10;
+/// Longest mute, one week in minutes
+const MAX_MUTE_MINUTES: i64 = 7 * 24 * 60;
+
+/// Registers all commands of this module
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(Box::new(Me));
+    registry.register(Box::new(Whisper));
+    registry.register(Box::new(Topic));
+    registry.register(Box::new(Nick));
+    registry.register(Box::new(Invite));
+    registry.register(Box::new(Kick));
+    registry.register(Box::new(Mute));
+    registry.register(Box::new(SlowMode));
+    registry.register(Box::new(Announcements));
+    registry.register(Box::new(Help));
+}
+
+/// Finds user by username, leading `@` is ignored
+fn find_user(ctx: &CommandContext, username: &str) -> Result<User, CommandError> {
+    let username = username.trim_start_matches('@');
+    match User::get_by_username(ctx.connection, username) {
+        Ok(user) => Ok(user),
+        Err(ShopError::NotFoundError(_)) => Err(CommandError::NotFound(format!(
+            "User {} does not exist",
+            username
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Finds member of the group by username, together with his role
+fn find_member(ctx: &CommandContext, username: &str) -> Result<(User, GroupRole), CommandError> {
+    let user = find_user(ctx, username)?;
+    match user.group_role(ctx.connection, ctx.group_id)? {
+        Some(role) => Ok((user, role)),
+        None => Err(CommandError::NotFound(format!(
+            "{} is not a member of this group",
+            user.username
+        ))),
+    }
+}
+
+/// Moderators can act only on members with lower role than their own
+fn check_moderation(
+    ctx: &CommandContext,
+    target: &User,
+    role: GroupRole,
+) -> Result<(), CommandError> {
+    if target.id == ctx.user.id {
+        return Err(CommandError::InvalidArguments(
+            "You cannot do that to yourself".to_string(),
+        ));
+    }
+    if role >= ctx.role {
+        return Err(CommandError::NoPermission(format!(
+            "{} has the same or higher role than you",
+            target.username
+        )));
+    }
+    Ok(())
+}
+
+fn notice(body: String) -> ChatEvent {
+    ChatEvent::Notice { body }
+}
+
+fn parse_user_id(user: &User) -> Result<Uuid, CommandError> {
+    Ok(Uuid::parse_str(&user.id).map_err(ShopError::from)?)
+}
+
+/// `/me <action>` - message describing action of sender
+struct Me;
+
+impl Command for Me {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+    fn usage(&self) -> &'static str {
+        "/me <action>"
+    }
+    fn description(&self) -> &'static str {
+        "Describe what you are doing, e.g. /me waves"
+    }
+    fn posts_message(&self) -> bool {
+        true
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() {
+            return Err(CommandError::Usage(self.usage()));
+        }
+        if let Some(until) = GroupMember::muted_until(ctx.connection, ctx.group_id, &ctx.user.id)? {
+            return Err(CommandError::Muted(until));
+        }
+        let body = format!("/me {}", args);
+        let stored = ChatMessage::create(ctx.connection, ctx.group_id, Some(&ctx.user.id), &body)?;
+        let data = WebhookData::Message {
+            id: stored.id.clone(),
+            sender: ctx.sender.clone(),
+            body,
+            sent_at: stored.created_at,
+        };
+        WebhookDelivery::emit(ctx.connection, ctx.group_id, &data);
+        Ok(vec![Effect::Broadcast(ChatEvent::Action {
+            id: stored.id,
+            sender: ctx.sender.clone(),
+            body: args.to_string(),
+            sent_at: stored.created_at,
+        })])
+    }
+}
+
+/// `/w <username> <message>` - private message to user connected to any room
+struct Whisper;
+
+impl Command for Whisper {
+    fn name(&self) -> &'static str {
+        "w"
+    }
+    fn aliases(&self) -> &'static [&'static str] {
+        &["whisper", "msg"]
+    }
+    fn usage(&self) -> &'static str {
+        "/w <username> <message>"
+    }
+    fn description(&self) -> &'static str {
+        "Send private message to connected user"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        let mut parts = args.splitn(2, char::is_whitespace);
+        let (target, body) = match (parts.next(), parts.next().map(str::trim)) {
+            (Some(target), Some(body)) if !target.is_empty() && !body.is_empty() => (target, body),
+            _ => return Err(CommandError::Usage(self.usage())),
+        };
+        // Whispers used to be addressed by user id, which is still accepted
+        let target_id = match Uuid::parse_str(target) {
+            Ok(id) => id,
+            Err(_) => parse_user_id(&find_user(ctx, target)?)?,
+        };
+        if !(ctx.is_connected)(&target_id) {
+            return Err(CommandError::NotFound(format!(
+                "{} is not connected",
+                target
+            )));
+        }
+        Ok(vec![Effect::SendTo(
+            target_id,
+            ChatEvent::Whisper {
+                sender: ctx.sender.clone(),
+                body: body.to_string(),
+            },
+        )])
+    }
+}
+
+/// `/topic [topic | -]` - shows or changes topic of group
+struct Topic;
+
+impl Command for Topic {
+    fn name(&self) -> &'static str {
+        "topic"
+    }
+    fn usage(&self) -> &'static str {
+        "/topic [new topic | -]"
+    }
+    fn description(&self) -> &'static str {
+        "Show topic of group, admins can change it or clear it with -"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() {
+            let topic = groups::table
+                .find(ctx.group_id)
+                .select(groups::topic)
+                .first::<Option<String>>(ctx.connection)
+                .map_err(ShopError::from)?;
+            let body = match topic {
+                Some(topic) => format!("Topic: {}", topic),
+                None => "No topic is set".to_string(),
+            };
+            return Ok(vec![Effect::Reply(notice(body))]);
+        }
+        if ctx.role < GroupRole::Admin {
+            return Err(CommandError::NoPermission(
+                "Only group admins can change topic".to_string(),
+            ));
+        }
+        if args.chars().count() > MAX_TOPIC_LENGTH {
+            return Err(CommandError::InvalidArguments(format!(
+                "Topic can have at most {} characters",
+                MAX_TOPIC_LENGTH
+            )));
+        }
+        let topic = if args == "-" {
+            None
+        } else {
+            Some(args.to_string())
+        };
+        diesel::update(groups::table.find(ctx.group_id))
+            .set(groups::topic.eq(&topic))
+            .execute(ctx.connection)
+            .map_err(ShopError::from)?;
+        WebhookDelivery::emit_group_update(ctx.connection, ctx.group_id);
+        Ok(vec![Effect::Broadcast(ChatEvent::Topic {
+            topic,
+            changed_by: ctx.sender.clone(),
+        })])
+    }
+}
+
+/// `/nick <display name>` - changes display name of sender
+struct Nick;
+
+impl Command for Nick {
+    fn name(&self) -> &'static str {
+        "nick"
+    }
+    fn usage(&self) -> &'static str {
+        "/nick <display name>"
+    }
+    fn description(&self) -> &'static str {
+        "Change your display name"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() {
+            return Err(CommandError::Usage(self.usage()));
+        }
+        if args.chars().count() > MAX_DISPLAY_NAME_LENGTH as usize {
+            return Err(CommandError::InvalidArguments(format!(
+                "Display name can have at most {} characters",
+                MAX_DISPLAY_NAME_LENGTH
+            )));
+        }
+        let update = ProfileUpdate {
+            display_name: Some(args.to_string()),
+            avatar_url: None,
+            bio: None,
+            status_text: None,
+            status_emoji: None,
+        };
+        let renamed: Sender = update.apply(ctx.connection, &ctx.user.id)?.into();
+        Ok(vec![Effect::Broadcast(notice(format!(
+            "{} is now known as {}",
+            ctx.sender.shown_name(),
+            renamed.shown_name()
+        )))])
+    }
+}
+
+/// `/invite <username>` - adds user to group
+struct Invite;
+
+impl Command for Invite {
+    fn name(&self) -> &'static str {
+        "invite"
+    }
+    fn usage(&self) -> &'static str {
+        "/invite <username>"
+    }
+    fn description(&self) -> &'static str {
+        "Add user to this group"
+    }
+    fn min_role(&self) -> GroupRole {
+        GroupRole::Admin
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() || args.contains(char::is_whitespace) {
+            return Err(CommandError::Usage(self.usage()));
+        }
+        let invited = find_user(ctx, args)?;
+        if invited.group_role(ctx.connection, ctx.group_id)?.is_some() {
+            return Err(CommandError::InvalidArguments(format!(
+                "{} is already a member of this group",
+                invited.username
+            )));
+        }
+        invited.join_group(ctx.connection, ctx.group_id, GroupRole::Member)?;
+        WebhookDelivery::emit_member(
+            ctx.connection,
+            ctx.group_id,
+            &invited.id,
+            WebhookEvent::Join,
+        );
+        let group_name = groups::table
+            .find(ctx.group_id)
+            .select(groups::name)
+            .first::<String>(ctx.connection)
+            .map_err(ShopError::from)?;
+        Ok(vec![
+            Effect::Broadcast(notice(format!(
+                "{} added {} to the group",
+                ctx.sender.shown_name(),
+                invited.username
+            ))),
+            Effect::SendTo(
+                parse_user_id(&invited)?,
+                notice(format!(
+                    "{} added you to group {}",
+                    ctx.sender.shown_name(),
+                    group_name
+                )),
+            ),
+        ])
+    }
+}
+
+/// `/kick <username> [reason]` - removes member from group and closes his socket
+struct Kick;
+
+impl Command for Kick {
+    fn name(&self) -> &'static str {
+        "kick"
+    }
+    fn usage(&self) -> &'static str {
+        "/kick <username> [reason]"
+    }
+    fn description(&self) -> &'static str {
+        "Remove member with lower role from this group"
+    }
+    fn min_role(&self) -> GroupRole {
+        GroupRole::Admin
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        let mut parts = args.splitn(2, char::is_whitespace);
+        let username = match parts.next() {
+            Some(username) if !username.is_empty() => username,
+            _ => return Err(CommandError::Usage(self.usage())),
+        };
+        let reason = parts.next().map(str::trim).unwrap_or_default();
+        let (kicked, role) = find_member(ctx, username)?;
+        check_moderation(ctx, &kicked, role)?;
+        WebhookDelivery::emit_member(
+            ctx.connection,
+            ctx.group_id,
+            &kicked.id,
+            WebhookEvent::Leave,
+        );
+        diesel::delete(
+            groups_users::table
+                .filter(groups_users::group_id.eq(ctx.group_id))
+                .filter(groups_users::user_id.eq(&kicked.id)),
+        )
+        .execute(ctx.connection)
+        .map_err(ShopError::from)?;
+        let mut body = format!(
+            "{} was kicked by {}",
+            kicked.username,
+            ctx.sender.shown_name()
+        );
+        if !reason.is_empty() {
+            body = format!("{}: {}", body, reason);
+        }
+        let mut effects = vec![Effect::Broadcast(notice(body.clone()))];
+        let kicked_id = parse_user_id(&kicked)?;
+        if ctx.online.contains(&kicked_id) {
+            effects.push(Effect::Disconnect(kicked_id, CloseCode::Policy, body));
+        }
+        Ok(effects)
+    }
+}
+
+/// `/mute <username> [minutes | off]` - stops member from sending messages for a while
+struct Mute;
+
+impl Command for Mute {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
+    fn usage(&self) -> &'static str {
+        "/mute <username> [minutes | off]"
+    }
+    fn description(&self) -> &'static str {
+        "Stop member with lower role from sending messages, for 10 minutes by default"
+    }
+    fn min_role(&self) -> GroupRole {
+        GroupRole::Admin
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        let (username, duration) = match parts.as_slice() {
+            [username] => (*username, None),
+            [username, duration] => (*username, Some(*duration)),
+            _ => return Err(CommandError::Usage(self.usage())),
+        };
+        let minutes = match duration {
+            None => Some(DEFAULT_MUTE_MINUTES),
+            Some("off") => None,
+            Some(minutes) => match minutes.parse::<i64>() {
+                Ok(minutes) if minutes > 0 && minutes <= MAX_MUTE_MINUTES => Some(minutes),
+                _ => {
+                    return Err(CommandError::InvalidArguments(format!(
+                        "Mute must last from 1 to {} minutes",
+                        MAX_MUTE_MINUTES
+                    )))
+                }
+            },
+        };
+        let (muted, role) = find_member(ctx, username)?;
+        check_moderation(ctx, &muted, role)?;
+        let until = minutes.map(|minutes| Utc::now().naive_utc() + Duration::minutes(minutes));
+        GroupMember::mute(ctx.connection, ctx.group_id, &muted.id, until)?;
+        let body = match minutes {
+            Some(minutes) => format!(
+                "{} was muted for {} minutes by {}",
+                muted.username,
+                minutes,
+                ctx.sender.shown_name()
+            ),
+            None => format!(
+                "{} was unmuted by {}",
+                muted.username,
+                ctx.sender.shown_name()
+            ),
+        };
+        Ok(vec![Effect::Broadcast(notice(body))])
+    }
+}
+
+/// `/slowmode [seconds | off]` - shows or changes slow mode of group
+struct SlowMode;
+
+impl Command for SlowMode {
+    fn name(&self) -> &'static str {
+        "slowmode"
+    }
+    fn usage(&self) -> &'static str {
+        "/slowmode [seconds | off]"
+    }
+    fn description(&self) -> &'static str {
+        "Show slow mode of group, admins can let members send only one message per given seconds"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() {
+            let seconds = groups::table
+                .find(ctx.group_id)
+                .select(groups::slow_mode_seconds)
+                .first::<i32>(ctx.connection)
+                .map_err(ShopError::from)?;
+            let body = match seconds {
+                0 => "Slow mode is off".to_string(),
+                seconds => format!("Slow mode is on, one message per {} seconds", seconds),
+            };
+            return Ok(vec![Effect::Reply(notice(body))]);
+        }
+        if ctx.role < GroupRole::Admin {
+            return Err(CommandError::NoPermission(
+                "Only group admins can change slow mode".to_string(),
+            ));
+        }
+        let seconds = match args {
+            "off" | "0" => 0,
+            seconds => match seconds.parse::<i32>() {
+                Ok(seconds) if seconds > 0 && seconds <= MAX_SLOW_MODE_SECONDS => seconds,
+                _ => {
+                    return Err(CommandError::InvalidArguments(format!(
+                        "Slow mode must be from 1 to {} seconds, or off",
+                        MAX_SLOW_MODE_SECONDS
+                    )))
+                }
+            },
+        };
+        diesel::update(groups::table.find(ctx.group_id))
+            .set(groups::slow_mode_seconds.eq(seconds))
+            .execute(ctx.connection)
+            .map_err(ShopError::from)?;
+        let body = match seconds {
+            0 => format!("{} turned slow mode off", ctx.sender.shown_name()),
+            seconds => format!(
+                "{} turned slow mode on, one message per {} seconds",
+                ctx.sender.shown_name(),
+                seconds
+            ),
+        };
+        Ok(vec![Effect::Broadcast(notice(body))])
+    }
+}
+
+/// `/announcements [on | off]` - shows or changes announcement only mode of group
+struct Announcements;
+
+impl Command for Announcements {
+    fn name(&self) -> &'static str {
+        "announcements"
+    }
+    fn usage(&self) -> &'static str {
+        "/announcements [on | off]"
+    }
+    fn description(&self) -> &'static str {
+        "Show announcement only mode of group, admins can turn it on so only they can post"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        if args.is_empty() {
+            let on = groups::table
+                .find(ctx.group_id)
+                .select(groups::announcement_only)
+                .first::<bool>(ctx.connection)
+                .map_err(ShopError::from)?;
+            let body = match on {
+                true => "Announcement only mode is on, only admins can post",
+                false => "Announcement only mode is off",
+            };
+            return Ok(vec![Effect::Reply(notice(body.to_string()))]);
+        }
+        if ctx.role < GroupRole::Admin {
+            return Err(CommandError::NoPermission(
+                "Only group admins can change announcement only mode".to_string(),
+            ));
+        }
+        let on = match args {
+            "on" => true,
+            "off" => false,
+            _ => return Err(CommandError::Usage(self.usage())),
+        };
+        diesel::update(groups::table.find(ctx.group_id))
+            .set(groups::announcement_only.eq(on))
+            .execute(ctx.connection)
+            .map_err(ShopError::from)?;
+        let body = match on {
+            true => format!(
+                "{} turned announcement only mode on, only admins can post",
+                ctx.sender.shown_name()
+            ),
+            false => format!(
+                "{} turned announcement only mode off",
+                ctx.sender.shown_name()
+            ),
+        };
+        Ok(vec![Effect::Broadcast(notice(body))])
+    }
+}
+
+/// `/help [command]` - lists commands available to sender
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+    fn usage(&self) -> &'static str {
+        "/help [command]"
+    }
+    fn description(&self) -> &'static str {
+        "List commands you can use, or show usage of one command"
+    }
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError> {
+        let commands = if args.is_empty() {
+            ctx.commands.help(ctx.role)
+        } else {
+            let name = args.trim_start_matches('/');
+            let command = ctx
+                .commands
+                .find(name)
+                .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+            vec![command.help()]
+        };
+        Ok(vec![Effect::Reply(ChatEvent::Help { commands })])
+    }
+}
@@ -0,0 +1,291 @@
+//! Slash commands sent over websocket instead of chat messages, e.g. `/me waves`
+//!
+//! Commands are looked up in [CommandRegistry] by name or alias and checked against role of
+//! sender in the group before they run. They do not talk to sockets directly, they return
+//! [Effect]s which [Lobby](crate::models::lobby::Lobby) applies. Errors and help are sent
+//! only to the socket which issued the command.
+//!
+//! Text starting with `//` is sent as ordinary message without the first slash. Legacy
+//! `\w <user> <message>` is still accepted as alias of `/w`.
+use crate::errors::ShopError;
+use crate::models::events::{ChatEvent, ErrorCode, Sender};
+use crate::models::group::GroupRole;
+use crate::models::user::User;
+use actix_web_actors::ws::CloseCode;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub mod builtin;
+
+/// Inbound text frame, either chat message or command
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input<'a> {
+    Text(&'a str),
+    Command { name: &'a str, args: &'a str },
+}
+
+/// Splits inbound text into command name and arguments
+pub fn parse(text: &str) -> Input<'_> {
+    if text.starts_with("//") {
+        return Input::Text(&text[1..]);
+    }
+    let command = match text.strip_prefix('/') {
+        Some(command) => command,
+        None => match text.strip_prefix("\\w") {
+            Some(args) if args.is_empty() || args.starts_with(' ') => {
+                return Input::Command {
+                    name: "w",
+                    args: args.trim(),
+                }
+            }
+            _ => return Input::Text(text),
+        },
+    };
+    let mut parts = command.splitn(2, char::is_whitespace);
+    Input::Command {
+        name: parts.next().unwrap_or_default(),
+        args: parts.next().unwrap_or_default().trim(),
+    }
+}
+
+/// What should happen after command succeeded
+#[derive(Debug)]
+pub enum Effect {
+    /// Event for everyone connected to room, including sender
+    Broadcast(ChatEvent),
+    /// Event only for socket which issued command
+    Reply(ChatEvent),
+    /// Event for socket of another user, in whichever room he is connected
+    SendTo(Uuid, ChatEvent),
+    /// Closes socket of user connected to room
+    Disconnect(Uuid, CloseCode, String),
+}
+
+/// Reason why command failed, sent back as [ChatEvent::Error]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Unknown(String),
+    /// Wrong arguments, holds usage of command
+    Usage(&'static str),
+    InvalidArguments(String),
+    NoPermission(String),
+    NotFound(String),
+    Muted(NaiveDateTime),
+    Failed(String),
+}
+
+impl From<ShopError> for CommandError {
+    fn from(e: ShopError) -> Self {
+        println!("Command failed: {}", e);
+        CommandError::Failed("Command failed, try again later".to_string())
+    }
+}
+
+impl CommandError {
+    /// Event sent to socket which issued failed command
+    pub fn into_event(self) -> ChatEvent {
+        let (code, body) = match self {
+            CommandError::Unknown(name) => (
+                ErrorCode::UnknownCommand,
+                format!("Unknown command /{}, try /help", name),
+            ),
+            CommandError::Usage(usage) => {
+                (ErrorCode::InvalidArguments, format!("Usage: {}", usage))
+            }
+            CommandError::InvalidArguments(body) => (ErrorCode::InvalidArguments, body),
+            CommandError::NoPermission(body) => (ErrorCode::NoPermission, body),
+            CommandError::NotFound(body) => (ErrorCode::NotFound, body),
+            CommandError::Muted(until) => (
+                ErrorCode::Muted,
+                format!(
+                    "You are muted until {}",
+                    until.format("%Y-%m-%d %H:%M:%S UTC")
+                ),
+            ),
+            CommandError::Failed(body) => (ErrorCode::CommandFailed, body),
+        };
+        ChatEvent::Error { code, body }
+    }
+}
+
+/// Description of command, returned by `/help`
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandHelp {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+/// Everything command may need to know about sender and room
+pub struct CommandContext<'a> {
+    pub connection: &'a PgConnection,
+    pub commands: &'a CommandRegistry,
+    pub user: &'a User,
+    pub sender: &'a Sender,
+    /// Role of sender in the group
+    pub role: GroupRole,
+    pub group_id: &'a str,
+    /// Users connected to room
+    pub online: &'a HashSet<Uuid>,
+    /// Check if user is connected to any room
+    pub is_connected: &'a dyn Fn(&Uuid) -> bool,
+}
+
+/// Trait implemented by every command
+pub trait Command {
+    /// Name used after slash, lowercase
+    fn name(&self) -> &'static str;
+    /// Other names of the same command
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn usage(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Lowest role in the group allowed to run command
+    fn min_role(&self) -> GroupRole {
+        GroupRole::Member
+    }
+    /// True for commands which post message into group, they are subject to slow mode
+    fn posts_message(&self) -> bool {
+        false
+    }
+    /// Runs command with arguments (text after name, trimmed)
+    fn run(&self, ctx: &CommandContext, args: &str) -> Result<Vec<Effect>, CommandError>;
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            name: self.name(),
+            usage: self.usage(),
+            description: self.description(),
+        }
+    }
+}
+
+/// All known commands
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        CommandRegistry::new()
+    }
+}
+
+impl CommandRegistry {
+    /// Registry with all [builtin] commands
+    pub fn new() -> Self {
+        let mut registry = CommandRegistry::empty();
+        builtin::register(&mut registry);
+        registry
+    }
+
+    pub fn empty() -> Self {
+        CommandRegistry {
+            commands: Vec::new(),
+        }
+    }
+
+    /// Adds command, replacing earlier command with the same name
+    pub fn register(&mut self, command: Box<dyn Command>) {
+        self.commands.retain(|known| known.name() != command.name());
+        self.commands.push(command);
+    }
+
+    /// Finds command by name or alias, case insensitive
+    pub fn find(&self, name: &str) -> Option<&dyn Command> {
+        let name = name.to_lowercase();
+        self.commands
+            .iter()
+            .find(|command| command.name() == name || command.aliases().contains(&name.as_str()))
+            .map(|command| command.as_ref())
+    }
+
+    /// Help of commands available to provided role, sorted by name
+    pub fn help(&self, role: GroupRole) -> Vec<CommandHelp> {
+        let mut help: Vec<CommandHelp> = self
+            .commands
+            .iter()
+            .filter(|command| command.min_role() <= role)
+            .map(|command| command.help())
+            .collect();
+        help.sort_by_key(|help| help.name);
+        help
+    }
+
+    /// Runs command after checking role of sender
+    pub fn execute(
+        &self,
+        ctx: &CommandContext,
+        name: &str,
+        args: &str,
+    ) -> Result<Vec<Effect>, CommandError> {
+        let command = self
+            .find(name)
+            .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+        if ctx.role < command.min_role() {
+            return Err(CommandError::NoPermission(format!(
+                "/{} can be used only by group {}s",
+                command.name(),
+                command.min_role().as_str()
+            )));
+        }
+        command.run(ctx, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("hello"), Input::Text("hello"));
+        assert_eq!(parse("//shrug"), Input::Text("/shrug"));
+        assert_eq!(
+            parse("/me  waves at all "),
+            Input::Command {
+                name: "me",
+                args: "waves at all"
+            }
+        );
+        assert_eq!(
+            parse("/help"),
+            Input::Command {
+                name: "help",
+                args: ""
+            }
+        );
+        assert_eq!(
+            parse("\\w bob hi"),
+            Input::Command {
+                name: "w",
+                args: "bob hi"
+            }
+        );
+        assert_eq!(parse("\\where"), Input::Text("\\where"));
+    }
+
+    #[test]
+    fn test_registry_lookup_and_help() {
+        let registry = CommandRegistry::new();
+        assert_eq!(registry.find("WHISPER").map(|c| c.name()), Some("w"));
+        assert!(registry.find("shrug").is_none());
+        let member: Vec<_> = registry
+            .help(GroupRole::Member)
+            .iter()
+            .map(|h| h.name)
+            .collect();
+        assert!(member.contains(&"me") && !member.contains(&"kick"));
+        assert!(member.contains(&"announcements"));
+        let admin: Vec<_> = registry
+            .help(GroupRole::Admin)
+            .iter()
+            .map(|h| h.name)
+            .collect();
+        assert!(admin.contains(&"kick"));
+    }
+}
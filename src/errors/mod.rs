@@ -1,6 +1,6 @@
 use actix_web::{
     http::{
-        header::{ContentType, ToStrError},
+        header::{ContentType, ToStrError, RETRY_AFTER},
         StatusCode,
     },
     Error, HttpResponse, ResponseError,
@@ -13,17 +13,22 @@ use validator::ValidationErrors;
 pub enum ShopError {
     AlreadyExistsError,
     BcryptError(String),
+    PasswordHashError(String),
     ConnectionError(String),
     DieselError(String),
     InvalidInput,
     JWTError(String),
     NoPermission(String),
+    /// Request conflicts with current state, e.g. limit of items is reached
+    Conflict(String),
     NotEnoughInStockError,
     NotFoundError(String),
     SerdeJsonError(String),
     ToStringError(String),
     ValidationErrors(String),
     ParseError(String),
+    #[display(fmt = "Too many requests, retry after {} seconds", _0)]
+    TooManyRequests(u64),
 }
 
 impl ResponseError for ShopError {
@@ -34,21 +39,27 @@ impl ResponseError for ShopError {
             ShopError::ConnectionError(_) => StatusCode::REQUEST_TIMEOUT,
             ShopError::InvalidInput => StatusCode::BAD_REQUEST,
             ShopError::NoPermission(_) => StatusCode::FORBIDDEN,
+            ShopError::Conflict(_) => StatusCode::CONFLICT,
             ShopError::SerdeJsonError(_) => StatusCode::BAD_REQUEST,
             ShopError::DieselError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ShopError::BcryptError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ShopError::PasswordHashError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ShopError::ToStringError(_) => StatusCode::BAD_REQUEST,
             ShopError::JWTError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ShopError::ValidationErrors(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ShopError::NotEnoughInStockError => StatusCode::BAD_REQUEST,
             ShopError::ParseError(_) => StatusCode::BAD_REQUEST,
+            ShopError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(self.to_string())
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        if let ShopError::TooManyRequests(retry_after) = self {
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
+        response.body(self.to_string())
     }
 }
 
@@ -74,9 +85,19 @@ impl From<r2d2::Error> for ShopError {
 
 impl From<bcrypt::BcryptError> for ShopError {
     fn from(e: bcrypt::BcryptError) -> Self {
-        match e {
-            e => ShopError::BcryptError(e.to_string()),
-        }
+        ShopError::BcryptError(e.to_string())
+    }
+}
+
+impl From<argon2::password_hash::Error> for ShopError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        ShopError::PasswordHashError(e.to_string())
+    }
+}
+
+impl From<argon2::Error> for ShopError {
+    fn from(e: argon2::Error) -> Self {
+        ShopError::PasswordHashError(e.to_string())
     }
 }
 
@@ -86,6 +107,12 @@ impl From<ToStrError> for ShopError {
     }
 }
 
+impl From<reqwest::Error> for ShopError {
+    fn from(e: reqwest::Error) -> Self {
+        ShopError::ConnectionError(e.to_string())
+    }
+}
+
 impl From<jsonwebtoken::errors::Error> for ShopError {
     fn from(e: jsonwebtoken::errors::Error) -> Self {
         ShopError::JWTError(e.to_string())
@@ -115,3 +142,15 @@ impl From<uuid::Error> for ShopError {
         ShopError::ParseError(e.to_string())
     }
 }
+
+impl From<actix::MailboxError> for ShopError {
+    fn from(e: actix::MailboxError) -> Self {
+        ShopError::ConnectionError(e.to_string())
+    }
+}
+
+impl From<actix_web::error::BlockingError> for ShopError {
+    fn from(e: actix_web::error::BlockingError) -> Self {
+        ShopError::ConnectionError(e.to_string())
+    }
+}
@@ -0,0 +1,307 @@
+//! Signing and verification keys of tokens
+//!
+//! Keys are configured from .env file:
+//! * `JWT_KEYS_DIR` - directory with PEM files, every `<kid>.pub.pem` public key is accepted
+//!   for verification, RSA keys are used with RS256 and Ed25519 keys with EdDSA
+//! * `JWT_SIGNING_KID` - kid of key used for signing, its private key is read from `<kid>.pem`
+//!
+//! Keys are rotated by adding new key pair, switching `JWT_SIGNING_KID` to it and removing
+//! public key of old one once all tokens signed with it expired.
+//! Without `JWT_KEYS_DIR`, tokens are signed with HS256 and `JWT_SECRET_KEY`, which then
+//! must not be empty.
+use crate::errors::ShopError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde::Serialize;
+use simple_asn1::ASN1Block;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const PUBLIC_KEY_SUFFIX: &str = ".pub.pem";
+const PRIVATE_KEY_SUFFIX: &str = ".pem";
+const RSA_OID: [u64; 7] = [1, 2, 840, 113_549, 1, 1, 1];
+const ED25519_OID: [u64; 4] = [1, 3, 101, 112];
+
+/// Public key in JSON Web Key format, see RFC 7517 and RFC 8037
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Jwk {
+    pub kty: &'static str,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+    pub alg: &'static str,
+    pub kid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+}
+
+/// Set of public keys served at `/.well-known/jwks.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+/// Key accepted for verification of tokens
+struct VerificationKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// Published form of the key, [None] for shared secret
+    jwk: Option<Jwk>,
+}
+
+/// Key used for signing new tokens
+struct SigningKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+/// All keys of the application, loaded once at startup
+pub struct KeyStore {
+    signing: SigningKey,
+    /// Verification keys by kid, secret key is stored under [None]
+    verification: HashMap<Option<String>, VerificationKey>,
+}
+
+fn key_error(message: String) -> ShopError {
+    ShopError::JWTError(message)
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Reads algorithm and JWK parameters from `SubjectPublicKeyInfo` structure of public key
+fn parse_public_key(kid: &str, pem: &[u8]) -> Result<(Algorithm, Jwk), ShopError> {
+    let pem = pem::parse(pem).map_err(|e| key_error(format!("Invalid PEM of {}: {}", kid, e)))?;
+    let invalid = || key_error(format!("Unsupported public key {}", kid));
+    let blocks = simple_asn1::from_der(&pem.contents).map_err(|_| invalid())?;
+    let (algorithm_id, public_key) = match blocks.first() {
+        Some(ASN1Block::Sequence(_, info)) => match info.as_slice() {
+            [ASN1Block::Sequence(_, algorithm_id), ASN1Block::BitString(_, _, key)] => {
+                (algorithm_id, key)
+            }
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    let oid = match algorithm_id.first() {
+        Some(ASN1Block::ObjectIdentifier(_, oid)) => oid.as_vec::<u64>().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    if oid == RSA_OID {
+        let rsa_key = simple_asn1::from_der(public_key).map_err(|_| invalid())?;
+        let (n, e) = match rsa_key.first() {
+            Some(ASN1Block::Sequence(_, parts)) => match parts.as_slice() {
+                [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => {
+                    (n.to_bytes_be().1, e.to_bytes_be().1)
+                }
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        return Ok((
+            Algorithm::RS256,
+            Jwk {
+                kty: "RSA",
+                key_use: "sig",
+                alg: "RS256",
+                kid: kid.to_string(),
+                n: Some(base64_url(&n)),
+                e: Some(base64_url(&e)),
+                crv: None,
+                x: None,
+            },
+        ));
+    }
+    if oid == ED25519_OID {
+        return Ok((
+            Algorithm::EdDSA,
+            Jwk {
+                kty: "OKP",
+                key_use: "sig",
+                alg: "EdDSA",
+                kid: kid.to_string(),
+                n: None,
+                e: None,
+                crv: Some("Ed25519"),
+                x: Some(base64_url(public_key)),
+            },
+        ));
+    }
+    Err(invalid())
+}
+
+fn verification_key(kid: &str, pem: &[u8]) -> Result<VerificationKey, ShopError> {
+    let (algorithm, jwk) = parse_public_key(kid, pem)?;
+    let key = match algorithm {
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(pem)?,
+        _ => DecodingKey::from_rsa_pem(pem)?,
+    };
+    Ok(VerificationKey {
+        algorithm,
+        key,
+        jwk: Some(jwk),
+    })
+}
+
+impl KeyStore {
+    /// Loads keys as described in [module documentation](self)
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError::JWTError] if no usable key is configured
+    pub fn from_env() -> Result<Self, ShopError> {
+        match dotenv::var("JWT_KEYS_DIR") {
+            Ok(dir) => {
+                let kid = dotenv::var("JWT_SIGNING_KID").map_err(|_| {
+                    key_error("JWT_SIGNING_KID must be set with JWT_KEYS_DIR".to_string())
+                })?;
+                KeyStore::from_dir(Path::new(&dir), &kid)
+            }
+            Err(_) => {
+                let secret = dotenv::var("JWT_SECRET_KEY").unwrap_or_default();
+                if secret.is_empty() {
+                    return Err(key_error(
+                        "Either JWT_KEYS_DIR or JWT_SECRET_KEY must be set".to_string(),
+                    ));
+                }
+                Ok(KeyStore::from_secret(secret.as_bytes()))
+            }
+        }
+    }
+
+    /// Keys for HS256 with shared secret, which is never published
+    pub fn from_secret(secret: &[u8]) -> Self {
+        let mut verification = HashMap::new();
+        verification.insert(
+            None,
+            VerificationKey {
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret),
+                jwk: None,
+            },
+        );
+        KeyStore {
+            signing: SigningKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                key: EncodingKey::from_secret(secret),
+            },
+            verification,
+        }
+    }
+
+    /// Loads all public keys from directory and private key of signing kid
+    pub fn from_dir(dir: &Path, signing_kid: &str) -> Result<Self, ShopError> {
+        let read_error = |e: std::io::Error| key_error(format!("Couldn't read keys: {}", e));
+        let mut verification = HashMap::new();
+        for entry in fs::read_dir(dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            let file_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            if let Some(kid) = file_name.strip_suffix(PUBLIC_KEY_SUFFIX) {
+                let pem = fs::read(&path).map_err(read_error)?;
+                verification.insert(Some(kid.to_string()), verification_key(kid, &pem)?);
+            }
+        }
+        let algorithm = verification
+            .get(&Some(signing_kid.to_string()))
+            .map(|key| key.algorithm)
+            .ok_or_else(|| {
+                key_error(format!(
+                    "Public key {}{} of signing key is missing",
+                    signing_kid, PUBLIC_KEY_SUFFIX
+                ))
+            })?;
+        let private_pem = fs::read(dir.join(format!("{}{}", signing_kid, PRIVATE_KEY_SUFFIX)))
+            .map_err(read_error)?;
+        let key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_pem)?,
+            _ => EncodingKey::from_rsa_pem(&private_pem)?,
+        };
+        Ok(KeyStore {
+            signing: SigningKey {
+                kid: Some(signing_kid.to_string()),
+                algorithm,
+                key,
+            },
+            verification,
+        })
+    }
+
+    /// Header of newly signed token
+    pub fn header(&self) -> jsonwebtoken::Header {
+        let mut header = jsonwebtoken::Header::new(self.signing.algorithm);
+        header.kid = self.signing.kid.clone();
+        header
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.signing.key
+    }
+
+    /// Key and algorithm for verifying token signed with provided kid
+    pub fn decoding_key(&self, kid: Option<String>) -> Option<(Algorithm, &DecodingKey)> {
+        self.verification
+            .get(&kid)
+            .map(|verification| (verification.algorithm, &verification.key))
+    }
+
+    /// Public keys for other services, shared secret is never included
+    pub fn jwks(&self) -> JwkSet {
+        let mut keys: Vec<Jwk> = self
+            .verification
+            .values()
+            .filter_map(|key| key.jwk.clone())
+            .collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        JwkSet { keys }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MFwwDQYJKoZIhvcNAQEBBQADSwAwSAJBAKktneFOX782XJ/xmedj17SEqxMzKYyB
+cLGX/9qy5paK6XYIfaWUgc/MzWuQ0mxUZpUrqT/fWbXn5loGnHq4qeUCAwEAAQ==
+-----END PUBLIC KEY-----
+";
+    const ED25519_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MCowBQYDK2VwAyEA35k/QJUBg1g9GQMfKzXdnJKonm93zm04q/z7PWYljvQ=
+-----END PUBLIC KEY-----
+";
+
+    #[test]
+    fn test_rsa_public_key_to_jwk() {
+        let (algorithm, jwk) = parse_public_key("rsa-1", RSA_PUBLIC_KEY.as_bytes()).unwrap();
+        assert_eq!(algorithm, Algorithm::RS256);
+        assert_eq!(jwk.kty, "RSA");
+        assert_eq!(jwk.e.as_deref(), Some("AQAB"));
+        assert_eq!(jwk.n.unwrap().len(), 86);
+    }
+
+    #[test]
+    fn test_ed25519_public_key_to_jwk() {
+        let (algorithm, jwk) = parse_public_key("ed-1", ED25519_PUBLIC_KEY.as_bytes()).unwrap();
+        assert_eq!(algorithm, Algorithm::EdDSA);
+        assert_eq!(jwk.crv, Some("Ed25519"));
+        assert_eq!(
+            jwk.x.as_deref(),
+            Some("35k_QJUBg1g9GQMfKzXdnJKonm93zm04q_z7PWYljvQ")
+        );
+    }
+
+    #[test]
+    fn test_secret_is_not_published() {
+        assert!(KeyStore::from_secret(b"secret").jwks().keys.is_empty());
+    }
+}
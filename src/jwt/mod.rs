@@ -1,21 +1,71 @@
 //!Jason web token module, relying on [jsonwebtoken] crate
+//!
+//! Tokens are signed with keys from [keys::KeyStore], loaded once at startup.
 use chrono::{Duration, Utc};
 use jsonwebtoken::*;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{errors::ShopError, models::user::User};
+
+pub mod keys;
+
+lazy_static! {
+    pub static ref KEYS: keys::KeyStore =
+        keys::KeyStore::from_env().unwrap_or_else(|e| panic!("Failed to load JWT keys: {}", e));
+}
+
+/// Loads signing keys, so that server does not start with missing or invalid key
+pub fn initialize() {
+    lazy_static::initialize(&KEYS);
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 
 /// Main structure for encoding user info into token
 pub struct UserClaims {
     pub id: String,
     pub username: String,
+    /// Token version of user at the moment of issuing, see [User::token_version]
+    #[serde(default)]
+    pub ver: i32,
     pub exp: i64,
     pub iat: i64,
 }
+
+/// Signs claims with current signing key
+fn sign<T: Serialize>(claims: &T) -> Result<String, ShopError> {
+    sign_with(&KEYS, claims)
+}
+
+fn sign_with<T: Serialize>(keys: &keys::KeyStore, claims: &T) -> Result<String, ShopError> {
+    Ok(encode(&keys.header(), claims, keys.encoding_key())?)
+}
+
+/// Verifies token with key identified by `kid` in its header, `audience` is required
+/// when provided
+fn check<T: DeserializeOwned>(token: &str, audience: Option<&str>) -> Result<T, ShopError> {
+    check_with(&KEYS, token, audience)
+}
+
+fn check_with<T: DeserializeOwned>(
+    keys: &keys::KeyStore,
+    token: &str,
+    audience: Option<&str>,
+) -> Result<T, ShopError> {
+    let header = decode_header(token)?;
+    let (algorithm, key) = keys
+        .decoding_key(header.kid)
+        .ok_or_else(|| ShopError::JWTError("Unknown signing key".to_string()))?;
+    let mut validation = Validation::new(algorithm);
+    if let Some(audience) = audience {
+        validation.set_audience(&[audience]);
+        validation.required_spec_claims.insert("aud".to_string());
+    }
+    Ok(decode::<T>(token, key, &validation)?.claims)
+}
+
 /// Function for encoding token from [User] struct.
 pub fn generate(user: &User) -> Result<String, ShopError> {
-    let secret = dotenv::var("JWT_SECRET_KEY").unwrap_or_else(|_| "".into());
     let duration = dotenv::var("JWT_LIFETIME_IN_SECONDS")
         .unwrap_or_else(|_| "300".into())
         .parse()?;
@@ -23,23 +73,85 @@ pub fn generate(user: &User) -> Result<String, ShopError> {
     let claims = UserClaims {
         id: String::from(&user.id),
         username: String::from(&user.username),
+        ver: user.token_version,
         exp: exp.timestamp(),
         iat: Utc::now().timestamp(),
     };
-    Ok(encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(&secret.as_bytes()),
-    )?)
+    sign(&claims)
 }
 ///Function for verifing token and returning user instance from token
 pub fn verify(token: String) -> Result<User, ShopError> {
-    let secret = dotenv::var("JWT_SECRET_KEY");
-    let secret = secret.unwrap_or_else(|_| "".into());
-    let token_data = decode::<UserClaims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    )?;
-    Ok(User::from_jwt(&token_data.claims))
+    let claims = check::<UserClaims>(&token, None)?;
+    Ok(User::from_jwt(&claims))
+}
+
+/// Value of [PendingClaims::pending] for tokens waiting for TOTP code
+pub const PENDING_TOTP: &str = "totp";
+/// Audience of pending tokens, so that services verifying tokens against published keys
+/// can tell them apart from login tokens and reject them
+pub const PENDING_AUDIENCE: &str = "two-factor-pending";
+
+/// Structure for encoding short-lived token, issued after password check when
+/// second factor is still required. It can only be exchanged for [UserClaims] token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingClaims {
+    pub sub: String,
+    /// Always [PENDING_AUDIENCE]
+    pub aud: String,
+    pub ver: i32,
+    pub pending: String,
+    pub exp: i64,
+    pub iat: i64,
+}
+/// Function for encoding pending second factor token for [User]
+pub fn generate_pending(user: &User) -> Result<String, ShopError> {
+    let duration = dotenv::var("TWO_FACTOR_PENDING_LIFETIME_IN_SECONDS")
+        .unwrap_or_else(|_| "300".into())
+        .parse()?;
+    let exp = Utc::now() + Duration::seconds(duration);
+    let claims = PendingClaims {
+        sub: String::from(&user.id),
+        aud: PENDING_AUDIENCE.to_string(),
+        ver: user.token_version,
+        pending: PENDING_TOTP.to_string(),
+        exp: exp.timestamp(),
+        iat: Utc::now().timestamp(),
+    };
+    sign(&claims)
+}
+///Function for verifing pending second factor token
+pub fn verify_pending(token: &str) -> Result<PendingClaims, ShopError> {
+    let claims = check::<PendingClaims>(token, Some(PENDING_AUDIENCE))?;
+    if claims.pending != PENDING_TOTP {
+        return Err(ShopError::NoPermission(
+            "No permission for that action".to_string(),
+        ));
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(aud: &str) -> PendingClaims {
+        PendingClaims {
+            sub: "user".to_string(),
+            aud: aud.to_string(),
+            ver: 0,
+            pending: PENDING_TOTP.to_string(),
+            exp: (Utc::now() + Duration::seconds(60)).timestamp(),
+            iat: Utc::now().timestamp(),
+        }
+    }
+
+    #[test]
+    fn test_pending_token_needs_its_audience() {
+        let keys = keys::KeyStore::from_secret(b"secret");
+        let token = sign_with(&keys, &pending(PENDING_AUDIENCE)).unwrap();
+        assert!(check_with::<PendingClaims>(&keys, &token, Some(PENDING_AUDIENCE)).is_ok());
+        assert!(check_with::<UserClaims>(&keys, &token, None).is_err());
+        let other = sign_with(&keys, &pending("other")).unwrap();
+        assert!(check_with::<PendingClaims>(&keys, &other, Some(PENDING_AUDIENCE)).is_err());
+    }
 }
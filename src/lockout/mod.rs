@@ -0,0 +1,416 @@
+//! Brute-force protection for login, tracking failed attempts per account and per IP
+//!
+//! Every failed attempt blocks further attempts on the account for exponentially growing
+//! delay. After too many failures account or IP is locked out for longer period and
+//! [Lockout] is returned, so it can be recorded into audit table.
+//!
+//! Attempts being verified are counted as pending from [LoginGuard::check] until their
+//! outcome is known, so burst of concurrent attempts cannot get around the lockout.
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::lockout_events;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Map is cleaned from stale entries once it grows over this size
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// What is being tracked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubjectKind {
+    Account,
+    Ip,
+}
+
+impl SubjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectKind::Account => "account",
+            SubjectKind::Ip => "ip",
+        }
+    }
+}
+
+/// Limits for failed login attempts, read from .env file
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    /// Failures of single account before it gets locked out
+    pub max_account_failures: u32,
+    /// Failures from single IP before it gets locked out
+    pub max_ip_failures: u32,
+    /// Delay after first failure, doubled with every next one
+    pub base_backoff: Duration,
+    /// Upper limit of delay between attempts
+    pub max_backoff: Duration,
+    /// Duration of lockout
+    pub lockout: Duration,
+    /// Failures older than this are forgotten
+    pub window: Duration,
+}
+
+impl LockoutPolicy {
+    /// Reads policy from `LOGIN_MAX_ACCOUNT_FAILURES` (default 5), `LOGIN_MAX_IP_FAILURES` (50),
+    /// `LOGIN_BASE_BACKOFF_IN_MILLIS` (500), `LOGIN_MAX_BACKOFF_IN_SECONDS` (30),
+    /// `LOGIN_LOCKOUT_IN_SECONDS` (900) and `LOGIN_FAILURE_WINDOW_IN_SECONDS` (900)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u64| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        LockoutPolicy {
+            max_account_failures: read("LOGIN_MAX_ACCOUNT_FAILURES", 5) as u32,
+            max_ip_failures: read("LOGIN_MAX_IP_FAILURES", 50) as u32,
+            base_backoff: Duration::from_millis(read("LOGIN_BASE_BACKOFF_IN_MILLIS", 500)),
+            max_backoff: Duration::from_secs(read("LOGIN_MAX_BACKOFF_IN_SECONDS", 30)),
+            lockout: Duration::from_secs(read("LOGIN_LOCKOUT_IN_SECONDS", 900)),
+            window: Duration::from_secs(read("LOGIN_FAILURE_WINDOW_IN_SECONDS", 900)),
+        }
+    }
+
+    fn max_failures(&self, kind: SubjectKind) -> u32 {
+        match kind {
+            SubjectKind::Account => self.max_account_failures,
+            SubjectKind::Ip => self.max_ip_failures,
+        }
+    }
+
+    /// Delay after `failures` failed attempts
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.base_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Failed attempts of single subject
+#[derive(Debug)]
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+    /// Attempts allowed by [LoginGuard::check] whose outcome is not known yet
+    pending: u32,
+}
+
+impl Attempts {
+    fn new(now: Instant) -> Self {
+        Attempts {
+            failures: 0,
+            last_failure: now,
+            blocked_until: now,
+            pending: 0,
+        }
+    }
+}
+
+/// Newly started lockout of subject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lockout {
+    pub kind: SubjectKind,
+    pub subject: String,
+    pub failures: u32,
+    pub duration: Duration,
+}
+
+impl Lockout {
+    /// Stores lockout into audit table
+    pub fn record(&self, connection: &PgConnection) -> Result<(), ShopError> {
+        let locked_until = Utc::now().naive_utc()
+            + chrono::Duration::from_std(self.duration)
+                .unwrap_or_else(|_| chrono::Duration::zero());
+        diesel::insert_into(lockout_events::table)
+            .values((
+                lockout_events::subject_kind.eq(self.kind.as_str()),
+                lockout_events::subject.eq(&self.subject),
+                lockout_events::failures.eq(self.failures as i32),
+                lockout_events::locked_until.eq(locked_until),
+            ))
+            .execute(connection)?;
+        Ok(())
+    }
+}
+
+/// Tracker of failed login attempts, shared between all workers
+pub struct LoginGuard {
+    policy: LockoutPolicy,
+    attempts: Mutex<HashMap<(SubjectKind, String), Attempts>>,
+}
+
+impl LoginGuard {
+    pub fn new(policy: LockoutPolicy) -> Self {
+        LoginGuard {
+            policy,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Accounts are tracked by normalized username, whether they exist or not
+    fn subjects(username: &str, ip: &str) -> [(SubjectKind, String); 2] {
+        [
+            (SubjectKind::Account, username.trim().to_lowercase()),
+            (SubjectKind::Ip, ip.to_string()),
+        ]
+    }
+
+    /// Failures of subject which still count, failures from before the window or before
+    /// the end of last lockout are forgotten
+    fn current_failures(&self, kind: SubjectKind, entry: &Attempts, now: Instant) -> u32 {
+        let lockout_ended =
+            entry.failures >= self.policy.max_failures(kind) && entry.blocked_until <= now;
+        if lockout_ended || now.duration_since(entry.last_failure) >= self.policy.window {
+            return 0;
+        }
+        entry.failures
+    }
+
+    /// Checks if login attempt is allowed and counts it as pending. Account can have single
+    /// pending attempt, IP as many as it has failures left before lockout.
+    /// # Returns
+    /// ## On success
+    /// * attempt whose outcome should be recorded: [Attempt]
+    /// ## On faliure
+    /// * error: [ShopError::TooManyRequests] with number of seconds to wait
+    pub fn check(&self, username: &str, ip: &str) -> Result<Attempt<'_>, ShopError> {
+        self.check_at(username, ip, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, ip: &str, now: Instant) -> Result<Attempt<'_>, ShopError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let subjects = Self::subjects(username, ip);
+        let mut wait = Duration::ZERO;
+        for key in &subjects {
+            let entry = match attempts.get(key) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            wait = wait.max(entry.blocked_until.saturating_duration_since(now));
+            let busy = match key.0 {
+                SubjectKind::Account => entry.pending > 0,
+                SubjectKind::Ip => {
+                    self.current_failures(key.0, entry, now) + entry.pending
+                        >= self.policy.max_failures(key.0)
+                }
+            };
+            if busy {
+                wait = wait.max(Duration::from_secs(1));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(ShopError::TooManyRequests(wait.as_secs().max(1)));
+        }
+        for key in subjects {
+            attempts
+                .entry(key)
+                .or_insert_with(|| Attempts::new(now))
+                .pending += 1;
+        }
+        Ok(Attempt {
+            guard: self,
+            username: username.to_string(),
+            ip: ip.to_string(),
+            finished: false,
+        })
+    }
+
+    /// Forgets pending attempt
+    fn release(&self, username: &str, ip: &str) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for key in Self::subjects(username, ip) {
+            if let Some(entry) = attempts.get_mut(&key) {
+                entry.pending = entry.pending.saturating_sub(1);
+            }
+        }
+    }
+
+    fn record_failure_at(&self, username: &str, ip: &str, now: Instant) -> Vec<Lockout> {
+        let mut attempts = self.attempts.lock().unwrap();
+        if attempts.len() > PRUNE_THRESHOLD {
+            let window = self.policy.window;
+            attempts.retain(|_, entry| {
+                entry.pending > 0
+                    || entry.blocked_until > now
+                    || now.duration_since(entry.last_failure) < window
+            });
+        }
+        let mut lockouts = Vec::new();
+        for (kind, subject) in Self::subjects(username, ip) {
+            let failures = attempts
+                .get(&(kind, subject.clone()))
+                .map(|entry| self.current_failures(kind, entry, now))
+                .unwrap_or_default();
+            let entry = attempts
+                .entry((kind, subject.clone()))
+                .or_insert_with(|| Attempts::new(now));
+            let max_failures = self.policy.max_failures(kind);
+            entry.pending = entry.pending.saturating_sub(1);
+            entry.failures = failures + 1;
+            entry.last_failure = now;
+            if entry.failures >= max_failures {
+                // attempts are rejected while locked, so every failure here starts new lockout
+                entry.blocked_until = now + self.policy.lockout;
+                lockouts.push(Lockout {
+                    kind,
+                    subject,
+                    failures: entry.failures,
+                    duration: self.policy.lockout,
+                });
+            } else if kind == SubjectKind::Account {
+                // IP can be shared by many users, so it is only locked out, without backoff
+                entry.blocked_until = now + self.policy.backoff(entry.failures);
+            }
+        }
+        lockouts
+    }
+
+    /// Forgets failed attempts of account after successful login
+    fn record_success(&self, username: &str, ip: &str) {
+        let mut attempts = self.attempts.lock().unwrap();
+        let [account, ip] = Self::subjects(username, ip);
+        attempts.remove(&account);
+        if let Some(entry) = attempts.get_mut(&ip) {
+            entry.pending = entry.pending.saturating_sub(1);
+        }
+    }
+}
+
+/// Login attempt allowed by [LoginGuard::check], it stays pending until its outcome is
+/// recorded. Attempt dropped without outcome (e.g. after database error) is released.
+pub struct Attempt<'a> {
+    guard: &'a LoginGuard,
+    username: String,
+    ip: String,
+    finished: bool,
+}
+
+impl Attempt<'_> {
+    /// Records failed attempt
+    /// # Returns
+    /// * lockouts started by this attempt: [Vec] of [Lockout]
+    pub fn failed(mut self) -> Vec<Lockout> {
+        self.finished = true;
+        self.guard
+            .record_failure_at(&self.username, &self.ip, Instant::now())
+    }
+
+    /// Forgets failed attempts of account after successful login
+    pub fn succeeded(mut self) {
+        self.finished = true;
+        self.guard.record_success(&self.username, &self.ip);
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.guard.release(&self.username, &self.ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> LoginGuard {
+        LoginGuard::new(LockoutPolicy {
+            max_account_failures: 3,
+            max_ip_failures: 5,
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(4),
+            lockout: Duration::from_secs(60),
+            window: Duration::from_secs(120),
+        })
+    }
+
+    #[test]
+    fn test_backoff_grows_until_lockout() {
+        let guard = guard();
+        let start = Instant::now();
+        assert!(guard.check_at("user", "ip", start).is_ok());
+        assert!(guard.record_failure_at("user", "ip", start).is_empty());
+        assert!(guard.check_at("user", "ip", start).is_err());
+        let later = start + Duration::from_secs(1);
+        assert!(guard.check_at("user", "ip", later).is_ok());
+        assert!(guard.record_failure_at("user", "ip", later).is_empty());
+        assert!(guard
+            .check_at("user", "ip", later + Duration::from_secs(1))
+            .is_err());
+        let later = later + Duration::from_secs(2);
+        let lockouts = guard.record_failure_at("USER", "ip", later);
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(lockouts[0].kind, SubjectKind::Account);
+        assert!(guard
+            .check_at("user", "other_ip", later + Duration::from_secs(59))
+            .is_err());
+        assert!(guard
+            .check_at("user", "other_ip", later + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_failures_reset_after_lockout() {
+        let guard = guard();
+        let mut now = Instant::now();
+        for _ in 0..3 {
+            now += Duration::from_secs(10);
+            guard.record_failure_at("user", "ip", now);
+        }
+        assert!(guard.check_at("user", "ip", now).is_err());
+        now += Duration::from_secs(60);
+        assert!(guard.check_at("user", "ip", now).is_ok());
+        assert!(guard.record_failure_at("user", "ip", now).is_empty());
+        assert!(guard
+            .check_at("user", "ip", now + Duration::from_secs(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ip_is_locked_across_accounts() {
+        let guard = guard();
+        let mut now = Instant::now();
+        let mut lockouts = Vec::new();
+        for i in 0..5 {
+            now += Duration::from_secs(10);
+            lockouts.extend(guard.record_failure_at(&format!("user{}", i), "ip", now));
+        }
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(lockouts[0].kind, SubjectKind::Ip);
+        assert!(guard.check_at("fresh_user", "ip", now).is_err());
+        assert!(guard.check_at("fresh_user", "other_ip", now).is_ok());
+    }
+
+    #[test]
+    fn test_success_resets_account() {
+        let guard = guard();
+        let now = Instant::now();
+        guard.record_failure_at("user", "ip", now);
+        let later = now + Duration::from_secs(1);
+        guard.check_at("user", "ip", later).unwrap().succeeded();
+        assert!(guard
+            .check_at("user", "other_ip", later + Duration::from_millis(10))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_attempts_are_pending() {
+        let guard = guard();
+        let now = Instant::now();
+        let first = guard.check_at("user", "ip", now).unwrap();
+        assert!(guard.check_at("USER", "ip", now).is_err());
+        // IP allows as many pending attempts as failures it has left
+        let others = (1..5)
+            .map(|i| guard.check_at(&format!("user{}", i), "ip", now).unwrap())
+            .collect::<Vec<_>>();
+        assert!(guard.check_at("user5", "ip", now).is_err());
+        drop(others);
+        assert!(guard.check_at("user5", "ip", now).is_ok());
+        assert!(first.failed().is_empty());
+        assert!(guard.check_at("user", "ip", now).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 /*!
 API for basic web-sockets realtime chat. It is multy threaded, supports multiple group chats with multiple users per chat.
 # Overview
-Crate provides basic authentification functionalities (header auth with stateless [jwt])
+Crate provides basic authentification functionalities (header auth with stateless [jwt], signed with
+rotatable RS256/EdDSA keys published at `/.well-known/jwks.json`)
 
 */
 use actix::Actor;
@@ -18,25 +19,50 @@ extern crate diesel;
 extern crate dotenv;
 #[macro_use]
 extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
 
 embed_migrations!("migrations");
 
+pub mod commands;
 pub mod errors;
 mod jwt;
+pub mod lockout;
+pub mod metrics;
+// diesel 1.4 derives implement traits inside generated functions
+#[allow(non_local_definitions)]
 pub mod models;
+pub mod notifier;
+mod oidc;
+mod password;
+pub mod ratelimit;
+pub mod retention;
 pub mod routes;
+#[allow(non_local_definitions)]
 mod schema;
+mod totp;
 pub mod utils;
+pub mod webhooks;
 
 ///Program entrance point
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let chat_server = Lobby::default().start();
+    jwt::initialize();
+    let state = utils::initialize();
+    let chat_server = Lobby::new(state.clone()).start();
+    webhooks::start(state.clone());
+    notifier::dispatcher::start(state.clone());
+    retention::start(state.clone(), chat_server.clone());
+    let rate_limiter = ratelimit::http::HttpRateLimiter::from_env();
     HttpServer::new(move || {
         App::new()
-            .app_data(Data::new(utils::initialize()))
+            .app_data(Data::new(state.clone()))
+            .wrap(rate_limiter.clone())
             .wrap(Logger::default())
+            .service(
+                web::resource("/.well-known/jwks.json").route(web::get().to(routes::jwks::handle)),
+            )
             .service(web::scope("/").configure(routes::router))
             .app_data(Data::new(chat_server.clone()))
     })
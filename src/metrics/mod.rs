@@ -0,0 +1,91 @@
+//! Runtime metrics, served in Prometheus text format by [crate::routes::metrics]
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// Counters and gauges shared by all workers
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Open websocket sessions
+    pub ws_sessions: AtomicI64,
+    /// Events waiting in outbound queues of all sessions
+    pub ws_queued_events: AtomicI64,
+    /// Bytes handed to sockets but not written to network yet
+    pub ws_in_flight_bytes: AtomicI64,
+    /// Deepest outbound queue seen since start
+    pub ws_peak_queue_depth: AtomicI64,
+    /// Ephemeral events dropped because of full queue
+    pub ws_dropped_events: AtomicU64,
+    /// Sessions closed because their client did not keep up
+    pub ws_slow_disconnects: AtomicU64,
+}
+
+impl Metrics {
+    /// Updates [Metrics::ws_peak_queue_depth] if `depth` is deeper
+    pub fn observe_queue_depth(&self, depth: usize) {
+        self.ws_peak_queue_depth
+            .fetch_max(depth as i64, Ordering::Relaxed);
+    }
+
+    /// Metrics in Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let gauges = [
+            ("ws_sessions", "Open websocket sessions", &self.ws_sessions),
+            (
+                "ws_queued_events",
+                "Events waiting in outbound queues",
+                &self.ws_queued_events,
+            ),
+            (
+                "ws_in_flight_bytes",
+                "Bytes handed to sockets but not written yet",
+                &self.ws_in_flight_bytes,
+            ),
+            (
+                "ws_peak_queue_depth",
+                "Deepest outbound queue since start",
+                &self.ws_peak_queue_depth,
+            ),
+        ];
+        let counters = [
+            (
+                "ws_dropped_events_total",
+                "Ephemeral events dropped because of full queue",
+                &self.ws_dropped_events,
+            ),
+            (
+                "ws_slow_disconnects_total",
+                "Sessions closed because client was too slow",
+                &self.ws_slow_disconnects,
+            ),
+        ];
+        let mut output = String::new();
+        for (name, help, value) in gauges {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} gauge", name);
+            let _ = writeln!(output, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        for (name, help, value) in counters {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            let _ = writeln!(output, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.ws_queued_events.fetch_add(3, Ordering::Relaxed);
+        metrics.observe_queue_depth(7);
+        metrics.observe_queue_depth(2);
+        let output = metrics.render();
+        assert!(output.contains("# TYPE ws_queued_events gauge\nws_queued_events 3\n"));
+        assert!(output.contains("ws_peak_queue_depth 7\n"));
+        assert!(output.contains("ws_dropped_events_total 0\n"));
+    }
+}
@@ -0,0 +1,154 @@
+use super::chat_message::ChatMessage;
+use super::group::{Group, GroupRole};
+use super::outbox::QueuedNotification;
+use super::profile::UserProfile;
+use super::user::User;
+use super::webhook::{WebhookDelivery, WebhookEvent};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{
+    api_keys, bots, external_identities, groups, groups_users, integrations, mentions,
+    notification_settings, password_resets, pins, profiles, recovery_codes, user_totp, users,
+    webhooks,
+};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Struct received from request for deleting own account
+#[derive(Debug, Deserialize)]
+pub struct AccountDeletion {
+    /// Not needed by users without password, see [User::confirm_identity]
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Group membership entry of personal data export
+#[derive(Debug, Serialize)]
+pub struct ExportedMembership {
+    pub group_id: String,
+    pub group_name: String,
+    pub role: GroupRole,
+    pub joined_at: NaiveDateTime,
+    pub owner: bool,
+}
+
+/// Struct for holding all personal data of user
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: NaiveDateTime,
+    pub user: UserProfile,
+    pub memberships: Vec<ExportedMembership>,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl AccountExport {
+    /// Collects profile, group memberships and messages of user
+    /// # Returns
+    /// ## On success
+    /// * all personal data of user: [AccountExport]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn build(connection: &PgConnection, user_id: &str) -> Result<Self, ShopError> {
+        let memberships = groups_users::table
+            .inner_join(groups::table)
+            .filter(groups_users::user_id.eq(user_id))
+            .order(groups_users::joined_at.asc())
+            .select((
+                groups::id,
+                groups::name,
+                groups::owner_id,
+                groups_users::role,
+                groups_users::joined_at,
+            ))
+            .load::<(String, String, String, String, NaiveDateTime)>(connection)?
+            .into_iter()
+            .map(|(group_id, group_name, owner_id, role, joined_at)| {
+                Ok(ExportedMembership {
+                    group_id,
+                    group_name,
+                    role: role.parse()?,
+                    joined_at,
+                    owner: owner_id == user_id,
+                })
+            })
+            .collect::<Result<Vec<_>, ShopError>>()?;
+        Ok(AccountExport {
+            exported_at: Utc::now().naive_utc(),
+            user: UserProfile::get(connection, user_id)?,
+            memberships,
+            messages: ChatMessage::by_sender(connection, user_id)?,
+        })
+    }
+}
+
+/// Deletes account of user and all of his personal data.
+/// * Owned groups are handed over to other members, or deleted if there are none
+/// * Messages are replaced with tombstones, so history of groups stays consistent
+/// * Owned bots are deleted the same way
+/// # Returns
+/// ## On faliure
+/// * error: [ShopError]
+pub fn delete(connection: &PgConnection, user: &User) -> Result<(), ShopError> {
+    connection.transaction(|| {
+        let owned_bots = bots::table
+            .select(bots::user_id)
+            .filter(bots::owner_id.eq(&user.id))
+            .load::<String>(connection)?;
+        for bot_id in owned_bots {
+            delete(connection, &User::get_by_id(connection, &bot_id)?)?;
+        }
+        let owned = groups::table
+            .select(groups::id)
+            .filter(groups::owner_id.eq(&user.id))
+            .load::<String>(connection)?;
+        for group_id in owned {
+            match Group::hand_over(connection, &group_id, &user.id)? {
+                Some(_) => WebhookDelivery::emit_group_update(connection, &group_id),
+                None => Group::delete(connection, &group_id)?,
+            }
+        }
+        let joined = groups_users::table
+            .select(groups_users::group_id)
+            .filter(groups_users::user_id.eq(&user.id))
+            .load::<String>(connection)?;
+        for group_id in joined {
+            WebhookDelivery::emit_member(connection, &group_id, &user.id, WebhookEvent::Leave);
+        }
+        QueuedNotification::delete_by_user(connection, &user.id)?;
+        ChatMessage::tombstone_by_sender(connection, &user.id)?;
+        diesel::delete(mentions::table.filter(mentions::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(groups_users::table.filter(groups_users::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(password_resets::table.filter(password_resets::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(profiles::table.filter(profiles::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(
+            notification_settings::table.filter(notification_settings::user_id.eq(&user.id)),
+        )
+        .execute(connection)?;
+        diesel::delete(
+            external_identities::table.filter(external_identities::user_id.eq(&user.id)),
+        )
+        .execute(connection)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(user_totp::table.filter(user_totp::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(api_keys::table.filter(api_keys::user_id.eq(&user.id)))
+            .execute(connection)?;
+        diesel::delete(bots::table.filter(bots::user_id.eq(&user.id))).execute(connection)?;
+        diesel::update(integrations::table.filter(integrations::created_by.eq(&user.id)))
+            .set(integrations::created_by.eq(None::<String>))
+            .execute(connection)?;
+        diesel::update(pins::table.filter(pins::pinned_by.eq(&user.id)))
+            .set(pins::pinned_by.eq(None::<String>))
+            .execute(connection)?;
+        diesel::update(webhooks::table.filter(webhooks::created_by.eq(&user.id)))
+            .set(webhooks::created_by.eq(None::<String>))
+            .execute(connection)?;
+        diesel::delete(users::table.filter(users::id.eq(&user.id))).execute(connection)?;
+        Ok(())
+    })
+}
@@ -0,0 +1,378 @@
+use super::profile::ProfileUpdate;
+use super::user::{User, MIN_USERNAME_LENGTH};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::password;
+use crate::schema::{api_keys, bots, users};
+use crate::utils::token;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// API keys start with this prefix, so they are easy to recognize (e.g. by secret scanners)
+pub const API_KEY_PREFIX: &str = "wck_";
+/// Characters of key stored in plain text, to tell keys apart
+const DISPLAYED_KEY_LENGTH: usize = 12;
+
+/// What API key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+    /// Read messages of joined groups, including receiving them over websocket
+    #[serde(rename = "chat:read")]
+    ChatRead,
+    /// Send messages over websocket
+    #[serde(rename = "chat:write")]
+    ChatWrite,
+    /// Join groups
+    #[serde(rename = "groups:join")]
+    GroupsJoin,
+    /// Read own and other users profiles
+    #[serde(rename = "users:read")]
+    UsersRead,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::ChatRead => "chat:read",
+            ApiScope::ChatWrite => "chat:write",
+            ApiScope::GroupsJoin => "groups:join",
+            ApiScope::UsersRead => "users:read",
+        }
+    }
+}
+
+impl std::str::FromStr for ApiScope {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chat:read" => Ok(ApiScope::ChatRead),
+            "chat:write" => Ok(ApiScope::ChatWrite),
+            "groups:join" => Ok(ApiScope::GroupsJoin),
+            "users:read" => Ok(ApiScope::UsersRead),
+            _ => Err(ShopError::ParseError(format!("Unknown scope {}", s))),
+        }
+    }
+}
+
+/// Scopes are stored space separated, as in OAuth
+fn parse_scopes(scopes: &str) -> Result<Vec<ApiScope>, ShopError> {
+    scopes
+        .split_whitespace()
+        .map(|scope| scope.parse())
+        .collect()
+}
+
+/// Struct for representing bot account, owned by human user.
+/// Bots cannot log in with password, they authenticate with [ApiKey].
+#[derive(Debug, Queryable, Serialize)]
+pub struct Bot {
+    #[serde(rename = "id")]
+    pub user_id: String,
+    pub owner_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Bot together with its username, returned from bot routes
+#[derive(Debug, Serialize)]
+pub struct BotInfo {
+    #[serde(flatten)]
+    pub bot: Bot,
+    pub username: String,
+}
+
+/// Struct received from request for creating bot
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct NewBot {
+    #[validate(length(min = "MIN_USERNAME_LENGTH"))]
+    pub username: String,
+    #[validate(length(max = 64))]
+    pub display_name: Option<String>,
+}
+
+impl Bot {
+    /// Check if user with provided id is a bot
+    pub fn is_bot(connection: &PgConnection, user_id: &str) -> Result<bool, ShopError> {
+        Ok(bots::table
+            .find(user_id)
+            .select(bots::user_id)
+            .first::<String>(connection)
+            .optional()?
+            .is_some())
+    }
+
+    /// Get bot owned by provided user
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] if bot does not exist or has another owner
+    pub fn get_owned(
+        connection: &PgConnection,
+        owner_id: &str,
+        bot_id: &str,
+    ) -> Result<Bot, ShopError> {
+        bots::table
+            .find(bot_id)
+            .filter(bots::owner_id.eq(owner_id))
+            .first::<Bot>(connection)
+            .optional()?
+            .ok_or_else(|| ShopError::NotFoundError("Bot not found".to_string()))
+    }
+
+    /// List bots owned by provided user
+    pub fn list_owned(
+        connection: &PgConnection,
+        owner_id: &str,
+    ) -> Result<Vec<BotInfo>, ShopError> {
+        Ok(bots::table
+            .inner_join(users::table)
+            .filter(bots::owner_id.eq(owner_id))
+            .order(bots::created_at)
+            .select((bots::all_columns, users::username))
+            .load::<(Bot, String)>(connection)?
+            .into_iter()
+            .map(|(bot, username)| BotInfo { bot, username })
+            .collect())
+    }
+
+    /// Creates bot user owned by provided user, limited by `BOTS_PER_USER` (default 10)
+    /// # Returns
+    /// ## On success
+    /// * created bot: [BotInfo]
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::AlreadyExistsError] if username is taken
+    pub fn create(
+        connection: &PgConnection,
+        owner: &User,
+        new_bot: NewBot,
+    ) -> Result<BotInfo, ShopError> {
+        let limit: i64 = dotenv::var("BOTS_PER_USER")
+            .unwrap_or_else(|_| "10".into())
+            .parse()?;
+        let owned: i64 = bots::table
+            .filter(bots::owner_id.eq(&owner.id))
+            .count()
+            .get_result(connection)?;
+        if owned >= limit {
+            return Err(ShopError::NoPermission(format!(
+                "Only {} bots per user are allowed",
+                limit
+            )));
+        }
+        if Bot::is_bot(connection, &owner.id)? {
+            return Err(ShopError::NoPermission(
+                "Bots cannot create bots".to_string(),
+            ));
+        }
+        if !User::is_available_username(connection, &new_bot.username) {
+            return Err(ShopError::AlreadyExistsError);
+        }
+        let info = connection.transaction::<_, ShopError, _>(|| {
+            let user = diesel::insert_into(users::table)
+                .values((
+                    users::username.eq(&new_bot.username),
+                    users::password.eq(password::NO_PASSWORD),
+                ))
+                .get_result::<User>(connection)?;
+            let bot = diesel::insert_into(bots::table)
+                .values((bots::user_id.eq(&user.id), bots::owner_id.eq(&owner.id)))
+                .get_result::<Bot>(connection)?;
+            Ok(BotInfo {
+                bot,
+                username: user.username,
+            })
+        })?;
+        if let Some(display_name) = new_bot.display_name {
+            let update = ProfileUpdate {
+                display_name: Some(display_name),
+                avatar_url: None,
+                bio: None,
+                status_text: None,
+                status_emoji: None,
+            };
+            update.apply(connection, &info.bot.user_id)?;
+        }
+        Ok(info)
+    }
+}
+
+/// Struct for representing API key of bot, only hash of the key is stored
+#[derive(Debug, Queryable, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    #[serde(rename = "bot_id")]
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    #[serde(skip_serializing)]
+    pub scopes: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+/// API key with parsed scopes, returned from routes
+#[derive(Debug, Serialize)]
+pub struct ApiKeyInfo {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    #[serde(rename = "scopes")]
+    pub scope_list: Vec<ApiScope>,
+}
+
+/// Newly created API key, plain key is returned only once
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+    pub key: String,
+}
+
+/// Struct received from request for creating API key
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct NewApiKey {
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiKey {
+    /// Check if key allows provided scope
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.split_whitespace().any(|s| s == scope.as_str())
+    }
+
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError::NoPermission] if key does not allow provided scope
+    pub fn require(&self, scope: ApiScope) -> Result<(), ShopError> {
+        if self.has_scope(scope) {
+            return Ok(());
+        }
+        Err(ShopError::NoPermission(format!(
+            "API key is missing scope {}",
+            scope.as_str()
+        )))
+    }
+
+    fn into_info(self) -> Result<ApiKeyInfo, ShopError> {
+        Ok(ApiKeyInfo {
+            scope_list: parse_scopes(&self.scopes)?,
+            key: self,
+        })
+    }
+
+    /// Finds active key and its bot, recording its use
+    /// # Returns
+    /// ## On success
+    /// * Tuple of bot user and key, (user: [User], key: [ApiKey])
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NoPermission] for unknown or revoked key
+    pub fn authenticate(connection: &PgConnection, key: &str) -> Result<(User, ApiKey), ShopError> {
+        let found = api_keys::table
+            .inner_join(users::table)
+            .filter(api_keys::key_hash.eq(token::hash(key)))
+            .filter(api_keys::revoked_at.is_null())
+            .select((users::all_columns, api_keys::all_columns))
+            .first::<(User, ApiKey)>(connection)
+            .optional()?;
+        let (user, api_key) =
+            found.ok_or_else(|| ShopError::NoPermission("Invalid API key".to_string()))?;
+        diesel::update(api_keys::table.find(&api_key.id))
+            .set(api_keys::last_used_at.eq(Utc::now().naive_utc()))
+            .execute(connection)?;
+        Ok((user, api_key))
+    }
+
+    /// Finds owner of active key, without recording its use
+    /// # Returns
+    /// ## On success
+    /// * id of bot user, [None] for unknown or revoked key: [Option]<[String]>
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn owner_id(connection: &PgConnection, key: &str) -> Result<Option<String>, ShopError> {
+        Ok(api_keys::table
+            .filter(api_keys::key_hash.eq(token::hash(key)))
+            .filter(api_keys::revoked_at.is_null())
+            .select(api_keys::user_id)
+            .first::<String>(connection)
+            .optional()?)
+    }
+
+    /// Creates new key for bot
+    /// # Returns
+    /// ## On success
+    /// * key with plain text value, which is not stored: [CreatedApiKey]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn create(
+        connection: &PgConnection,
+        bot: &Bot,
+        new_key: NewApiKey,
+    ) -> Result<CreatedApiKey, ShopError> {
+        if new_key.scopes.is_empty() {
+            return Err(ShopError::ParseError(
+                "At least one scope is required".to_string(),
+            ));
+        }
+        let key = format!("{}{}", API_KEY_PREFIX, token::generate());
+        let scopes: Vec<&str> = new_key.scopes.iter().map(|scope| scope.as_str()).collect();
+        let api_key = diesel::insert_into(api_keys::table)
+            .values((
+                api_keys::user_id.eq(&bot.user_id),
+                api_keys::name.eq(&new_key.name),
+                api_keys::prefix.eq(&key[..DISPLAYED_KEY_LENGTH]),
+                api_keys::key_hash.eq(token::hash(&key)),
+                api_keys::scopes.eq(scopes.join(" ")),
+            ))
+            .get_result::<ApiKey>(connection)?;
+        Ok(CreatedApiKey {
+            info: api_key.into_info()?,
+            key,
+        })
+    }
+
+    /// List all keys of bot, including revoked ones
+    pub fn list(connection: &PgConnection, bot: &Bot) -> Result<Vec<ApiKeyInfo>, ShopError> {
+        api_keys::table
+            .filter(api_keys::user_id.eq(&bot.user_id))
+            .order(api_keys::created_at)
+            .load::<ApiKey>(connection)?
+            .into_iter()
+            .map(ApiKey::into_info)
+            .collect()
+    }
+
+    /// Revokes key of bot, it is kept for audit
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] if bot has no such key
+    pub fn revoke(connection: &PgConnection, bot: &Bot, key_id: &str) -> Result<(), ShopError> {
+        let revoked = diesel::update(
+            api_keys::table
+                .find(key_id)
+                .filter(api_keys::user_id.eq(&bot.user_id)),
+        )
+        .set(api_keys::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(connection)?;
+        if revoked == 0 {
+            return Err(ShopError::NotFoundError("API key not found".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_round_trip() {
+        let scopes = parse_scopes("chat:read chat:write").unwrap();
+        assert_eq!(scopes, vec![ApiScope::ChatRead, ApiScope::ChatWrite]);
+        assert!(parse_scopes("chat:read admin").is_err());
+        let json = serde_json::to_string(&scopes).unwrap();
+        assert_eq!(json, r#"["chat:read","chat:write"]"#);
+    }
+}
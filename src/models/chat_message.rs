@@ -0,0 +1,144 @@
+use super::integration::{Attachment, Integration};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::messages;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+
+/// Struct for representing message stored in group chat history.
+/// Deleted messages are kept as tombstones, without body and sender.
+/// Messages posted by [Integration] have no sender, but `integration_id` instead.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ChatMessage {
+    pub id: String,
+    pub group_id: String,
+    pub sender_id: Option<String>,
+    pub body: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub integration_id: Option<String>,
+    /// Json list of [Attachment]s
+    pub attachments: Option<String>,
+    /// Sequence number of event which announced message, see [Group::next_seq](super::group::Group::next_seq)
+    pub seq: Option<i64>,
+    /// When message is purged, set for messages of groups with ephemeral retention
+    /// once they are read, see [RetentionPolicy](super::retention::RetentionPolicy)
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Struct for inserting new message into database
+#[derive(Insertable)]
+#[table_name = "messages"]
+struct NewChatMessage<'a> {
+    group_id: &'a str,
+    sender_id: Option<&'a str>,
+    body: &'a str,
+}
+
+impl ChatMessage {
+    /// Stores new message sent to group
+    /// # Returns
+    /// ## On success
+    /// * Stored message: [ChatMessage]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn create(
+        connection: &PgConnection,
+        group_id: &str,
+        sender_id: Option<&str>,
+        body: &str,
+    ) -> Result<ChatMessage, ShopError> {
+        Ok(diesel::insert_into(messages::table)
+            .values(NewChatMessage {
+                group_id,
+                sender_id,
+                body,
+            })
+            .get_result::<ChatMessage>(connection)?)
+    }
+
+    /// Stores message posted to group by integration
+    /// # Returns
+    /// ## On success
+    /// * Stored message: [ChatMessage]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn create_from_integration(
+        connection: &PgConnection,
+        integration: &Integration,
+        body: &str,
+        attachments: &[Attachment],
+    ) -> Result<ChatMessage, ShopError> {
+        let attachments = if attachments.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(attachments)?)
+        };
+        Ok(diesel::insert_into(messages::table)
+            .values((
+                messages::group_id.eq(&integration.group_id),
+                messages::integration_id.eq(&integration.id),
+                messages::body.eq(body),
+                messages::attachments.eq(attachments),
+            ))
+            .get_result::<ChatMessage>(connection)?)
+    }
+
+    /// Stores sequence number of broadcast message
+    pub fn set_seq(connection: &PgConnection, id: &str, seq: i64) -> Result<(), ShopError> {
+        diesel::update(messages::table.find(id))
+            .set(messages::seq.eq(seq))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Get at most `limit` latest messages of group with sequence number greater than
+    /// `last_seq`, ordered by sequence number
+    pub fn since_seq(
+        connection: &PgConnection,
+        group_id: &str,
+        last_seq: i64,
+        limit: i64,
+    ) -> Result<Vec<ChatMessage>, ShopError> {
+        let mut found = messages::table
+            .filter(messages::group_id.eq(group_id))
+            .filter(messages::seq.gt(last_seq))
+            .order(messages::seq.desc())
+            .limit(limit)
+            .load::<ChatMessage>(connection)?;
+        found.reverse();
+        Ok(found)
+    }
+
+    /// Get all messages sent by user, oldest first
+    pub fn by_sender(
+        connection: &PgConnection,
+        sender_id: &str,
+    ) -> Result<Vec<ChatMessage>, ShopError> {
+        Ok(messages::table
+            .filter(messages::sender_id.eq(sender_id))
+            .order(messages::created_at.asc())
+            .load::<ChatMessage>(connection)?)
+    }
+
+    /// Replaces all messages of user with tombstones
+    /// # Returns
+    /// ## On success
+    /// * number of tombstoned messages: [usize]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn tombstone_by_sender(
+        connection: &PgConnection,
+        sender_id: &str,
+    ) -> Result<usize, ShopError> {
+        Ok(
+            diesel::update(messages::table.filter(messages::sender_id.eq(sender_id)))
+                .set((
+                    messages::sender_id.eq(None::<String>),
+                    messages::body.eq(None::<String>),
+                    messages::deleted_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(connection)?,
+        )
+    }
+}
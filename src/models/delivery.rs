@@ -0,0 +1,189 @@
+//! Acknowledgements of message frames sent with client generated id
+//!
+//! Clients which retry sending after network hiccup put the same `client_id` into every
+//! attempt. Lobby remembers accepted ids of every user in [DedupeWindow], and repeated
+//! frame is answered with the same [ChatEvent::Ack] instead of being posted again.
+use super::events::ChatEvent;
+use chrono::{NaiveDateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Limits of remembered client ids, read from .env file
+#[derive(Debug, Clone, Copy)]
+pub struct DedupeLimits {
+    /// How long accepted id is remembered
+    pub window: Duration,
+    /// Most ids remembered for single user, the oldest ones are forgotten first
+    pub max_ids_per_user: usize,
+}
+
+impl DedupeLimits {
+    /// Reads limits from `WS_DEDUPE_WINDOW_IN_SECONDS` (default 300) and
+    /// `WS_DEDUPE_MAX_IDS_PER_USER` (1000)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u64| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        DedupeLimits {
+            window: Duration::from_secs(read("WS_DEDUPE_WINDOW_IN_SECONDS", 300)),
+            max_ids_per_user: read("WS_DEDUPE_MAX_IDS_PER_USER", 1000).max(1) as usize,
+        }
+    }
+}
+
+/// Outcome of accepted frame, repeated in every ack for its client id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivered {
+    /// Id of stored message, [None] for commands which do not post message
+    pub id: Option<String>,
+    pub sent_at: NaiveDateTime,
+}
+
+impl Delivered {
+    /// Outcome of command which did not post message
+    pub fn now() -> Self {
+        Delivered {
+            id: None,
+            sent_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// Outcome of frame which posted message announced by `event`
+    pub fn of(event: &ChatEvent) -> Option<Self> {
+        match event {
+            ChatEvent::Message { id, sent_at, .. } | ChatEvent::Action { id, sent_at, .. } => {
+                Some(Delivered {
+                    id: Some(id.clone()),
+                    sent_at: *sent_at,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Ack sent to socket which sent frame with `client_id`
+    pub fn ack(&self, client_id: &str) -> ChatEvent {
+        ChatEvent::Ack {
+            client_id: client_id.to_string(),
+            id: self.id.clone(),
+            sent_at: self.sent_at,
+        }
+    }
+}
+
+/// Accepted client ids of single user, in order of acceptance
+#[derive(Debug, Default)]
+struct UserIds {
+    order: VecDeque<(Instant, String)>,
+    delivered: HashMap<String, Delivered>,
+}
+
+impl UserIds {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some((accepted, _)) = self.order.front() {
+            if now.saturating_duration_since(*accepted) < window {
+                break;
+            }
+            if let Some((_, client_id)) = self.order.pop_front() {
+                self.delivered.remove(&client_id);
+            }
+        }
+    }
+}
+
+/// Client ids accepted within [DedupeLimits::window], kept by lobby
+#[derive(Debug)]
+pub struct DedupeWindow {
+    limits: DedupeLimits,
+    users: HashMap<Uuid, UserIds>,
+}
+
+impl DedupeWindow {
+    pub fn new(limits: DedupeLimits) -> Self {
+        DedupeWindow {
+            limits,
+            users: HashMap::new(),
+        }
+    }
+
+    /// Outcome of frame with the same `client_id` which user sent before
+    pub fn get(&mut self, user_id: Uuid, client_id: &str, now: Instant) -> Option<&Delivered> {
+        let ids = self.users.get_mut(&user_id)?;
+        ids.expire(now, self.limits.window);
+        ids.delivered.get(client_id)
+    }
+
+    /// Remembers outcome of accepted frame
+    pub fn insert(&mut self, user_id: Uuid, client_id: &str, delivered: Delivered, now: Instant) {
+        let ids = self.users.entry(user_id).or_default();
+        ids.expire(now, self.limits.window);
+        if ids.order.len() >= self.limits.max_ids_per_user {
+            if let Some((_, oldest)) = ids.order.pop_front() {
+                ids.delivered.remove(&oldest);
+            }
+        }
+        if ids
+            .delivered
+            .insert(client_id.to_string(), delivered)
+            .is_none()
+        {
+            ids.order.push_back((now, client_id.to_string()));
+        }
+    }
+
+    /// Forgets users whose ids all expired
+    pub fn forget_expired(&mut self, now: Instant) {
+        let window = self.limits.window;
+        self.users.retain(|_, ids| {
+            ids.expire(now, window);
+            !ids.order.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivered(id: &str) -> Delivered {
+        Delivered {
+            id: Some(id.to_string()),
+            sent_at: NaiveDateTime::from_timestamp(0, 0),
+        }
+    }
+
+    #[test]
+    fn test_window_expires_ids() {
+        let now = Instant::now();
+        let mut window = DedupeWindow::new(DedupeLimits {
+            window: Duration::from_secs(60),
+            max_ids_per_user: 10,
+        });
+        let user = Uuid::new_v4();
+        window.insert(user, "c1", delivered("m1"), now);
+        assert_eq!(window.get(user, "c1", now), Some(&delivered("m1")));
+        assert_eq!(window.get(Uuid::new_v4(), "c1", now), None);
+        assert_eq!(window.get(user, "c1", now + Duration::from_secs(61)), None);
+        window.forget_expired(now + Duration::from_secs(61));
+        assert!(window.users.is_empty());
+    }
+
+    #[test]
+    fn test_window_keeps_newest_ids() {
+        let now = Instant::now();
+        let mut window = DedupeWindow::new(DedupeLimits {
+            window: Duration::from_secs(60),
+            max_ids_per_user: 2,
+        });
+        let user = Uuid::new_v4();
+        window.insert(user, "c1", delivered("m1"), now);
+        window.insert(user, "c2", delivered("m2"), now);
+        window.insert(user, "c3", delivered("m3"), now);
+        assert_eq!(window.get(user, "c1", now), None);
+        assert_eq!(window.get(user, "c3", now), Some(&delivered("m3")));
+    }
+}
@@ -0,0 +1,252 @@
+use super::integration::{Attachment, IntegrationSender};
+use super::mention::MentionKind;
+use super::profile::UserProfile;
+use crate::commands::CommandHelp;
+use crate::ratelimit::LimitScope;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Struct for representing sender of chat message
+#[derive(Debug, Clone, Serialize)]
+pub struct Sender {
+    pub id: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl From<UserProfile> for Sender {
+    fn from(profile: UserProfile) -> Self {
+        Sender {
+            id: profile.id,
+            username: profile.username,
+            display_name: profile.display_name,
+            avatar_url: profile.avatar_url,
+        }
+    }
+}
+
+impl Sender {
+    /// Name that should be shown to other users, display name if set, otherwise username
+    pub fn shown_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
+}
+
+/// Events sent from lobby to connected clients, serialized as json with `type` tag.
+/// Events broadcast to room, which are not ephemeral, also carry `seq` number.
+/// ```
+/// {
+///     "type": "message",
+///     "seq": 42,
+///     "id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///     "sender": {
+///         "id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///         "username": "test_user",
+///         "display_name": "Test User",
+///         "avatar_url": null
+///     },
+///     "body": "Hello!",
+///     "sent_at": "2022-08-26T10:30:00.000000"
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    Notice {
+        body: String,
+    },
+    Message {
+        id: String,
+        sender: Sender,
+        body: String,
+        sent_at: NaiveDateTime,
+    },
+    Whisper {
+        sender: Sender,
+        body: String,
+    },
+    /// Message sent with `/me`, describing action of sender
+    Action {
+        id: String,
+        sender: Sender,
+        body: String,
+        sent_at: NaiveDateTime,
+    },
+    /// Topic of group was changed, [None] when it was cleared
+    Topic {
+        topic: Option<String>,
+        changed_by: Sender,
+    },
+    /// Commands available to the user, sent only to the socket which asked for them
+    Help {
+        commands: Vec<CommandHelp>,
+    },
+    /// Error sent only to the socket which caused it
+    Error {
+        code: ErrorCode,
+        body: String,
+    },
+    /// Frame was rejected by rate limit, sent only to the socket which sent it
+    RateLimited {
+        scope: LimitScope,
+        retry_after_ms: u64,
+        body: String,
+    },
+    /// Message posted by [IntegrationSender] through incoming webhook
+    Integration {
+        id: String,
+        integration: IntegrationSender,
+        body: String,
+        attachments: Vec<Attachment>,
+        sent_at: NaiveDateTime,
+    },
+    /// Sent after connecting, once missed events were replayed and live delivery starts.
+    /// `complete` is false when some events after requested `last_seq` could not be replayed.
+    Sync {
+        seq: i64,
+        replayed: usize,
+        complete: bool,
+    },
+    /// User was mentioned in message, sent to his socket in whichever room he is connected to.
+    /// Message was posted either by `sender` or by `integration`.
+    Mention {
+        kind: MentionKind,
+        group_id: String,
+        message_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sender: Option<Sender>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        integration: Option<IntegrationSender>,
+        body: String,
+        sent_at: NaiveDateTime,
+    },
+    /// Message was pinned by group admin
+    Pin {
+        message_id: String,
+        pinned_by: Sender,
+        pinned_at: NaiveDateTime,
+    },
+    /// Message was unpinned by group admin
+    Unpin {
+        message_id: String,
+        unpinned_by: Sender,
+    },
+    /// Messages were purged according to retention policy of group
+    Deleted {
+        message_ids: Vec<String>,
+    },
+    /// Message frame with `client_id` was accepted, sent only to the socket which sent it.
+    /// `id` is [None] for commands which do not post message.
+    Ack {
+        client_id: String,
+        id: Option<String>,
+        sent_at: NaiveDateTime,
+    },
+    /// Message frame with `client_id` was rejected, `reason` is event which is sent
+    /// for rejected frames without `client_id` (e.g. `error` or `rate_limited`)
+    Nack {
+        client_id: String,
+        reason: Box<ChatEvent>,
+    },
+}
+
+/// Machine readable reason of [ChatEvent::Error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    UnknownCommand,
+    InvalidArguments,
+    NoPermission,
+    NotFound,
+    CommandFailed,
+    Muted,
+    /// Message is empty or contains characters which are not allowed
+    InvalidMessage,
+}
+
+impl ChatEvent {
+    /// Events which may be dropped for slow clients, they are not stored anywhere
+    /// and losing them does not make history of room incomplete
+    pub fn is_ephemeral(&self) -> bool {
+        matches!(
+            self,
+            ChatEvent::Notice { .. } | ChatEvent::RateLimited { .. }
+        )
+    }
+
+    /// Rejection of frame, turned into [ChatEvent::Nack] when client sent its id
+    pub fn into_reply(self, client_id: Option<&str>) -> ChatEvent {
+        match client_id {
+            Some(client_id) => ChatEvent::Nack {
+                client_id: client_id.to_string(),
+                reason: Box::new(self),
+            },
+            None => self,
+        }
+    }
+
+    /// Id of stored message announced by event
+    pub fn message_id(&self) -> Option<&str> {
+        match self {
+            ChatEvent::Message { id, .. }
+            | ChatEvent::Action { id, .. }
+            | ChatEvent::Integration { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Serializes event into json text frame
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Serializes event broadcast to room together with its sequence number
+    pub fn to_json_with_seq(&self, seq: i64) -> String {
+        serde_json::to_string(&Sequenced { seq, event: self }).unwrap_or_default()
+    }
+}
+
+/// Event of room with its `seq` field, which clients send back as `last_seq` when
+/// they reconnect
+#[derive(Serialize)]
+struct Sequenced<'a> {
+    seq: i64,
+    #[serde(flatten)]
+    event: &'a ChatEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_with_seq() {
+        let event = ChatEvent::Topic {
+            topic: Some("News".to_string()),
+            changed_by: Sender {
+                id: "1".to_string(),
+                username: "test_user".to_string(),
+                display_name: None,
+                avatar_url: None,
+            },
+        };
+        let json: serde_json::Value = serde_json::from_str(&event.to_json_with_seq(7)).unwrap();
+        assert_eq!(json["type"], "topic");
+        assert_eq!(json["seq"], 7);
+        assert_eq!(json["topic"], "News");
+    }
+
+    #[test]
+    fn test_nack_wraps_reason() {
+        let error = ChatEvent::Error {
+            code: ErrorCode::Muted,
+            body: "You are muted".to_string(),
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&error.into_reply(Some("c1")).to_json()).unwrap();
+        assert_eq!(json["type"], "nack");
+        assert_eq!(json["client_id"], "c1");
+        assert_eq!(json["reason"]["code"], "muted");
+    }
+}
@@ -0,0 +1,140 @@
+use super::profile::ProfileUpdate;
+use super::user::{User, MIN_USERNAME_LENGTH};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::oidc::IdTokenClaims;
+use crate::password;
+use crate::schema::{external_identities, users};
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::Rng;
+
+/// Attempts to find free username before giving up
+const USERNAME_ATTEMPTS: usize = 5;
+
+/// Struct for representing link between [User] and subject at external provider
+#[derive(Debug, Queryable)]
+pub struct ExternalIdentity {
+    pub id: String,
+    pub user_id: String,
+    pub issuer: String,
+    pub subject: String,
+    pub created_at: NaiveDateTime,
+    /// Last login through provider, confirms sensitive actions of users without password
+    pub last_login_at: NaiveDateTime,
+}
+
+/// Username proposed from claims, `preferred_username` or local part of email
+fn proposed_username(claims: &IdTokenClaims) -> String {
+    let proposed = claims
+        .preferred_username
+        .clone()
+        .or_else(|| {
+            claims
+                .email
+                .as_ref()
+                .and_then(|email| email.split('@').next().map(String::from))
+        })
+        .unwrap_or_default();
+    let proposed: String = proposed
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .take(32)
+        .collect();
+    if proposed.len() < MIN_USERNAME_LENGTH as usize {
+        return format!("user_{}", proposed);
+    }
+    proposed
+}
+
+impl ExternalIdentity {
+    /// Finds user linked to subject of verified id token, creating user and link on first login.
+    /// New users get username from the token (with random suffix when taken) and cannot
+    /// log in with password, they confirm sensitive actions by logging in again instead,
+    /// see [ExternalIdentity::recently_authenticated].
+    /// # Returns
+    /// ## On success
+    /// * linked user: [User]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn login(
+        connection: &PgConnection,
+        issuer: &str,
+        claims: &IdTokenClaims,
+    ) -> Result<User, ShopError> {
+        let linked = external_identities::table
+            .inner_join(users::table)
+            .select(users::all_columns)
+            .filter(external_identities::issuer.eq(issuer))
+            .filter(external_identities::subject.eq(&claims.sub))
+            .first::<User>(connection)
+            .optional()?;
+        if let Some(user) = linked {
+            diesel::update(
+                external_identities::table
+                    .filter(external_identities::issuer.eq(issuer))
+                    .filter(external_identities::subject.eq(&claims.sub)),
+            )
+            .set(external_identities::last_login_at.eq(Utc::now().naive_utc()))
+            .execute(connection)?;
+            return Ok(user);
+        }
+        let user = connection.transaction::<_, ShopError, _>(|| {
+            let user = ExternalIdentity::create_user(connection, &proposed_username(claims))?;
+            diesel::insert_into(external_identities::table)
+                .values((
+                    external_identities::user_id.eq(&user.id),
+                    external_identities::issuer.eq(issuer),
+                    external_identities::subject.eq(&claims.sub),
+                ))
+                .execute(connection)?;
+            Ok(user)
+        })?;
+        if let Some(name) = &claims.name {
+            let update = ProfileUpdate {
+                display_name: Some(name.chars().take(64).collect()),
+                avatar_url: None,
+                bio: None,
+                status_text: None,
+                status_emoji: None,
+            };
+            update.apply(connection, &user.id)?;
+        }
+        Ok(user)
+    }
+
+    /// Checks if user logged in through provider within `OIDC_REAUTH_WINDOW_IN_SECONDS`
+    /// (default 300), which confirms sensitive actions of users who have no password
+    pub fn recently_authenticated(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<bool, ShopError> {
+        let window = dotenv::var("OIDC_REAUTH_WINDOW_IN_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300);
+        let since = Utc::now().naive_utc() - Duration::seconds(window);
+        let logins = external_identities::table
+            .filter(external_identities::user_id.eq(user_id))
+            .filter(external_identities::last_login_at.ge(since))
+            .count()
+            .get_result::<i64>(connection)?;
+        Ok(logins > 0)
+    }
+
+    fn create_user(connection: &PgConnection, proposed: &str) -> Result<User, ShopError> {
+        let mut username = proposed.to_string();
+        for _ in 0..USERNAME_ATTEMPTS {
+            if User::is_available_username(connection, &username) {
+                return Ok(diesel::insert_into(users::table)
+                    .values((
+                        users::username.eq(&username),
+                        users::password.eq(password::NO_PASSWORD),
+                    ))
+                    .get_result::<User>(connection)?);
+            }
+            username = format!("{}_{:04}", proposed, rand::thread_rng().gen_range(0..10000));
+        }
+        Err(ShopError::AlreadyExistsError)
+    }
+}
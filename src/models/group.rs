@@ -1,10 +1,16 @@
+use super::member::MissedMessages;
+use super::notification::GroupNotifications;
+use super::outbox::QueuedNotification;
+use super::pin::Pin;
+use super::retention::PurgeRecord;
 use super::user::User;
+use super::webhook::Webhook;
 use crate::diesel::ExpressionMethods;
 use crate::{
     errors::ShopError,
-    schema::{groups, groups_users},
+    schema::{groups, groups_users, integrations, mentions, messages},
 };
-use diesel::{PgConnection, RunQueryDsl};
+use diesel::{PgConnection, QueryDsl, RunQueryDsl};
 use serde::{Deserialize, Serialize};
 
 /// Struct for representing chat group
@@ -14,6 +20,20 @@ pub struct Group {
     #[serde(skip_serializing)]
     pub owner_id: String,
     pub name: String,
+    pub topic: Option<String>,
+    /// Minimal delay between messages of single member, 0 when slow mode is off
+    pub slow_mode_seconds: i32,
+    /// Sequence number of last event broadcast to group
+    #[serde(skip_serializing, default)]
+    pub last_seq: i64,
+    /// Only group admins can post messages when it is on
+    #[serde(default)]
+    pub announcement_only: bool,
+    /// Stored [RetentionPolicy](super::retention::RetentionPolicy), returned by its own route
+    #[serde(skip, default)]
+    pub retention_mode: String,
+    #[serde(skip, default)]
+    pub retention_value: i32,
 }
 /// Struct received from request, used for creating new group
 #[derive(Debug, Deserialize, validator::Validate)]
@@ -33,14 +53,87 @@ pub struct InsertableNewGroup {
 pub struct JoinableGroup {
     pub id: String,
 }
+/// Role of a user inside of a group, stored in `groups_users.role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl GroupRole {
+    /// Database representation of the role
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupRole::Member => "member",
+            GroupRole::Admin => "admin",
+            GroupRole::Owner => "owner",
+        }
+    }
+}
+
+impl std::str::FromStr for GroupRole {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "member" => Ok(GroupRole::Member),
+            "admin" => Ok(GroupRole::Admin),
+            "owner" => Ok(GroupRole::Owner),
+            _ => Err(ShopError::ParseError(format!("Unknown group role: {}", s))),
+        }
+    }
+}
+/// Group joined by user, together with his notification settings for it
+#[derive(Debug, Serialize)]
+pub struct JoinedGroup {
+    #[serde(flatten)]
+    pub group: Group,
+    pub notifications: GroupNotifications,
+}
 /// Struct for holding [User] and all his joined groups, if any
 #[derive(Debug, Serialize)]
 pub struct UserGroups {
     pub user: User,
-    pub groups: Option<Vec<Group>>,
+    pub groups: Option<Vec<JoinedGroup>>,
+    /// Groups with messages sent while user was not connected
+    pub missed: Option<Vec<MissedMessages>>,
 }
 impl Group {
+    /// Assigns next sequence number to event broadcast to group
+    /// # Returns
+    /// ## On success
+    /// * sequence number: [i64]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn next_seq(connection: &PgConnection, group_id: &str) -> Result<i64, ShopError> {
+        Ok(diesel::update(groups::table.find(group_id))
+            .set(groups::last_seq.eq(groups::last_seq + 1))
+            .returning(groups::last_seq)
+            .get_result::<i64>(connection)?)
+    }
+    /// Sequence number of last event broadcast to group
+    pub fn last_seq(connection: &PgConnection, group_id: &str) -> Result<i64, ShopError> {
+        Ok(groups::table
+            .find(group_id)
+            .select(groups::last_seq)
+            .first::<i64>(connection)?)
+    }
     pub fn delete(connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        Webhook::delete_by_group(connection, group_id)?;
+        QueuedNotification::delete_by_group(connection, group_id)?;
+        Pin::delete_by_group(connection, group_id)?;
+        PurgeRecord::delete_by_group(connection, group_id)?;
+        diesel::delete(mentions::table)
+            .filter(mentions::group_id.eq(group_id))
+            .execute(connection)?;
+        diesel::delete(messages::table)
+            .filter(messages::group_id.eq(group_id))
+            .execute(connection)?;
+        diesel::delete(integrations::table)
+            .filter(integrations::group_id.eq(group_id))
+            .execute(connection)?;
         diesel::delete(groups_users::table)
             .filter(groups_users::group_id.eq(group_id))
             .execute(connection)?;
@@ -49,4 +142,49 @@ impl Group {
             .execute(connection)?;
         Ok(())
     }
+    /// Hands ownership of group over to another member, preferring admins and then
+    /// members who joined earliest
+    /// # Returns
+    /// ## On success
+    /// * id of new owner: [String], or [None] if there is no other member
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn hand_over(
+        connection: &PgConnection,
+        group_id: &str,
+        leaving_user_id: &str,
+    ) -> Result<Option<String>, ShopError> {
+        let members = groups_users::table
+            .select((groups_users::user_id, groups_users::role))
+            .filter(groups_users::group_id.eq(group_id))
+            .filter(groups_users::user_id.ne(leaving_user_id))
+            .order(groups_users::joined_at.asc())
+            .load::<(String, String)>(connection)?;
+        let mut successor: Option<(String, GroupRole)> = None;
+        for (user_id, role) in members {
+            let role: GroupRole = role.parse()?;
+            let better = match &successor {
+                Some((_, best)) => role > *best,
+                None => true,
+            };
+            if better {
+                successor = Some((user_id, role));
+            }
+        }
+        let successor = match successor {
+            Some((user_id, _)) => user_id,
+            None => return Ok(None),
+        };
+        diesel::update(groups::table.filter(groups::id.eq(group_id)))
+            .set(groups::owner_id.eq(&successor))
+            .execute(connection)?;
+        diesel::update(
+            groups_users::table
+                .filter(groups_users::group_id.eq(group_id))
+                .filter(groups_users::user_id.eq(&successor)),
+        )
+        .set(groups_users::role.eq(GroupRole::Owner.as_str()))
+        .execute(connection)?;
+        Ok(Some(successor))
+    }
 }
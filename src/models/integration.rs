@@ -0,0 +1,285 @@
+use super::user::User;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::integrations;
+use crate::utils::token;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_INTEGRATION_TEXT_LENGTH: u64 = 4000;
+pub const MAX_ATTACHMENTS: u64 = 20;
+/// Limit of serialized attachments of single message, in bytes
+const MAX_ATTACHMENTS_SIZE: usize = 16 * 1024;
+
+/// Struct for representing incoming webhook of group, which lets external systems
+/// (e.g. CI) post messages under its name. Only hash of its token is stored.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct Integration {
+    pub id: String,
+    pub group_id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_by: Option<String>,
+    pub created_at: NaiveDateTime,
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// Newly created integration, token and URL containing it are returned only once
+#[derive(Debug, Serialize)]
+pub struct CreatedIntegration {
+    #[serde(flatten)]
+    pub integration: Integration,
+    pub token: String,
+    /// Path to `POST` messages to
+    pub url: String,
+}
+
+/// Struct received from request for creating integration
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct NewIntegration {
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    #[validate(url, length(max = 512))]
+    pub avatar_url: Option<String>,
+}
+
+/// Integration shown as sender of its messages
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrationSender {
+    pub id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+impl From<&Integration> for IntegrationSender {
+    fn from(integration: &Integration) -> Self {
+        IntegrationSender {
+            id: integration.id.clone(),
+            name: integration.name.clone(),
+            avatar_url: integration.avatar_url.clone(),
+        }
+    }
+}
+
+/// Field of [Attachment], shown as table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentField {
+    pub title: String,
+    pub value: String,
+    #[serde(default)]
+    pub short: bool,
+}
+
+/// Rich part of incoming message, in the same shape as Slack message attachments
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pretext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_link: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_link: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<AttachmentField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumb_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts: Option<i64>,
+}
+
+/// Struct received from external system posting into group, Slack compatible.
+/// Unknown fields (e.g. `channel` or `username`) are ignored.
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct IncomingMessage {
+    #[validate(length(max = "MAX_INTEGRATION_TEXT_LENGTH"))]
+    pub text: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = "MAX_ATTACHMENTS"))]
+    pub attachments: Vec<Attachment>,
+}
+
+impl IncomingMessage {
+    /// Text stored as message body, attachment fallback is used when text is missing
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError::ParseError] for message without any content or with too large
+    ///   attachments
+    pub fn body(&self) -> Result<String, ShopError> {
+        let size = serde_json::to_string(&self.attachments)?.len();
+        if size > MAX_ATTACHMENTS_SIZE {
+            return Err(ShopError::ParseError(
+                "Attachments are too large".to_string(),
+            ));
+        }
+        let text = self.text.as_deref().map(str::trim).unwrap_or_default();
+        if !text.is_empty() {
+            return Ok(text.to_string());
+        }
+        let fallback = self.attachments.iter().find_map(|attachment| {
+            attachment
+                .fallback
+                .as_ref()
+                .or(attachment.text.as_ref())
+                .or(attachment.title.as_ref())
+        });
+        match fallback {
+            Some(fallback) => Ok(fallback.clone()),
+            None if !self.attachments.is_empty() => Ok(String::new()),
+            None => Err(ShopError::ParseError(
+                "Message must have text or attachments".to_string(),
+            )),
+        }
+    }
+}
+
+impl Integration {
+    /// Get active integration of provided group
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] if group has no such integration
+    pub fn get(
+        connection: &PgConnection,
+        group_id: &str,
+        integration_id: &str,
+    ) -> Result<Integration, ShopError> {
+        integrations::table
+            .find(integration_id)
+            .filter(integrations::group_id.eq(group_id))
+            .filter(integrations::deleted_at.is_null())
+            .first::<Integration>(connection)
+            .optional()?
+            .ok_or_else(|| ShopError::NotFoundError("Integration not found".to_string()))
+    }
+
+    /// Finds integration by its id, even when it was deleted since
+    pub fn get_including_deleted(
+        connection: &PgConnection,
+        integration_id: &str,
+    ) -> Result<Integration, ShopError> {
+        Ok(integrations::table
+            .find(integration_id)
+            .first::<Integration>(connection)?)
+    }
+
+    /// Finds active integration by its id and token
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NoPermission] for unknown or deleted integration
+    pub fn authenticate(
+        connection: &PgConnection,
+        integration_id: &str,
+        token: &str,
+    ) -> Result<Integration, ShopError> {
+        integrations::table
+            .find(integration_id)
+            .filter(integrations::token_hash.eq(token::hash(token)))
+            .filter(integrations::deleted_at.is_null())
+            .first::<Integration>(connection)
+            .optional()?
+            .ok_or_else(|| ShopError::NoPermission("Invalid integration token".to_string()))
+    }
+
+    /// Creates integration for group, limited by `INTEGRATIONS_PER_GROUP` (default 10)
+    /// # Returns
+    /// ## On success
+    /// * integration with plain text token, which is not stored: [CreatedIntegration]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn create(
+        connection: &PgConnection,
+        group_id: &str,
+        creator: &User,
+        new_integration: NewIntegration,
+    ) -> Result<CreatedIntegration, ShopError> {
+        let limit: i64 = dotenv::var("INTEGRATIONS_PER_GROUP")
+            .unwrap_or_else(|_| "10".into())
+            .parse()?;
+        let registered: i64 = integrations::table
+            .filter(integrations::group_id.eq(group_id))
+            .filter(integrations::deleted_at.is_null())
+            .count()
+            .get_result(connection)?;
+        if registered >= limit {
+            return Err(ShopError::NoPermission(format!(
+                "Only {} integrations per group are allowed",
+                limit
+            )));
+        }
+        let token = token::generate();
+        let integration = diesel::insert_into(integrations::table)
+            .values((
+                integrations::group_id.eq(group_id),
+                integrations::name.eq(&new_integration.name),
+                integrations::avatar_url.eq(&new_integration.avatar_url),
+                integrations::token_hash.eq(token::hash(&token)),
+                integrations::created_by.eq(&creator.id),
+            ))
+            .get_result::<Integration>(connection)?;
+        Ok(CreatedIntegration {
+            url: format!("/hooks/{}/{}", integration.id, token),
+            integration,
+            token,
+        })
+    }
+
+    /// List active integrations of group
+    pub fn list(connection: &PgConnection, group_id: &str) -> Result<Vec<Integration>, ShopError> {
+        Ok(integrations::table
+            .filter(integrations::group_id.eq(group_id))
+            .filter(integrations::deleted_at.is_null())
+            .order(integrations::created_at)
+            .load::<Integration>(connection)?)
+    }
+
+    /// Deletes integration, so its token stops working. Row is kept, because
+    /// already posted messages still show its name.
+    pub fn delete(&self, connection: &PgConnection) -> Result<(), ShopError> {
+        diesel::update(integrations::table.find(&self.id))
+            .set(integrations::deleted_at.eq(Utc::now().naive_utc()))
+            .execute(connection)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_payload() {
+        let message: IncomingMessage = serde_json::from_str(
+            r##"{
+                "channel": "#builds",
+                "attachments": [{
+                    "fallback": "Build #42 failed",
+                    "color": "danger",
+                    "fields": [{"title": "Branch", "value": "main", "short": true}]
+                }]
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(message.body().unwrap(), "Build #42 failed");
+        assert!(message.attachments[0].fields[0].short);
+        let empty: IncomingMessage = serde_json::from_str(r#"{"text": " "}"#).unwrap();
+        assert!(empty.body().is_err());
+    }
+}
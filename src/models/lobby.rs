@@ -1,38 +1,670 @@
+use super::chat_message::ChatMessage;
+use super::delivery::{DedupeWindow, Delivered};
+use super::events::{ChatEvent, ErrorCode, Sender};
+use super::group::Group;
+use super::integration::IntegrationSender;
+use super::member::GroupMember;
+use super::mention::{self, MENTION_ALL_ROLE};
+use super::notification::{self, NotifyReason};
+use super::outbox::{self, NotifiedMessage};
+use super::profile::UserProfile;
+use super::replay::{self, ReplayBuffer};
+use super::retention::{self, RetentionPolicy};
 use super::user::User;
+use super::webhook::{WebhookData, WebhookDelivery};
+use super::ws::WsConn;
+use crate::commands::{self, CommandContext, CommandError, CommandRegistry, Effect, Input};
+use crate::ratelimit::{Limited, MessageLimiter};
 use crate::{
-    models::messages::{ClientActorMessage, Connect, Disconnect, WsMessage},
-    utils,
+    errors::ShopError,
+    models::messages::{
+        ClientActorMessage, CloseSession, Connect, Disconnect, RetentionChanged, RoomEvent,
+        RoomPresence, WsMessage,
+    },
+    utils::AppState,
 };
-use actix::prelude::{Actor, Context, Handler, Recipient};
+use actix::prelude::{Actor, Addr, Context, Handler, MessageResult};
+use actix_web::web;
+use actix_web_actors::ws::CloseCode;
+use chrono::NaiveDateTime;
+use diesel::Connection;
 use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-type Socket = Recipient<WsMessage>;
+type Socket = Addr<WsConn>;
 /// Struct for representing global lobby which consists of groups
 pub struct Lobby {
-    sessions: HashMap<Uuid, Socket>,     //self id to self
+    sessions: HashMap<Uuid, Socket>, //self id to self
+    /// Ids of `sessions`, shared with notifications queued in background
+    connected: Arc<RwLock<HashSet<Uuid>>>,
     rooms: HashMap<Uuid, HashSet<Uuid>>, //room id  to list of users id
+    commands: CommandRegistry,
+    limiter: MessageLimiter,
+    /// Client ids of accepted messages, so retried frames are not posted twice
+    delivered: DedupeWindow,
+    /// Latest events of rooms, for clients which reconnect. Buffers of rooms without
+    /// connected users are dropped once idle, see [Lobby::forget_idle_replay].
+    replay: HashMap<Uuid, ReplayBuffer>,
+    /// Retention policies of rooms with connected users, kept up to date by
+    /// [RetentionChanged]
+    retention: HashMap<Uuid, RetentionPolicy>,
+    state: AppState,
 }
 
-/// By default lobby is created empty
-impl Default for Lobby {
-    fn default() -> Lobby {
+impl Lobby {
+    /// Lobby is created empty, sharing database pool with the rest of the app
+    pub fn new(state: AppState) -> Lobby {
         Lobby {
             sessions: HashMap::new(),
+            connected: Arc::default(),
             rooms: HashMap::new(),
+            commands: CommandRegistry::new(),
+            limiter: MessageLimiter::new(state.static_data.message_policy.clone()),
+            delivered: DedupeWindow::new(state.static_data.dedupe_limits),
+            replay: HashMap::new(),
+            retention: HashMap::new(),
+            state,
         }
     }
-}
 
-impl Lobby {
-    /// Method for sending message to user with provided id
-    fn send_message(&self, message: &str, id_to: &Uuid) {
+    /// Method for sending event to user with provided id
+    fn send_message(&self, event: &ChatEvent, id_to: &Uuid) {
+        self.send_json(&event.to_json(), event.is_ephemeral(), id_to);
+    }
+
+    /// Method for answering rejected frame, with nack when client sent its id
+    fn reply(&self, msg: &ClientActorMessage, event: ChatEvent) {
+        self.send_message(&event.into_reply(msg.client_id.as_deref()), &msg.id);
+    }
+
+    /// Method for sending already serialized event to user with provided id
+    fn send_json(&self, text: &str, ephemeral: bool, id_to: &Uuid) {
         if let Some(socket_recipient) = self.sessions.get(id_to) {
-            let _ = socket_recipient.do_send(WsMessage(message.to_owned()));
+            socket_recipient.do_send(WsMessage {
+                text: text.to_owned(),
+                ephemeral,
+            });
         } else {
             println!("Attempting to send message but couldn't find user id.");
         }
     }
+
+    /// Method for sending event to every user in room, except the one with `except` id.
+    /// Events which are not ephemeral get sequence number and are kept for replay.
+    fn broadcast(&mut self, event: &ChatEvent, room_id: &Uuid, except: Option<&Uuid>) {
+        if let ChatEvent::Deleted { message_ids } = event {
+            // deleted and purged messages must not be replayed from memory either
+            if let Some(buffer) = self.replay.get_mut(room_id) {
+                buffer.tombstone(message_ids);
+            }
+        }
+        let ephemeral = event.is_ephemeral();
+        let message = if ephemeral {
+            event.to_json()
+        } else {
+            self.sequence(event, room_id)
+        };
+        let lifetime = match event.message_id() {
+            Some(_) => self.ephemeral_lifetime(room_id),
+            None => None,
+        };
+        if let Some(room) = self.rooms.get(room_id) {
+            room.iter()
+                .filter(|conn_id| Some(*conn_id) != except)
+                .for_each(|conn_id| self.send_json(&message, ephemeral, conn_id));
+            if let Some(lifetime) = lifetime {
+                self.expire_read(event, room, lifetime);
+            }
+        }
+    }
+
+    /// Lifetime of read messages of room with connected users when it is ephemeral, policy
+    /// is loaded from database only once
+    fn ephemeral_lifetime(&mut self, room_id: &Uuid) -> Option<chrono::Duration> {
+        if !self.rooms.contains_key(room_id) {
+            return None;
+        }
+        if let Some(policy) = self.retention.get(room_id) {
+            return policy.ephemeral_lifetime();
+        }
+        let policy = self
+            .state
+            .get_pg_connection()
+            .and_then(|connection| RetentionPolicy::get(&connection, &room_id.to_string()));
+        match policy {
+            Ok(policy) => {
+                self.retention.insert(*room_id, policy);
+                policy.ephemeral_lifetime()
+            }
+            Err(e) => {
+                println!("Couldn't load retention policy of {}: {}", room_id, e);
+                None
+            }
+        }
+    }
+
+    /// Starts expiry of message in ephemeral group once it was delivered live to someone
+    /// else than its sender
+    fn expire_read(&self, event: &ChatEvent, room: &HashSet<Uuid>, lifetime: chrono::Duration) {
+        let message_id = match event.message_id() {
+            Some(message_id) => message_id,
+            None => return,
+        };
+        let sender_id = match event {
+            ChatEvent::Message { sender, .. } | ChatEvent::Action { sender, .. } => {
+                Uuid::parse_str(&sender.id).ok()
+            }
+            _ => None,
+        };
+        if room.iter().all(|conn_id| Some(*conn_id) == sender_id) {
+            return;
+        }
+        let result = self
+            .state
+            .get_pg_connection()
+            .and_then(|connection| retention::message_read(&connection, message_id, lifetime));
+        if let Err(e) = result {
+            println!("Couldn't start expiry of message {}: {}", message_id, e);
+        }
+    }
+
+    /// Assigns next sequence number of room to event and remembers it for replay,
+    /// when database cannot be reached event is sent without it
+    fn sequence(&mut self, event: &ChatEvent, room_id: &Uuid) -> String {
+        let group_id = room_id.to_string();
+        let seq = self.state.get_pg_connection().and_then(|connection| {
+            connection.transaction(|| {
+                let seq = Group::next_seq(&connection, &group_id)?;
+                if let Some(id) = event.message_id() {
+                    ChatMessage::set_seq(&connection, id, seq)?;
+                }
+                Ok(seq)
+            })
+        });
+        match seq {
+            Ok(seq) => {
+                let message = event.to_json_with_seq(seq);
+                let now = Instant::now();
+                if !self.replay.contains_key(room_id) {
+                    self.forget_idle_replay(now);
+                }
+                let buffer_size = self.state.static_data.replay_limits.buffer_size;
+                self.replay
+                    .entry(*room_id)
+                    .or_insert_with(|| ReplayBuffer::new(buffer_size, now))
+                    .push(seq, event.message_id(), message.clone(), now);
+                message
+            }
+            Err(e) => {
+                println!("Couldn't assign sequence number in {}: {}", room_id, e);
+                event.to_json()
+            }
+        }
+    }
+
+    /// Drops replay buffers of rooms nobody is connected to, which got no events for
+    /// [ReplayLimits::idle_timeout](replay::ReplayLimits::idle_timeout). Clients
+    /// reconnecting later are replayed stored messages instead.
+    fn forget_idle_replay(&mut self, now: Instant) {
+        let timeout = self.state.static_data.replay_limits.idle_timeout;
+        let rooms = &self.rooms;
+        self.replay
+            .retain(|room_id, buffer| rooms.contains_key(room_id) || !buffer.is_idle(timeout, now));
+    }
+
+    /// Replays events missed by reconnected user, or messages sent while he was offline
+    /// when he did not send `last_seq`, and tells where live delivery starts. Events
+    /// skipped because of replay limits are not marked as seen while he is connected.
+    fn resume(&self, room_id: &Uuid, user_id: &Uuid, last_seq: Option<i64>) {
+        let group_id = room_id.to_string();
+        let member_id = user_id.to_string();
+        let limits = &self.state.static_data.replay_limits;
+        let result = self.state.get_pg_connection().and_then(|connection| {
+            let current = Group::last_seq(&connection, &group_id)?;
+            let (last_seq, max) = match last_seq {
+                Some(last_seq) => (last_seq, limits.max_replayed),
+                None => (
+                    GroupMember::last_seen_seq(&connection, &group_id, &member_id)?,
+                    limits.max_offline_replayed,
+                ),
+            };
+            let replay = replay::missed_events(
+                &connection,
+                self.replay.get(room_id),
+                &group_id,
+                last_seq,
+                current,
+                max,
+            )?;
+            GroupMember::mark_seen(&connection, &group_id, &member_id, replay.seen_until)?;
+            // latest events were replayed after the skipped ones
+            retention::messages_read(
+                &connection,
+                &group_id,
+                &member_id,
+                replay.skipped_until,
+                current,
+            )?;
+            Ok((current, replay))
+        });
+        let (seq, replay) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Couldn't replay events of {}: {}", room_id, e);
+                return;
+            }
+        };
+        for message in &replay.events {
+            self.send_json(message, false, user_id);
+        }
+        let sync = ChatEvent::Sync {
+            seq,
+            replayed: replay.events.len(),
+            complete: replay.complete,
+        };
+        self.send_message(&sync, user_id);
+    }
+
+    /// Marks everything broadcast to room so far as received by user who leaves it, he got
+    /// every event since [ChatEvent::Sync] live. Events skipped by replay on connect count as
+    /// seen from now on, so that next connect replays newer ones instead of the same again,
+    /// client learned about them from incomplete [ChatEvent::Sync].
+    fn mark_seen(&self, room_id: &Uuid, user_id: &Uuid) {
+        let group_id = room_id.to_string();
+        let result = self.state.get_pg_connection().and_then(|connection| {
+            let current = Group::last_seq(&connection, &group_id)?;
+            GroupMember::mark_seen(&connection, &group_id, &user_id.to_string(), current)
+        });
+        if let Err(e) = result {
+            println!("Couldn't mark events of {} as seen: {}", room_id, e);
+        }
+    }
+
+    /// Method for loading sender info (username and profile) of user with provided id
+    fn sender(&self, id: &Uuid) -> Option<Sender> {
+        let result = self
+            .state
+            .get_pg_connection()
+            .and_then(|connection| UserProfile::get(&connection, &id.to_string()));
+        match result {
+            Ok(profile) => Some(profile.into()),
+            Err(e) => {
+                println!("Couldn't load sender {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// Runs slash command sent by user connected to room
+    /// # Returns
+    /// * outcome for ack: [Delivered], or [None] when command failed
+    fn run_command(
+        &mut self,
+        msg: &ClientActorMessage,
+        sender: &Sender,
+        name: &str,
+        args: &str,
+    ) -> Option<Delivered> {
+        let group_id = msg.room_id.to_string();
+        let empty = HashSet::new();
+        let online = self.rooms.get(&msg.room_id).unwrap_or(&empty);
+        let is_connected = |id: &Uuid| self.sessions.contains_key(id);
+        let result = self
+            .state
+            .get_pg_connection()
+            .map_err(CommandError::from)
+            .and_then(|connection| {
+                let user = User::get_by_id(&connection, &sender.id)?;
+                let role = user.group_role(&connection, &group_id)?.ok_or_else(|| {
+                    CommandError::NoPermission("You are not a member of this group".to_string())
+                })?;
+                let ctx = CommandContext {
+                    connection: &connection,
+                    commands: &self.commands,
+                    user: &user,
+                    sender,
+                    role,
+                    group_id: &group_id,
+                    online,
+                    is_connected: &is_connected,
+                };
+                self.commands.execute(&ctx, name, args)
+            });
+        let effects = match result {
+            Ok(effects) => effects,
+            Err(e) => {
+                self.reply(msg, e.into_event());
+                return None;
+            }
+        };
+        let delivered = effects
+            .iter()
+            .find_map(|effect| match effect {
+                Effect::Broadcast(event) => Delivered::of(event),
+                _ => None,
+            })
+            .unwrap_or_else(Delivered::now);
+        for effect in effects {
+            self.apply(effect, msg);
+        }
+        Some(delivered)
+    }
+
+    /// Applies effect of command sent by `msg`
+    fn apply(&mut self, effect: Effect, msg: &ClientActorMessage) {
+        match effect {
+            Effect::Broadcast(event) => {
+                self.broadcast(&event, &msg.room_id, None);
+                self.notify(&msg.room_id, &event);
+            }
+            Effect::Reply(event) => self.send_message(&event, &msg.id),
+            Effect::SendTo(id, event) => {
+                if self.sessions.contains_key(&id) {
+                    self.send_message(&event, &id);
+                }
+            }
+            Effect::Disconnect(id, code, reason) => {
+                let in_room =
+                    matches!(self.rooms.get(&msg.room_id), Some(room) if room.contains(&id));
+                if let (true, Some(socket)) = (in_room, self.sessions.get(&id)) {
+                    socket.do_send(CloseSession { code, reason });
+                }
+            }
+        }
+    }
+
+    /// Notifies members about message posted to room, connected ones get mention events,
+    /// the rest is notified later by [crate::notifier::dispatcher]
+    fn notify(&self, room_id: &Uuid, event: &ChatEvent) {
+        let posted = match Posted::of(event) {
+            Some(posted) => posted,
+            None => return,
+        };
+        let mentioned = self.mention(room_id, &posted);
+        let group_id = room_id.to_string();
+        let message_id = posted.id.to_string();
+        let sender_id = posted.sender.map(|sender| sender.id.clone());
+        let sender_username = posted.author_name().to_string();
+        let body = posted.body.to_string();
+        let db = self.state.static_data.db.clone();
+        let notifier = self.state.static_data.notifier.clone();
+        let connected = self.connected.clone();
+        let id = posted.id.to_string();
+        // every member of group is looked up, so it is done outside of lobby
+        actix_web::rt::spawn(async move {
+            let result = web::block(move || {
+                let connection = db.get()?;
+                let message = NotifiedMessage {
+                    group_id: &group_id,
+                    message_id: &message_id,
+                    sender_id: sender_id.as_deref(),
+                    sender_username: &sender_username,
+                    body: &body,
+                };
+                outbox::enqueue(
+                    &connection,
+                    &message,
+                    &mentioned,
+                    notifier.as_ref(),
+                    |user_id| {
+                        let connected = connected.read().unwrap();
+                        matches!(Uuid::parse_str(user_id), Ok(id) if connected.contains(&id))
+                    },
+                )
+            })
+            .await
+            .map_err(ShopError::from)
+            .and_then(|result| result);
+            if let Err(e) = result {
+                println!("Couldn't queue notifications about {}: {}", id, e);
+            }
+        });
+    }
+
+    /// Stores mentions of members in message posted to room and notifies mentioned users
+    /// who are connected to any room, unless their notification settings say otherwise.
+    /// Integrations are set up by group admins, so they may mention `@all`.
+    /// # Returns
+    /// * ids of every mentioned member: [Vec]
+    fn mention(&self, room_id: &Uuid, posted: &Posted) -> Vec<String> {
+        let found = mention::parse(posted.body);
+        if found.is_empty() {
+            return Vec::new();
+        }
+        let group_id = room_id.to_string();
+        let sender_id = posted.sender.map(|sender| sender.id.as_str());
+        let empty = HashSet::new();
+        let online = self.rooms.get(room_id).unwrap_or(&empty);
+        let result = self.state.get_pg_connection().and_then(|connection| {
+            let can_mention_all = match sender_id {
+                Some(sender_id) => {
+                    let role = User::get_by_id(&connection, sender_id)?
+                        .group_role(&connection, &group_id)?;
+                    matches!(role, Some(role) if role >= MENTION_ALL_ROLE)
+                }
+                None => true,
+            };
+            let mentioned = mention::create(
+                &connection,
+                &group_id,
+                posted.id,
+                sender_id,
+                &found,
+                online,
+                can_mention_all,
+            )?;
+            let mut notified = Vec::new();
+            for (user_id, kind) in &mentioned {
+                let user_id = match Uuid::parse_str(user_id) {
+                    Ok(user_id) if self.sessions.contains_key(&user_id) => user_id,
+                    _ => continue,
+                };
+                let member_id = user_id.to_string();
+                if notification::should_notify(
+                    &connection,
+                    &group_id,
+                    &member_id,
+                    NotifyReason::Mention,
+                )? {
+                    notified.push((user_id, *kind));
+                }
+            }
+            let mentioned = mentioned.into_iter().map(|(user_id, _)| user_id).collect();
+            Ok((can_mention_all, notified, mentioned))
+        });
+        let (can_mention_all, notified, mentioned) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Couldn't store mentions in {}: {}", posted.id, e);
+                return Vec::new();
+            }
+        };
+        if let (true, false, Some(sender_id)) = (found.all, can_mention_all, sender_id) {
+            if let Ok(sender_id) = Uuid::parse_str(sender_id) {
+                let notice = ChatEvent::Notice {
+                    body: "Only group admins can mention @all".to_string(),
+                };
+                self.send_message(&notice, &sender_id);
+            }
+        }
+        for (user_id, kind) in notified {
+            let event = ChatEvent::Mention {
+                kind,
+                group_id: group_id.clone(),
+                message_id: posted.id.to_string(),
+                sender: posted.sender.cloned(),
+                integration: posted.integration.cloned(),
+                body: posted.body.to_string(),
+                sent_at: posted.sent_at,
+            };
+            self.send_message(&event, &user_id);
+        }
+        mentioned
+    }
+
+    /// Checks announcement only mode of room, see [read_only_check]
+    fn check_read_only(&self, msg: &ClientActorMessage) -> Result<(), CommandError> {
+        let read_only = self.state.get_pg_connection().and_then(|connection| {
+            GroupMember::read_only(&connection, &msg.room_id.to_string(), &msg.id.to_string())
+        });
+        if let Err(e) = &read_only {
+            println!(
+                "Couldn't check announcement only mode of {}: {}",
+                msg.room_id, e
+            );
+        }
+        read_only_check(read_only)
+    }
+
+    /// Interval of slow mode of room. Like announcement only mode, message is rejected
+    /// when it could not be checked.
+    fn slow_mode(&self, msg: &ClientActorMessage) -> Result<Option<Duration>, CommandError> {
+        let interval = self.state.get_pg_connection().and_then(|connection| {
+            GroupMember::slow_mode(&connection, &msg.room_id.to_string(), &msg.id.to_string())
+        });
+        interval.map_err(|e| {
+            println!("Couldn't check slow mode of {}: {}", msg.room_id, e);
+            CommandError::Failed("Message was not sent, try again later".to_string())
+        })
+    }
+
+    /// Answers rejected message with error and closes socket of repeat offender
+    fn reject(&mut self, msg: &ClientActorMessage, limited: Limited, now: Instant) {
+        self.reply(msg, limited.into_event());
+        if self.limiter.strike(msg.id, now) {
+            if let Some(socket) = self.sessions.get(&msg.id) {
+                socket.do_send(CloseSession {
+                    code: CloseCode::Policy,
+                    reason: "Too many messages".to_string(),
+                });
+            }
+        }
+    }
+
+    /// Stores message and sends it to everyone in room, unless sender is muted
+    /// # Returns
+    /// * outcome for ack: [Delivered], or [None] when message was not sent
+    fn send_text(
+        &mut self,
+        msg: &ClientActorMessage,
+        sender: Sender,
+        body: &str,
+    ) -> Option<Delivered> {
+        let group_id = msg.room_id.to_string();
+        let stored = self.state.get_pg_connection().and_then(|connection| {
+            if let Some(until) = GroupMember::muted_until(&connection, &group_id, &sender.id)? {
+                return Ok(Err(until));
+            }
+            let stored = ChatMessage::create(&connection, &group_id, Some(&sender.id), body)?;
+            let data = WebhookData::Message {
+                id: stored.id.clone(),
+                sender: sender.clone(),
+                body: body.to_string(),
+                sent_at: stored.created_at,
+            };
+            WebhookDelivery::emit(&connection, &group_id, &data);
+            Ok(Ok(stored))
+        });
+        let stored = match stored {
+            Ok(Ok(stored)) => stored,
+            Ok(Err(until)) => {
+                self.reply(msg, CommandError::Muted(until).into_event());
+                return None;
+            }
+            Err(e) => {
+                println!("Couldn't store message from {}: {}", msg.id, e);
+                let error = ChatEvent::Error {
+                    code: ErrorCode::CommandFailed,
+                    body: "Message was not sent, try again later".to_string(),
+                };
+                self.reply(msg, error);
+                return None;
+            }
+        };
+        let event = ChatEvent::Message {
+            id: stored.id,
+            sender,
+            body: body.to_string(),
+            sent_at: stored.created_at,
+        };
+        self.broadcast(&event, &msg.room_id, None);
+        self.notify(&msg.room_id, &event);
+        Delivered::of(&event)
+    }
+}
+
+/// Message members are notified about, posted by user or integration
+struct Posted<'a> {
+    id: &'a str,
+    sender: Option<&'a Sender>,
+    integration: Option<&'a IntegrationSender>,
+    body: &'a str,
+    sent_at: NaiveDateTime,
+}
+
+impl<'a> Posted<'a> {
+    /// Message announced by event, [None] for other events
+    fn of(event: &'a ChatEvent) -> Option<Self> {
+        match event {
+            ChatEvent::Message {
+                id,
+                sender,
+                body,
+                sent_at,
+            }
+            | ChatEvent::Action {
+                id,
+                sender,
+                body,
+                sent_at,
+            } => Some(Posted {
+                id,
+                sender: Some(sender),
+                integration: None,
+                body,
+                sent_at: *sent_at,
+            }),
+            ChatEvent::Integration {
+                id,
+                integration,
+                body,
+                sent_at,
+                ..
+            } => Some(Posted {
+                id,
+                sender: None,
+                integration: Some(integration),
+                body,
+                sent_at: *sent_at,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Name shown in notifications
+    fn author_name(&self) -> &'a str {
+        match (self.sender, self.integration) {
+            (Some(sender), _) => &sender.username,
+            (None, Some(integration)) => &integration.name,
+            (None, None) => "",
+        }
+    }
+}
+
+/// Decides if message may be posted in room with announcement only mode `read_only`.
+/// Mode is a permission, so message is rejected also when it could not be checked.
+fn read_only_check(read_only: Result<bool, ShopError>) -> Result<(), CommandError> {
+    match read_only {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(CommandError::NoPermission(
+            "Only group admins can post in announcement only mode".to_string(),
+        )),
+        Err(_) => Err(CommandError::Failed(
+            "Message was not sent, try again later".to_string(),
+        )),
+    }
 }
 
 impl Actor for Lobby {
@@ -43,23 +675,26 @@ impl Handler<Disconnect> for Lobby {
     type Result = ();
     /// Method for handling disconnect messages by lobby
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        let connection = utils::establish_connection();
-        let username = User::get_username(&connection, &msg.id.to_string()).unwrap();
         if self.sessions.remove(&msg.id).is_some() {
-            self.rooms
-                .get(&msg.room_id)
-                .unwrap()
-                .iter()
-                .filter(|conn_id| *conn_id.to_owned() != msg.id)
-                .for_each(|user_id| {
-                    self.send_message(&format!("{} disconnected.", username), user_id)
-                });
+            self.connected.write().unwrap().remove(&msg.id);
+            let now = Instant::now();
+            self.limiter.forget(now);
+            self.delivered.forget_expired(now);
+            self.forget_idle_replay(now);
+            self.mark_seen(&msg.room_id, &msg.id);
+            if let Some(sender) = self.sender(&msg.id) {
+                let event = ChatEvent::Notice {
+                    body: format!("{} disconnected.", sender.shown_name()),
+                };
+                self.broadcast(&event, &msg.room_id, Some(&msg.id));
+            }
             if let Some(lobby) = self.rooms.get_mut(&msg.room_id) {
                 if lobby.len() > 1 {
                     lobby.remove(&msg.id);
                 } else {
                     //only one in the lobby, remove it entirely
                     self.rooms.remove(&msg.room_id);
+                    self.retention.remove(&msg.room_id);
                 }
             }
         }
@@ -70,22 +705,24 @@ impl Handler<Connect> for Lobby {
     type Result = ();
     /// Method for handling connect messages by lobby
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
-        let connection = utils::establish_connection();
-        let username = User::get_username(&connection, &msg.self_id.to_string()).unwrap();
         self.rooms
             .entry(msg.lobby_id)
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(msg.self_id);
-
-        self.rooms
-            .get(&msg.lobby_id)
-            .unwrap()
-            .iter()
-            .filter(|conn_id| *conn_id.to_owned() != msg.self_id)
-            .for_each(|conn_id| self.send_message(&format!("{} just joined!", username), conn_id));
-
         self.sessions.insert(msg.self_id, msg.addr);
-        self.send_message(&format!("Welcome {}!", username), &msg.self_id);
+        self.connected.write().unwrap().insert(msg.self_id);
+
+        if let Some(sender) = self.sender(&msg.self_id) {
+            let event = ChatEvent::Notice {
+                body: format!("{} just joined!", sender.shown_name()),
+            };
+            self.broadcast(&event, &msg.lobby_id, Some(&msg.self_id));
+            let welcome = ChatEvent::Notice {
+                body: format!("Welcome {}!", sender.shown_name()),
+            };
+            self.send_message(&welcome, &msg.self_id);
+        }
+        self.resume(&msg.lobby_id, &msg.self_id, msg.last_seq);
     }
 }
 
@@ -93,16 +730,104 @@ impl Handler<ClientActorMessage> for Lobby {
     type Result = ();
     /// Method for handling direct messages by lobby
     fn handle(&mut self, msg: ClientActorMessage, _ctx: &mut Context<Self>) -> Self::Result {
-        if msg.msg.starts_with("\\w") {
-            if let Some(id_to) = msg.msg.split(' ').collect::<Vec<&str>>().get(1) {
-                self.send_message(&msg.msg, &Uuid::parse_str(id_to).unwrap());
+        let now = Instant::now();
+        if let Some(client_id) = &msg.client_id {
+            if let Some(delivered) = self.delivered.get(msg.id, client_id, now) {
+                let ack = delivered.ack(client_id);
+                self.send_message(&ack, &msg.id);
+                return;
             }
-        } else {
-            self.rooms
-                .get(&msg.room_id)
-                .unwrap()
-                .iter()
-                .for_each(|client| self.send_message(&msg.msg, client));
         }
+        if let Err(limited) = self.limiter.check(msg.id, msg.room_id, now) {
+            self.reject(&msg, limited, now);
+            return;
+        }
+        let sender = match self.sender(&msg.id) {
+            Some(sender) => sender,
+            None => {
+                let error = ChatEvent::Error {
+                    code: ErrorCode::CommandFailed,
+                    body: "Message was not sent, try again later".to_string(),
+                };
+                return self.reply(&msg, error);
+            }
+        };
+        let input = commands::parse(&msg.msg);
+        let posts_message = match input {
+            Input::Command { name, .. } => {
+                matches!(self.commands.find(name), Some(command) if command.posts_message())
+            }
+            Input::Text(_) => true,
+        };
+        if posts_message {
+            if let Err(error) = self.check_read_only(&msg) {
+                return self.reply(&msg, error.into_event());
+            }
+            let interval = match self.slow_mode(&msg) {
+                Ok(interval) => interval,
+                Err(error) => return self.reply(&msg, error.into_event()),
+            };
+            if let Some(interval) = interval {
+                let limited = self
+                    .limiter
+                    .check_slow_mode(msg.id, msg.room_id, interval, now);
+                if let Err(limited) = limited {
+                    self.reject(&msg, limited, now);
+                    return;
+                }
+            }
+        }
+        let delivered = match input {
+            Input::Command { name, args } => self.run_command(&msg, &sender, name, args),
+            Input::Text(body) => self.send_text(&msg, sender, body),
+        };
+        if let (Some(client_id), Some(delivered)) = (&msg.client_id, delivered) {
+            self.send_message(&delivered.ack(client_id), &msg.id);
+            self.delivered.insert(msg.id, client_id, delivered, now);
+        }
+    }
+}
+
+impl Handler<RoomEvent> for Lobby {
+    type Result = ();
+    /// Method for broadcasting event coming from outside of websocket sessions, members
+    /// are notified about messages (e.g. from integrations) the same way as in chat
+    fn handle(&mut self, msg: RoomEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        self.broadcast(&msg.event, &msg.room_id, None);
+        self.notify(&msg.room_id, &msg.event);
+    }
+}
+
+impl Handler<RetentionChanged> for Lobby {
+    type Result = ();
+    /// Method for updating cached retention policy of room
+    fn handle(&mut self, msg: RetentionChanged, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.rooms.contains_key(&msg.room_id) {
+            self.retention.insert(msg.room_id, msg.policy);
+        }
+    }
+}
+
+impl Handler<RoomPresence> for Lobby {
+    type Result = MessageResult<RoomPresence>;
+    /// Method for returning ids of users currently connected to room
+    fn handle(&mut self, msg: RoomPresence, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.rooms.get(&msg.room_id).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_check_fails_closed() {
+        assert!(read_only_check(Ok(false)).is_ok());
+        assert!(matches!(
+            read_only_check(Ok(true)),
+            Err(CommandError::NoPermission(_))
+        ));
+        let unknown = read_only_check(Err(ShopError::ConnectionError("down".to_string())));
+        assert!(matches!(unknown, Err(CommandError::Failed(_))));
     }
 }
@@ -0,0 +1,244 @@
+use super::group::GroupRole;
+use super::pagination::{Page, Pagination};
+use super::retention;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{groups, groups_users, mentions, messages, users};
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::sql;
+use diesel::sql_types::BigInt;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Struct for representing member of a group, together with his presence
+#[derive(Debug, Serialize)]
+pub struct GroupMember {
+    pub id: String,
+    pub username: String,
+    pub role: GroupRole,
+    pub joined_at: NaiveDateTime,
+    pub online: bool,
+}
+
+/// Messages of group sent while member was not connected to it
+#[derive(Debug, Serialize)]
+pub struct MissedMessages {
+    pub group_id: String,
+    pub messages: i64,
+    /// Missed messages which mention member, see [create](super::mention::create)
+    pub mentions: i64,
+}
+
+impl GroupMember {
+    /// Get single page of members of provided group, ordered by join date
+    /// # Arguments
+    /// * online - ids of users currently connected to group room
+    /// # Returns
+    /// ## On success
+    /// * page of members: [Page]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn list(
+        connection: &PgConnection,
+        group_id: &str,
+        pagination: &Pagination,
+        online: &HashSet<Uuid>,
+    ) -> Result<Page<GroupMember>, ShopError> {
+        let total = groups_users::table
+            .filter(groups_users::group_id.eq(group_id))
+            .count()
+            .get_result::<i64>(connection)?;
+        let rows = groups_users::table
+            .inner_join(users::table)
+            .filter(groups_users::group_id.eq(group_id))
+            .order((groups_users::joined_at.asc(), users::username.asc()))
+            .limit(pagination.per_page())
+            .offset(pagination.offset())
+            .select((
+                users::id,
+                users::username,
+                groups_users::role,
+                groups_users::joined_at,
+            ))
+            .load::<(String, String, String, NaiveDateTime)>(connection)?;
+        let online: HashSet<String> = online.iter().map(Uuid::to_string).collect();
+        let members = rows
+            .into_iter()
+            .map(|(id, username, role, joined_at)| {
+                Ok(GroupMember {
+                    online: online.contains(&id),
+                    id,
+                    username,
+                    role: role.parse()?,
+                    joined_at,
+                })
+            })
+            .collect::<Result<Vec<_>, ShopError>>()?;
+        Ok(Page::new(pagination, total, members))
+    }
+
+    /// Get time until which member cannot send messages into group
+    /// # Returns
+    /// ## On success
+    /// * end of mute: [NaiveDateTime], or [None] if member is not muted (anymore)
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn muted_until(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<NaiveDateTime>, ShopError> {
+        let muted_until = groups_users::table
+            .select(groups_users::muted_until)
+            .filter(groups_users::group_id.eq(group_id))
+            .filter(groups_users::user_id.eq(user_id))
+            .first::<Option<NaiveDateTime>>(connection)
+            .optional()?
+            .flatten();
+        Ok(muted_until.filter(|until| *until > Utc::now().naive_utc()))
+    }
+
+    /// Mutes member of group until provided time, [None] unmutes him
+    pub fn mute(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+        until: Option<NaiveDateTime>,
+    ) -> Result<(), ShopError> {
+        diesel::update(
+            groups_users::table
+                .filter(groups_users::group_id.eq(group_id))
+                .filter(groups_users::user_id.eq(user_id)),
+        )
+        .set(groups_users::muted_until.eq(until))
+        .execute(connection)?;
+        Ok(())
+    }
+
+    /// Get slow mode interval which applies to member of group
+    /// # Returns
+    /// ## On success
+    /// * minimal delay between messages: [Duration], or [None] when slow mode is off
+    ///   or member is admin of the group
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn slow_mode(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<Duration>, ShopError> {
+        let (seconds, role) = groups::table
+            .inner_join(groups_users::table)
+            .filter(groups::id.eq(group_id))
+            .filter(groups_users::user_id.eq(user_id))
+            .select((groups::slow_mode_seconds, groups_users::role))
+            .first::<(i32, String)>(connection)?;
+        if seconds <= 0 || role.parse::<GroupRole>()? >= GroupRole::Admin {
+            return Ok(None);
+        }
+        Ok(Some(Duration::from_secs(seconds as u64)))
+    }
+
+    /// Checks announcement only mode of group
+    /// # Returns
+    /// ## On success
+    /// * true when group is in announcement only mode and member is not its admin: [bool]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn read_only(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<bool, ShopError> {
+        let (announcement_only, role) = groups::table
+            .inner_join(groups_users::table)
+            .filter(groups::id.eq(group_id))
+            .filter(groups_users::user_id.eq(user_id))
+            .select((groups::announcement_only, groups_users::role))
+            .first::<(bool, String)>(connection)?;
+        Ok(announcement_only && role.parse::<GroupRole>()? < GroupRole::Admin)
+    }
+
+    /// Get sequence number of last event of group which member received
+    pub fn last_seen_seq(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<i64, ShopError> {
+        Ok(groups_users::table
+            .select(groups_users::last_seen_seq)
+            .filter(groups_users::group_id.eq(group_id))
+            .filter(groups_users::user_id.eq(user_id))
+            .first::<i64>(connection)?)
+    }
+
+    /// Marks events of group up to `seq` as received by member, position never moves back.
+    /// Messages received for the first time start to expire in ephemeral groups.
+    pub fn mark_seen(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+        seq: i64,
+    ) -> Result<(), ShopError> {
+        connection.transaction(|| {
+            let previous = GroupMember::last_seen_seq(connection, group_id, user_id)?;
+            if previous >= seq {
+                return Ok(());
+            }
+            diesel::update(
+                groups_users::table
+                    .filter(groups_users::group_id.eq(group_id))
+                    .filter(groups_users::user_id.eq(user_id)),
+            )
+            .set(groups_users::last_seen_seq.eq(seq))
+            .execute(connection)?;
+            retention::messages_read(connection, group_id, user_id, previous, seq)
+        })
+    }
+
+    /// Counts messages sent by others to groups of user since he was last connected to them
+    /// # Returns
+    /// ## On success
+    /// * groups with missed messages: [Vec] of [MissedMessages]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn missed(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<Vec<MissedMessages>, ShopError> {
+        let unseen = groups_users::group_id
+            .eq(messages::group_id)
+            .and(messages::seq.gt(groups_users::last_seen_seq.nullable()));
+        let mention = mentions::message_id
+            .eq(messages::id)
+            .and(mentions::user_id.eq(user_id));
+        let counts = messages::table
+            .inner_join(groups_users::table.on(unseen))
+            .left_join(mentions::table.on(mention))
+            .filter(groups_users::user_id.eq(user_id))
+            .filter(messages::body.is_not_null())
+            .filter(
+                messages::sender_id
+                    .is_null()
+                    .or(messages::sender_id.ne(user_id)),
+            )
+            .group_by(messages::group_id)
+            .select((
+                messages::group_id,
+                // diesel can't mix aggregates with grouped columns
+                sql::<BigInt>("count(*)"),
+                sql::<BigInt>("count(mentions.id)"),
+            ))
+            .load::<(String, i64, i64)>(connection)?;
+        Ok(counts
+            .into_iter()
+            .map(|(group_id, messages, mentions)| MissedMessages {
+                group_id,
+                messages,
+                mentions,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,263 @@
+use super::group::GroupRole;
+use super::pagination::{Page, Pagination};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{groups_users, mentions, messages, users};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Lowest role which can notify whole group with `@all`
+pub const MENTION_ALL_ROLE: GroupRole = GroupRole::Admin;
+
+/// How member was mentioned, stored in `mentions.kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionKind {
+    /// `@username`
+    User,
+    /// `@here`, members connected to room
+    Here,
+    /// `@all`, every member of group
+    All,
+}
+
+impl MentionKind {
+    /// Name of kind, as stored in database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MentionKind::User => "user",
+            MentionKind::Here => "here",
+            MentionKind::All => "all",
+        }
+    }
+}
+
+impl std::str::FromStr for MentionKind {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(MentionKind::User),
+            "here" => Ok(MentionKind::Here),
+            "all" => Ok(MentionKind::All),
+            _ => Err(ShopError::ParseError(format!(
+                "Unknown mention kind: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Mentions found in message body
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Mentions<'a> {
+    pub usernames: Vec<&'a str>,
+    pub here: bool,
+    pub all: bool,
+}
+
+impl Mentions<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.usernames.is_empty() && !self.here && !self.all
+    }
+}
+
+/// Finds `@username`, `@here` and `@all` in message body. Mention has to start at the
+/// beginning of word, so e-mail addresses are not mentions, and trailing `.` or `-`
+/// (e.g. end of sentence) is not part of username.
+pub fn parse(body: &str) -> Mentions<'_> {
+    let mut mentions = Mentions::default();
+    let mut previous = None;
+    for (i, c) in body.char_indices() {
+        let starts_word = !matches!(previous, Some(p) if is_name_char(p));
+        previous = Some(c);
+        if c != '@' || !starts_word {
+            continue;
+        }
+        let rest = &body[i + 1..];
+        let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        let name = rest[..end].trim_end_matches(['.', '-']);
+        match name {
+            "" => (),
+            "here" => mentions.here = true,
+            "all" => mentions.all = true,
+            name => {
+                if !mentions
+                    .usernames
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(name))
+                {
+                    mentions.usernames.push(name);
+                }
+            }
+        }
+    }
+    mentions
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// Struct for inserting mention into database
+#[derive(Insertable)]
+#[table_name = "mentions"]
+struct NewMention<'a> {
+    message_id: &'a str,
+    group_id: &'a str,
+    user_id: &'a str,
+    kind: &'a str,
+}
+
+/// Resolves mentions against members of group and stores them, sender is never
+/// mentioned and `@all` is ignored unless `can_mention_all`
+/// # Arguments
+/// * sender_id - [None] for messages posted by integrations
+/// * online - ids of users currently connected to group room, for `@here`
+/// # Returns
+/// ## On success
+/// * ids of mentioned users with kind of their mention: [Vec]
+/// ## On faliure
+/// * error: [ShopError]
+pub fn create(
+    connection: &PgConnection,
+    group_id: &str,
+    message_id: &str,
+    sender_id: Option<&str>,
+    found: &Mentions,
+    online: &HashSet<Uuid>,
+    can_mention_all: bool,
+) -> Result<Vec<(String, MentionKind)>, ShopError> {
+    let members = groups_users::table
+        .inner_join(users::table)
+        .filter(groups_users::group_id.eq(group_id))
+        .select((users::id, users::username))
+        .load::<(String, String)>(connection)?;
+    let online: HashSet<String> = online.iter().map(Uuid::to_string).collect();
+    let mentioned = members
+        .into_iter()
+        .filter(|(id, _)| Some(id.as_str()) != sender_id)
+        .filter_map(|(id, username)| {
+            let kind = if found
+                .usernames
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&username))
+            {
+                MentionKind::User
+            } else if found.all && can_mention_all {
+                MentionKind::All
+            } else if found.here && online.contains(&id) {
+                MentionKind::Here
+            } else {
+                return None;
+            };
+            Some((id, kind))
+        })
+        .collect::<Vec<_>>();
+    let rows = mentioned
+        .iter()
+        .map(|(user_id, kind)| NewMention {
+            message_id,
+            group_id,
+            user_id,
+            kind: kind.as_str(),
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        diesel::insert_into(mentions::table)
+            .values(&rows)
+            .execute(connection)?;
+    }
+    Ok(mentioned)
+}
+
+/// Mention of user shown in his inbox, newest first
+#[derive(Debug, Serialize)]
+pub struct MentionInfo {
+    pub id: String,
+    pub kind: MentionKind,
+    pub group_id: String,
+    pub message_id: String,
+    pub sender_id: Option<String>,
+    pub sender_username: Option<String>,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl MentionInfo {
+    /// Mentions of user, newest first, mentions in deleted messages are left out
+    pub fn list(
+        connection: &PgConnection,
+        user_id: &str,
+        pagination: &Pagination,
+    ) -> Result<Page<MentionInfo>, ShopError> {
+        let total = mentions::table
+            .inner_join(messages::table)
+            .filter(mentions::user_id.eq(user_id))
+            .filter(messages::body.is_not_null())
+            .count()
+            .get_result::<i64>(connection)?;
+        let rows = mentions::table
+            .inner_join(messages::table.left_join(users::table))
+            .filter(mentions::user_id.eq(user_id))
+            .filter(messages::body.is_not_null())
+            .order(mentions::created_at.desc())
+            .limit(pagination.per_page())
+            .offset(pagination.offset())
+            .select((
+                mentions::id,
+                mentions::kind,
+                mentions::group_id,
+                mentions::message_id,
+                messages::sender_id,
+                users::username.nullable(),
+                messages::body,
+                mentions::created_at,
+            ))
+            .load::<(
+                String,
+                String,
+                String,
+                String,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                NaiveDateTime,
+            )>(connection)?;
+        let items = rows
+            .into_iter()
+            .map(
+                |(id, kind, group_id, message_id, sender_id, sender_username, body, created_at)| {
+                    Ok(MentionInfo {
+                        id,
+                        kind: kind.parse()?,
+                        group_id,
+                        message_id,
+                        sender_id,
+                        sender_username,
+                        body: body.unwrap_or_default(),
+                        created_at,
+                    })
+                },
+            )
+            .collect::<Result<Vec<_>, ShopError>>()?;
+        Ok(Page::new(pagination, total, items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mentions() {
+        let mentions = parse("@alice and @Bob_1, see mail@example.com. Thanks @bob_1. @here");
+        assert_eq!(mentions.usernames, vec!["alice", "Bob_1"]);
+        assert!(mentions.here);
+        assert!(!mentions.all);
+        assert!(parse("no one @ all").is_empty());
+        assert!(parse("(@all)").all);
+    }
+}
@@ -1,17 +1,35 @@
-use actix::prelude::{Message, Recipient};
+use super::events::ChatEvent;
+use super::retention::RetentionPolicy;
+use super::ws::WsConn;
+use actix::prelude::{Addr, Message};
+use actix_web_actors::ws::CloseCode;
+use std::collections::HashSet;
 use uuid::Uuid;
 
-/// Basic Message struct for holding message: [String]
-#[derive(Message)]
+/// Message struct for holding serialized event sent to websocket
+#[derive(Debug, Message)]
 #[rtype(result = "()")]
-pub struct WsMessage(pub String);
+pub struct WsMessage {
+    pub text: String,
+    /// Event which can be dropped when client is too slow, see [ChatEvent::is_ephemeral]
+    pub ephemeral: bool,
+}
 /// Message struct that sends connect information
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Connect {
-    pub addr: Recipient<WsMessage>,
+    pub addr: Addr<WsConn>,
     pub lobby_id: Uuid,
     pub self_id: Uuid,
+    /// Sequence number of last event received before reconnecting
+    pub last_seq: Option<i64>,
+}
+/// Message struct for closing websocket session with provided code and reason
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseSession {
+    pub code: CloseCode,
+    pub reason: String,
 }
 /// Message struct that sends disconnect information
 #[derive(Message)]
@@ -27,4 +45,27 @@ pub struct ClientActorMessage {
     pub id: Uuid,
     pub msg: String,
     pub room_id: Uuid,
+    /// Id generated by client, which wants to be acknowledged
+    pub client_id: Option<String>,
+}
+/// Message struct for broadcasting event to everyone connected to room,
+/// when it does not come from websocket session (e.g. from HTTP route)
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoomEvent {
+    pub room_id: Uuid,
+    pub event: ChatEvent,
+}
+/// Message struct for telling lobby that retention policy of room was changed
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RetentionChanged {
+    pub room_id: Uuid,
+    pub policy: RetentionPolicy,
+}
+/// Message struct for asking lobby which users are connected to room
+#[derive(Message)]
+#[rtype(result = "HashSet<Uuid>")]
+pub struct RoomPresence {
+    pub room_id: Uuid,
 }
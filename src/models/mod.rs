@@ -1,6 +1,26 @@
 //! Module with all models
+pub mod account;
+pub mod bot;
+pub mod chat_message;
+pub mod delivery;
+pub mod events;
+pub mod external_identity;
 pub mod group;
+pub mod integration;
 pub mod lobby;
+pub mod member;
+pub mod mention;
 pub mod messages;
+pub mod notification;
+pub mod outbound;
+pub mod outbox;
+pub mod pagination;
+pub mod password_reset;
+pub mod pin;
+pub mod profile;
+pub mod replay;
+pub mod retention;
+pub mod two_factor;
 pub mod user;
+pub mod webhook;
 pub mod ws;
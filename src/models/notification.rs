@@ -0,0 +1,440 @@
+use super::user::User;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::notifier::{Notification, Notifier};
+use crate::schema::{groups_users, notification_settings};
+use crate::utils::token;
+use chrono::{Duration, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Which messages of group member wants to be notified about, stored in
+/// `groups_users.notify_level`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyLevel {
+    All,
+    Mentions,
+    None,
+}
+
+impl NotifyLevel {
+    /// Name of level, as stored in database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyLevel::All => "all",
+            NotifyLevel::Mentions => "mentions",
+            NotifyLevel::None => "none",
+        }
+    }
+}
+
+impl std::str::FromStr for NotifyLevel {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(NotifyLevel::All),
+            "mentions" => Ok(NotifyLevel::Mentions),
+            "none" => Ok(NotifyLevel::None),
+            _ => Err(ShopError::ParseError(format!(
+                "Unknown notify level: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// What notification would be about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyReason {
+    Message,
+    Mention,
+}
+
+/// Notification settings of member for single group, also received from request
+/// for changing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupNotifications {
+    pub level: NotifyLevel,
+    /// No notifications from group until this time, regardless of level
+    pub muted_until: Option<NaiveDateTime>,
+}
+
+impl GroupNotifications {
+    /// True when member wants to be notified for `reason`
+    pub fn allows(&self, reason: NotifyReason, now: NaiveDateTime) -> bool {
+        if matches!(self.muted_until, Some(until) if until > now) {
+            return false;
+        }
+        match self.level {
+            NotifyLevel::All => true,
+            NotifyLevel::Mentions => reason == NotifyReason::Mention,
+            NotifyLevel::None => false,
+        }
+    }
+
+    /// Get notification settings of member for group
+    pub fn get(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<GroupNotifications, ShopError> {
+        let (level, muted_until) = groups_users::table
+            .select((groups_users::notify_level, groups_users::notify_muted_until))
+            .filter(groups_users::group_id.eq(group_id))
+            .filter(groups_users::user_id.eq(user_id))
+            .first::<(String, Option<NaiveDateTime>)>(connection)?;
+        Ok(GroupNotifications {
+            level: level.parse()?,
+            muted_until,
+        })
+    }
+
+    /// Changes notification settings of member for group
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] when user is not a member
+    pub fn set(
+        &self,
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<(), ShopError> {
+        let updated = diesel::update(
+            groups_users::table
+                .filter(groups_users::group_id.eq(group_id))
+                .filter(groups_users::user_id.eq(user_id)),
+        )
+        .set((
+            groups_users::notify_level.eq(self.level.as_str()),
+            groups_users::notify_muted_until.eq(self.muted_until),
+        ))
+        .execute(connection)?;
+        if updated == 0 {
+            return Err(ShopError::NotFoundError(
+                "You are not a member of that group".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Minutes notifications of offline user are collected into single digest, unless he
+/// changed it
+pub const DEFAULT_DIGEST_MINUTES: i32 = 10;
+
+fn default_digest_minutes() -> i32 {
+    DEFAULT_DIGEST_MINUTES
+}
+
+/// Global notification settings of user, also received from request for changing them.
+/// Do not disturb is on every day from `dnd_start` until `dnd_end` in user's local time,
+/// window may cross midnight (e.g. 22:00:00 - 07:00:00).
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Serialize, Deserialize, Validate)]
+pub struct NotificationSettings {
+    #[serde(skip)]
+    pub user_id: String,
+    pub dnd_start: Option<NaiveTime>,
+    pub dnd_end: Option<NaiveTime>,
+    /// Offset of user's time zone from UTC
+    #[validate(range(min = -720, max = 840))]
+    pub utc_offset_minutes: i32,
+    /// Confirmed address for e-mail notifications, changing it requires current password
+    #[validate(email, length(max = 254))]
+    pub email: Option<String>,
+    /// Device token for push notifications
+    #[validate(length(min = 1, max = 4096))]
+    pub push_token: Option<String>,
+    /// How long notifications are collected before they are sent as single digest,
+    /// 0 sends them as soon as possible
+    #[serde(default = "default_digest_minutes")]
+    #[validate(range(min = 0, max = 1440))]
+    pub digest_minutes: i32,
+    /// Address which replaces `email` once user confirms it, read only
+    #[serde(default, skip_deserializing)]
+    pub pending_email: Option<String>,
+}
+
+/// Struct received from request for changing global notification settings
+#[derive(Debug, Deserialize)]
+pub struct NotificationSettingsUpdate {
+    #[serde(flatten)]
+    pub settings: NotificationSettings,
+    /// Current password, needed only when `email` changes. Not needed by users without
+    /// password, see [User::confirm_identity]
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Struct received from request for confirming new e-mail address
+#[derive(Debug, Deserialize)]
+pub struct EmailConfirmation {
+    pub token: String,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            user_id: String::new(),
+            dnd_start: None,
+            dnd_end: None,
+            utc_offset_minutes: 0,
+            email: None,
+            push_token: None,
+            digest_minutes: DEFAULT_DIGEST_MINUTES,
+            pending_email: None,
+        }
+    }
+}
+
+impl NotificationSettings {
+    /// True when do not disturb window is on at `now` (UTC)
+    pub fn is_do_not_disturb(&self, now: NaiveDateTime) -> bool {
+        self.do_not_disturb_until(now).is_some()
+    }
+
+    /// End of do not disturb window (UTC) which is on at `now`, [None] when it is off
+    pub fn do_not_disturb_until(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let (start, end) = match (self.dnd_start, self.dnd_end) {
+            (Some(start), Some(end)) if start != end => (start, end),
+            _ => return None,
+        };
+        let offset = Duration::minutes(self.utc_offset_minutes as i64);
+        let local = now + offset;
+        let time = local.time();
+        let on = if start < end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        };
+        if !on {
+            return None;
+        }
+        let mut until = local.date().and_time(end);
+        if time >= end {
+            until += Duration::days(1);
+        }
+        Some(until - offset)
+    }
+
+    /// Get global settings of user, defaults when he never changed them
+    pub fn get(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<NotificationSettings, ShopError> {
+        let settings = notification_settings::table
+            .find(user_id)
+            .select((
+                notification_settings::user_id,
+                notification_settings::dnd_start,
+                notification_settings::dnd_end,
+                notification_settings::utc_offset_minutes,
+                notification_settings::email,
+                notification_settings::push_token,
+                notification_settings::digest_minutes,
+                notification_settings::pending_email,
+            ))
+            .first::<NotificationSettings>(connection)
+            .optional()?;
+        Ok(settings.unwrap_or_else(|| NotificationSettings {
+            user_id: user_id.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    /// Stores global settings of user, except for `email`, which is changed by
+    /// [NotificationSettings::change_email]
+    pub fn set(&self, connection: &PgConnection, user_id: &str) -> Result<(), ShopError> {
+        let values = (
+            notification_settings::user_id.eq(user_id),
+            notification_settings::dnd_start.eq(self.dnd_start),
+            notification_settings::dnd_end.eq(self.dnd_end),
+            notification_settings::utc_offset_minutes.eq(self.utc_offset_minutes),
+            notification_settings::push_token.eq(&self.push_token),
+            notification_settings::digest_minutes.eq(self.digest_minutes),
+        );
+        diesel::insert_into(notification_settings::table)
+            .values(values)
+            .on_conflict(notification_settings::user_id)
+            .do_update()
+            .set(values)
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Changes e-mail address of user whose settings were stored. Removing address takes
+    /// effect at once, new address is only kept as pending and single-use confirmation
+    /// token is delivered to it through `notifier`, see [NotificationSettings::confirm_email].
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn change_email(
+        connection: &PgConnection,
+        notifier: &dyn Notifier,
+        user: &User,
+        email: Option<&str>,
+    ) -> Result<(), ShopError> {
+        let settings = notification_settings::table.find(&user.id);
+        let email = match email {
+            Some(email) => email,
+            None => {
+                diesel::update(settings)
+                    .set((
+                        notification_settings::email.eq(None::<String>),
+                        notification_settings::pending_email.eq(None::<String>),
+                        notification_settings::email_token_hash.eq(None::<String>),
+                        notification_settings::email_token_expires_at.eq(None::<NaiveDateTime>),
+                    ))
+                    .execute(connection)?;
+                return Ok(());
+            }
+        };
+        let lifetime = dotenv::var("EMAIL_CONFIRMATION_LIFETIME_IN_SECONDS")
+            .unwrap_or_else(|_| "3600".into())
+            .parse()?;
+        let expires_at = Utc::now().naive_utc() + Duration::seconds(lifetime);
+        let confirmation_token = token::generate();
+        diesel::update(settings)
+            .set((
+                notification_settings::pending_email.eq(email),
+                notification_settings::email_token_hash.eq(token::hash(&confirmation_token)),
+                notification_settings::email_token_expires_at.eq(expires_at),
+            ))
+            .execute(connection)?;
+        notifier.notify(&Notification {
+            user_id: user.id.clone(),
+            username: user.username.clone(),
+            subject: "Confirm e-mail address".to_string(),
+            body: format!(
+                "Use this token to confirm your e-mail address: {}\nIt can be used once, until {} UTC.",
+                confirmation_token, expires_at
+            ),
+            email: Some(email.to_string()),
+            push_token: None,
+        })
+    }
+
+    /// Redeems confirmation token of user, his pending address becomes `email`
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NoPermission] if token is invalid or expired
+    pub fn confirm_email(
+        connection: &PgConnection,
+        user_id: &str,
+        confirmation_token: &str,
+    ) -> Result<(), ShopError> {
+        let confirmed = diesel::update(
+            notification_settings::table
+                .filter(notification_settings::user_id.eq(user_id))
+                .filter(notification_settings::pending_email.is_not_null())
+                .filter(notification_settings::email_token_hash.eq(token::hash(confirmation_token)))
+                .filter(notification_settings::email_token_expires_at.gt(Utc::now().naive_utc())),
+        )
+        .set((
+            notification_settings::email.eq(notification_settings::pending_email),
+            notification_settings::pending_email.eq(None::<String>),
+            notification_settings::email_token_hash.eq(None::<String>),
+            notification_settings::email_token_expires_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(connection)?;
+        if confirmed == 0 {
+            return Err(ShopError::NoPermission(
+                "Invalid or expired confirmation token".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks group settings of member and his do not disturb window
+/// # Returns
+/// ## On success
+/// * true when member should be notified about `reason` now: [bool]
+/// ## On faliure
+/// * error: [ShopError]
+pub fn should_notify(
+    connection: &PgConnection,
+    group_id: &str,
+    user_id: &str,
+    reason: NotifyReason,
+) -> Result<bool, ShopError> {
+    let now = Utc::now().naive_utc();
+    if !GroupNotifications::get(connection, group_id, user_id)?.allows(reason, now) {
+        return Ok(false);
+    }
+    Ok(!NotificationSettings::get(connection, user_id)?.is_do_not_disturb(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2022, 11, 18).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn test_group_notifications() {
+        let mentions = GroupNotifications {
+            level: NotifyLevel::Mentions,
+            muted_until: None,
+        };
+        assert!(mentions.allows(NotifyReason::Mention, at(12, 0)));
+        assert!(!mentions.allows(NotifyReason::Message, at(12, 0)));
+        let muted = GroupNotifications {
+            level: NotifyLevel::All,
+            muted_until: Some(at(13, 0)),
+        };
+        assert!(!muted.allows(NotifyReason::Mention, at(12, 0)));
+        assert!(muted.allows(NotifyReason::Message, at(13, 30)));
+    }
+
+    #[test]
+    fn test_do_not_disturb_crosses_midnight() {
+        let settings = NotificationSettings {
+            user_id: "id".to_string(),
+            dnd_start: Some(NaiveTime::from_hms(22, 0, 0)),
+            dnd_end: Some(NaiveTime::from_hms(7, 0, 0)),
+            utc_offset_minutes: 60,
+            ..Default::default()
+        };
+        assert!(settings.is_do_not_disturb(at(21, 0)));
+        assert!(settings.is_do_not_disturb(at(5, 59)));
+        assert!(!settings.is_do_not_disturb(at(6, 0)));
+        assert!(!settings.is_do_not_disturb(at(20, 59)));
+        assert!(!NotificationSettings::default().is_do_not_disturb(at(23, 0)));
+    }
+
+    #[test]
+    fn test_do_not_disturb_until() {
+        let settings = NotificationSettings {
+            dnd_start: Some(NaiveTime::from_hms(22, 0, 0)),
+            dnd_end: Some(NaiveTime::from_hms(7, 0, 0)),
+            utc_offset_minutes: 60,
+            ..Default::default()
+        };
+        let tomorrow = NaiveDate::from_ymd(2022, 11, 19).and_hms(6, 0, 0);
+        assert_eq!(settings.do_not_disturb_until(at(21, 30)), Some(tomorrow));
+        assert_eq!(settings.do_not_disturb_until(at(5, 0)), Some(at(6, 0)));
+        assert_eq!(settings.do_not_disturb_until(at(12, 0)), None);
+    }
+
+    #[test]
+    fn test_settings_update() {
+        let update: NotificationSettingsUpdate = serde_json::from_str(
+            r#"{
+                "utc_offset_minutes": 0,
+                "email": "user@example.com",
+                "pending_email": "other@example.com",
+                "password": "password1"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(update.password.as_deref(), Some("password1"));
+        assert_eq!(update.settings.email.as_deref(), Some("user@example.com"));
+        assert_eq!(update.settings.pending_email, None);
+        assert_eq!(update.settings.digest_minutes, DEFAULT_DIGEST_MINUTES);
+    }
+}
@@ -0,0 +1,203 @@
+//! Backpressure of events sent to websocket sessions
+//!
+//! Events for session wait in [OutboundQueue] of its [WsConn](super::ws::WsConn) and are
+//! written to socket only while less than [QueueLimits::max_in_flight_bytes] are waiting
+//! for network. [OutboundStream] wraps body of websocket response and counts bytes which
+//! were actually taken by network. When queue of slow client gets full, oldest ephemeral
+//! events (e.g. join notices) are dropped, and when there is none, session is closed.
+use super::messages::WsMessage;
+use crate::metrics::Metrics;
+use actix_web::web::Bytes;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Limits of outbound queue of single session, read from .env file
+#[derive(Debug, Clone, Copy)]
+pub struct QueueLimits {
+    /// Events waiting in queue before slow session is handled
+    pub max_queued_events: usize,
+    /// Bytes written to socket but not taken by network yet
+    pub max_in_flight_bytes: usize,
+}
+
+impl QueueLimits {
+    /// Reads limits from `WS_MAX_QUEUED_EVENTS` (default 256) and `WS_MAX_IN_FLIGHT_BYTES`
+    /// (65536)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: usize| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        QueueLimits {
+            max_queued_events: read("WS_MAX_QUEUED_EVENTS", 256).max(1),
+            max_in_flight_bytes: read("WS_MAX_IN_FLIGHT_BYTES", 64 * 1024).max(1),
+        }
+    }
+}
+
+/// Result of [OutboundQueue::push]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushed {
+    Queued,
+    /// Queue was full, oldest ephemeral event was dropped to make room
+    DroppedOldest,
+    /// Queue was full of events which cannot be dropped, new ephemeral event was dropped
+    DroppedNew,
+    /// Queue is full of events which cannot be dropped, session should be closed
+    Overflow,
+}
+
+/// Events waiting to be written to socket
+#[derive(Debug)]
+pub struct OutboundQueue {
+    limit: usize,
+    events: VecDeque<WsMessage>,
+}
+
+impl OutboundQueue {
+    pub fn new(limit: usize) -> Self {
+        OutboundQueue {
+            limit,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn push(&mut self, message: WsMessage) -> Pushed {
+        let mut pushed = Pushed::Queued;
+        if self.events.len() >= self.limit {
+            match self.events.iter().position(|queued| queued.ephemeral) {
+                Some(oldest) => {
+                    self.events.remove(oldest);
+                    pushed = Pushed::DroppedOldest;
+                }
+                None if message.ephemeral => return Pushed::DroppedNew,
+                None => return Pushed::Overflow,
+            }
+        }
+        self.events.push_back(message);
+        pushed
+    }
+
+    pub fn pop(&mut self) -> Option<WsMessage> {
+        self.events.pop_front()
+    }
+}
+
+/// Bytes written to socket of session, but not taken by network yet
+#[derive(Debug, Default)]
+pub struct InFlight(AtomicUsize);
+
+impl InFlight {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn add(&self, bytes: usize) {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Subtracts written bytes, which also contain frames not counted by [InFlight::add]
+    /// (e.g. pings), so it stops at zero
+    /// # Returns
+    /// * number of subtracted bytes
+    fn written(&self, bytes: usize) -> usize {
+        let previous = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |in_flight| {
+                Some(in_flight.saturating_sub(bytes))
+            })
+            .unwrap_or_default();
+        previous.min(bytes)
+    }
+}
+
+/// Body of websocket response, which tracks bytes taken by network
+pub struct OutboundStream<S> {
+    inner: Pin<Box<S>>,
+    in_flight: Arc<InFlight>,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> OutboundStream<S> {
+    pub fn new(inner: S, in_flight: Arc<InFlight>, metrics: Arc<Metrics>) -> Self {
+        OutboundStream {
+            inner: Box::pin(inner),
+            in_flight,
+            metrics,
+        }
+    }
+}
+
+impl<S, E> Stream for OutboundStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &polled {
+            let written = self.in_flight.written(bytes.len());
+            self.metrics
+                .ws_in_flight_bytes
+                .fetch_sub(written as i64, Ordering::Relaxed);
+        }
+        polled
+    }
+}
+
+impl<S> Drop for OutboundStream<S> {
+    fn drop(&mut self) {
+        let unsent = self.in_flight.written(usize::MAX);
+        self.metrics
+            .ws_in_flight_bytes
+            .fetch_sub(unsent as i64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, ephemeral: bool) -> WsMessage {
+        WsMessage {
+            text: text.to_string(),
+            ephemeral,
+        }
+    }
+
+    #[test]
+    fn test_queue_drops_ephemeral_then_overflows() {
+        let mut queue = OutboundQueue::new(2);
+        assert_eq!(queue.push(message("joined", true)), Pushed::Queued);
+        assert_eq!(queue.push(message("a", false)), Pushed::Queued);
+        assert_eq!(queue.push(message("b", false)), Pushed::DroppedOldest);
+        assert_eq!(queue.push(message("left", true)), Pushed::DroppedNew);
+        assert_eq!(queue.push(message("c", false)), Pushed::Overflow);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().map(|m| m.text), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_in_flight_stops_at_zero() {
+        let in_flight = InFlight::default();
+        in_flight.add(10);
+        assert_eq!(in_flight.written(4), 4);
+        assert_eq!(in_flight.written(100), 6);
+        assert_eq!(in_flight.get(), 0);
+    }
+}
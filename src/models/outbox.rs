@@ -0,0 +1,343 @@
+//! Notifications waiting for users who were offline when they were mentioned or when
+//! message was posted in their group
+//!
+//! Lobby queues them with [enqueue] and [crate::notifier::dispatcher] sends them later,
+//! collected into digests.
+use super::notification::{GroupNotifications, NotifyReason};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::notifier::Notifier;
+use crate::schema::{groups, groups_users, messages, notification_outbox, notification_settings};
+use chrono::{NaiveDateTime, Utc};
+use std::collections::HashMap;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_SENT: &str = "sent";
+const STATUS_SKIPPED: &str = "skipped";
+const STATUS_FAILED: &str = "failed";
+
+impl NotifyReason {
+    /// Name of reason, as stored in database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyReason::Message => "message",
+            NotifyReason::Mention => "mention",
+        }
+    }
+}
+
+impl std::str::FromStr for NotifyReason {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message" => Ok(NotifyReason::Message),
+            "mention" => Ok(NotifyReason::Mention),
+            _ => Err(ShopError::ParseError(format!(
+                "Unknown notify reason: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Message which offline members may be notified about
+pub struct NotifiedMessage<'a> {
+    pub group_id: &'a str,
+    pub message_id: &'a str,
+    /// [None] for messages posted by integrations
+    pub sender_id: Option<&'a str>,
+    pub sender_username: &'a str,
+    pub body: &'a str,
+}
+
+/// Struct for inserting notification into outbox
+#[derive(Insertable)]
+#[table_name = "notification_outbox"]
+struct NewNotification<'a> {
+    user_id: &'a str,
+    group_id: &'a str,
+    message_id: &'a str,
+    reason: &'a str,
+    title: String,
+    body: &'a str,
+}
+
+/// Queues notifications about message for members of its group who are not connected,
+/// mentioned members are notified about mention, the rest about message if their
+/// settings of group allow it. Sender and members `notifier` cannot reach (e.g. without
+/// e-mail when only e-mail is configured) are never notified.
+/// # Arguments
+/// * mentioned - ids of members mentioned in message
+/// * is_online - true for ids of users connected to lobby
+/// # Returns
+/// ## On success
+/// * number of queued notifications: [usize]
+/// ## On faliure
+/// * error: [ShopError]
+pub fn enqueue(
+    connection: &PgConnection,
+    message: &NotifiedMessage,
+    mentioned: &[String],
+    notifier: &dyn Notifier,
+    is_online: impl Fn(&str) -> bool,
+) -> Result<usize, ShopError> {
+    let settings = notification_settings::user_id.eq(groups_users::user_id);
+    let members = groups_users::table
+        .left_join(notification_settings::table.on(settings))
+        .filter(groups_users::group_id.eq(message.group_id))
+        .select((
+            groups_users::user_id,
+            groups_users::notify_level,
+            groups_users::notify_muted_until,
+            notification_settings::email.nullable(),
+            notification_settings::push_token.nullable(),
+        ))
+        .load::<(
+            String,
+            String,
+            Option<NaiveDateTime>,
+            Option<String>,
+            Option<String>,
+        )>(connection)?;
+    let group_name = groups::table
+        .find(message.group_id)
+        .select(groups::name)
+        .first::<String>(connection)?;
+    let now = Utc::now().naive_utc();
+    let mut rows = Vec::new();
+    for (user_id, level, muted_until, email, push_token) in &members {
+        if Some(user_id.as_str()) == message.sender_id
+            || is_online(user_id)
+            || !notifier.reaches(email.as_deref(), push_token.as_deref())
+        {
+            continue;
+        }
+        let reason = if mentioned.contains(user_id) {
+            NotifyReason::Mention
+        } else {
+            NotifyReason::Message
+        };
+        let settings = GroupNotifications {
+            level: level.parse()?,
+            muted_until: *muted_until,
+        };
+        if !settings.allows(reason, now) {
+            continue;
+        }
+        rows.push(NewNotification {
+            user_id,
+            group_id: message.group_id,
+            message_id: message.message_id,
+            reason: reason.as_str(),
+            title: title(reason, message.sender_username, &group_name),
+            body: message.body,
+        });
+    }
+    if !rows.is_empty() {
+        diesel::insert_into(notification_outbox::table)
+            .values(&rows)
+            .execute(connection)?;
+    }
+    Ok(rows.len())
+}
+
+fn title(reason: NotifyReason, sender: &str, group: &str) -> String {
+    match reason {
+        NotifyReason::Mention => format!("{} mentioned you in {}", sender, group),
+        NotifyReason::Message => format!("{} in {}", sender, group),
+    }
+}
+
+/// Struct for representing single queued notification
+#[derive(Debug, Clone, Queryable)]
+pub struct QueuedNotification {
+    pub id: String,
+    pub user_id: String,
+    pub group_id: String,
+    pub message_id: String,
+    pub reason: String,
+    pub title: String,
+    pub body: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+    /// Space separated channels which delivered notification, so retries skip them
+    pub delivered_channels: String,
+}
+
+/// Pending notifications of single user, split by whether they are still worth sending
+#[derive(Debug, Default)]
+pub struct Pending {
+    /// Notifications to send, oldest first
+    pub current: Vec<QueuedNotification>,
+    /// Ids of notifications about messages which were deleted or read in the meantime,
+    /// or whose group was left or muted
+    pub stale: Vec<String>,
+}
+
+impl QueuedNotification {
+    /// Channels which already delivered notification
+    pub fn delivered_channels(&self) -> Vec<&str> {
+        self.delivered_channels.split_whitespace().collect()
+    }
+
+    /// Users who have pending notifications due to be sent
+    pub fn due_users(connection: &PgConnection, limit: i64) -> Result<Vec<String>, ShopError> {
+        Ok(notification_outbox::table
+            .filter(notification_outbox::status.eq(STATUS_PENDING))
+            .filter(notification_outbox::next_attempt_at.le(Utc::now().naive_utc()))
+            .select(notification_outbox::user_id)
+            .distinct()
+            .limit(limit)
+            .load::<String>(connection)?)
+    }
+
+    /// Every pending notification of user, checked against current state of messages and
+    /// group settings of user
+    pub fn pending(connection: &PgConnection, user_id: &str) -> Result<Pending, ShopError> {
+        let rows = notification_outbox::table
+            .inner_join(messages::table)
+            .filter(notification_outbox::user_id.eq(user_id))
+            .filter(notification_outbox::status.eq(STATUS_PENDING))
+            .order(notification_outbox::created_at.asc())
+            .select((
+                notification_outbox::all_columns,
+                messages::seq,
+                messages::body.is_not_null(),
+            ))
+            .load::<(QueuedNotification, Option<i64>, bool)>(connection)?;
+        let memberships: HashMap<String, (i64, GroupNotifications)> = groups_users::table
+            .filter(groups_users::user_id.eq(user_id))
+            .select((
+                groups_users::group_id,
+                groups_users::last_seen_seq,
+                groups_users::notify_level,
+                groups_users::notify_muted_until,
+            ))
+            .load::<(String, i64, String, Option<NaiveDateTime>)>(connection)?
+            .into_iter()
+            .map(|(group_id, last_seen_seq, level, muted_until)| {
+                let settings = GroupNotifications {
+                    level: level.parse()?,
+                    muted_until,
+                };
+                Ok((group_id, (last_seen_seq, settings)))
+            })
+            .collect::<Result<_, ShopError>>()?;
+        let now = Utc::now().naive_utc();
+        let mut pending = Pending::default();
+        for (queued, seq, exists) in rows {
+            let current = match memberships.get(&queued.group_id) {
+                Some((last_seen_seq, settings)) => {
+                    exists
+                        && !matches!(seq, Some(seq) if seq <= *last_seen_seq)
+                        && settings.allows(queued.reason.parse()?, now)
+                }
+                None => false,
+            };
+            if current {
+                pending.current.push(queued);
+            } else {
+                pending.stale.push(queued.id);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Notifications are not worth sending anymore
+    pub fn skip(connection: &PgConnection, ids: &[String]) -> Result<(), ShopError> {
+        diesel::update(notification_outbox::table.filter(notification_outbox::id.eq_any(ids)))
+            .set(notification_outbox::status.eq(STATUS_SKIPPED))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Notifications will not be attempted before `until`
+    pub fn postpone(
+        connection: &PgConnection,
+        ids: &[String],
+        until: NaiveDateTime,
+    ) -> Result<(), ShopError> {
+        diesel::update(notification_outbox::table.filter(notification_outbox::id.eq_any(ids)))
+            .set(notification_outbox::next_attempt_at.eq(until))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Records notifications which were delivered
+    pub fn record_sent(connection: &PgConnection, ids: &[String]) -> Result<(), ShopError> {
+        diesel::update(notification_outbox::table.filter(notification_outbox::id.eq_any(ids)))
+            .set((
+                notification_outbox::status.eq(STATUS_SENT),
+                notification_outbox::attempts.eq(notification_outbox::attempts + 1),
+                notification_outbox::last_error.eq(None::<String>),
+                notification_outbox::sent_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Records failed attempt, notification is retried at `retry_at` unless it is its
+    /// last attempt. Channels which delivered it during the attempt are remembered
+    /// together with earlier ones.
+    pub fn record_failure(
+        &self,
+        connection: &PgConnection,
+        last_attempt: bool,
+        retry_at: NaiveDateTime,
+        error: &str,
+        delivered: &[&str],
+    ) -> Result<(), ShopError> {
+        let mut channels = self.delivered_channels();
+        for channel in delivered {
+            if !channels.contains(channel) {
+                channels.push(channel);
+            }
+        }
+        // sorted, so notifications delivered through same channels are sent together
+        channels.sort_unstable();
+        diesel::update(notification_outbox::table.find(&self.id))
+            .set((
+                notification_outbox::status.eq(if last_attempt {
+                    STATUS_FAILED
+                } else {
+                    STATUS_PENDING
+                }),
+                notification_outbox::attempts.eq(self.attempts + 1),
+                notification_outbox::next_attempt_at.eq(retry_at),
+                notification_outbox::last_error.eq(error),
+                notification_outbox::delivered_channels.eq(channels.join(" ")),
+            ))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Deletes every notification for user and about messages he sent
+    pub fn delete_by_user(connection: &PgConnection, user_id: &str) -> Result<(), ShopError> {
+        let sent = messages::table
+            .select(messages::id)
+            .filter(messages::sender_id.eq(user_id));
+        diesel::delete(
+            notification_outbox::table.filter(
+                notification_outbox::user_id
+                    .eq(user_id)
+                    .or(notification_outbox::message_id.eq_any(sent)),
+            ),
+        )
+        .execute(connection)?;
+        Ok(())
+    }
+
+    /// Deletes every notification about group
+    pub fn delete_by_group(connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        diesel::delete(
+            notification_outbox::table.filter(notification_outbox::group_id.eq(group_id)),
+        )
+        .execute(connection)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Struct received from query string of paginated list requests
+#[derive(Debug, Default, Deserialize)]
+pub struct Pagination {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl Pagination {
+    /// Requested page, starting from 1
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+    /// Requested page size, limited to [MAX_PAGE_SIZE]
+    pub fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+    /// Number of rows to skip for requested page
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1).saturating_mul(self.per_page())
+    }
+}
+
+/// Struct for holding single page of some list together with paging info
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub items: Vec<T>,
+}
+
+impl<T> Page<T> {
+    pub fn new(pagination: &Pagination, total: i64, items: Vec<T>) -> Self {
+        Page {
+            page: pagination.page(),
+            per_page: pagination.per_page(),
+            total,
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(page: Option<i64>, per_page: Option<i64>) -> Pagination {
+        Pagination { page, per_page }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let pagination = pagination(None, None);
+        assert_eq!(pagination.page(), 1);
+        assert_eq!(pagination.per_page(), DEFAULT_PAGE_SIZE);
+        assert_eq!(pagination.offset(), 0);
+    }
+
+    #[test]
+    fn test_offset() {
+        assert_eq!(pagination(Some(3), Some(10)).offset(), 20);
+        assert_eq!(pagination(Some(0), Some(10)).offset(), 0);
+        assert_eq!(pagination(Some(-5), Some(10)).offset(), 0);
+        assert_eq!(pagination(Some(i64::MAX), Some(10)).offset(), i64::MAX);
+    }
+
+    #[test]
+    fn test_per_page_is_clamped() {
+        assert_eq!(pagination(None, Some(0)).per_page(), 1);
+        assert_eq!(pagination(None, Some(-3)).per_page(), 1);
+        assert_eq!(
+            pagination(None, Some(MAX_PAGE_SIZE + 1)).per_page(),
+            MAX_PAGE_SIZE
+        );
+        assert_eq!(
+            pagination(Some(2), Some(MAX_PAGE_SIZE * 2)).offset(),
+            MAX_PAGE_SIZE
+        );
+    }
+}
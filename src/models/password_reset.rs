@@ -0,0 +1,124 @@
+use super::bot::Bot;
+use super::notification::NotificationSettings;
+use super::user::{User, MIN_PASSWORD_LENGTH};
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::notifier::{Notification, Notifier};
+use crate::schema::password_resets;
+use crate::utils::token;
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+/// Struct for representing issued password reset token, only hash of token is stored
+#[derive(Debug, Queryable)]
+pub struct PasswordReset {
+    pub id: String,
+    pub user_id: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Struct received from request for starting password reset
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub username: String,
+}
+
+/// Struct received from request for finishing password reset
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    #[validate(length(min = "MIN_PASSWORD_LENGTH"))]
+    pub new_password: String,
+}
+
+impl PasswordReset {
+    /// Issues single-use reset token for user and delivers it through provided notifier.
+    /// Succeeds even if user does not exist or token could not be delivered, so existence
+    /// of usernames is not revealed, delivery failures are only logged.
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn request(
+        connection: &PgConnection,
+        notifier: &dyn Notifier,
+        username: &str,
+    ) -> Result<(), ShopError> {
+        let user = match User::get_by_username(connection, username) {
+            Ok(user) => user,
+            Err(ShopError::NotFoundError(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if Bot::is_bot(connection, &user.id)? {
+            // bots have no password and nobody would receive the token
+            return Ok(());
+        }
+        let lifetime = dotenv::var("PASSWORD_RESET_LIFETIME_IN_SECONDS")
+            .unwrap_or_else(|_| "3600".into())
+            .parse()?;
+        let expires_at = Utc::now().naive_utc() + Duration::seconds(lifetime);
+        let reset_token = token::generate();
+        diesel::insert_into(password_resets::table)
+            .values((
+                password_resets::user_id.eq(&user.id),
+                password_resets::token_hash.eq(token::hash(&reset_token)),
+                password_resets::expires_at.eq(expires_at),
+            ))
+            .execute(connection)?;
+        let settings = NotificationSettings::get(connection, &user.id)?;
+        let delivered = notifier.notify(&Notification {
+            user_id: user.id,
+            username: user.username,
+            subject: "Password reset".to_string(),
+            body: format!(
+                "Use this token to reset your password: {}\nIt can be used once, until {} UTC.",
+                reset_token, expires_at
+            ),
+            // only confirmed address, see NotificationSettings::change_email
+            email: settings.email,
+            push_token: settings.push_token,
+        });
+        if let Err(e) = delivered {
+            println!("Couldn't deliver password reset token: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Redeems reset token and sets new password, revoking all sessions of user
+    /// # Returns
+    /// ## On success
+    /// * User with changed password: [User]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn redeem(
+        connection: &PgConnection,
+        reset_token: &str,
+        new_password: &str,
+    ) -> Result<User, ShopError> {
+        connection.transaction(|| {
+            let now = Utc::now().naive_utc();
+            let reset = password_resets::table
+                .filter(password_resets::token_hash.eq(token::hash(reset_token)))
+                .filter(password_resets::used_at.is_null())
+                .filter(password_resets::expires_at.gt(now))
+                .for_update()
+                .first::<PasswordReset>(connection)
+                .optional()?
+                .ok_or_else(|| {
+                    ShopError::NoPermission("Invalid or expired reset token".to_string())
+                })?;
+            // every outstanding token of user is invalidated, not only redeemed one
+            diesel::update(
+                password_resets::table
+                    .filter(password_resets::user_id.eq(&reset.user_id))
+                    .filter(password_resets::used_at.is_null()),
+            )
+            .set(password_resets::used_at.eq(now))
+            .execute(connection)?;
+            let user = User::get_by_id(connection, &reset.user_id)?;
+            user.set_password(connection, new_password)
+        })
+    }
+}
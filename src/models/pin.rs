@@ -0,0 +1,236 @@
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{messages, pins, users};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Most messages pinned in single group
+pub const MAX_PINS: i64 = 50;
+
+/// Struct for representing pinned message
+#[derive(Debug, Queryable, Serialize)]
+pub struct Pin {
+    pub id: String,
+    pub group_id: String,
+    pub message_id: String,
+    pub pinned_by: Option<String>,
+    pub pinned_at: NaiveDateTime,
+}
+
+/// Struct received from request for pinning message
+#[derive(Debug, Deserialize)]
+pub struct NewPin {
+    pub message_id: String,
+}
+
+/// Pinned message shown in list of pins, newest pin first
+#[derive(Debug, Serialize)]
+pub struct PinnedMessage {
+    pub message_id: String,
+    /// [None] for messages posted by integrations or deleted users
+    pub sender_id: Option<String>,
+    pub sender_username: Option<String>,
+    pub body: String,
+    pub sent_at: NaiveDateTime,
+    pub pinned_by: Option<String>,
+    pub pinned_by_username: Option<String>,
+    pub pinned_at: NaiveDateTime,
+}
+
+impl Pin {
+    /// Pins message of group
+    /// # Returns
+    /// ## On success
+    /// * pin: [Pin]
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] when message is not in group or it was
+    ///   deleted, [ShopError::AlreadyExistsError] when it is pinned already and
+    ///   [ShopError::Conflict] when group has [MAX_PINS] pins
+    pub fn create(
+        connection: &PgConnection,
+        group_id: &str,
+        message_id: &str,
+        user_id: &str,
+    ) -> Result<Pin, ShopError> {
+        connection.transaction(|| {
+            let exists = messages::table
+                .filter(messages::id.eq(message_id))
+                .filter(messages::group_id.eq(group_id))
+                .filter(messages::body.is_not_null())
+                .count()
+                .get_result::<i64>(connection)?;
+            let pinned = pins::table
+                .filter(pins::group_id.eq(group_id))
+                .select(pins::message_id)
+                .load::<String>(connection)?;
+            check_pin(exists > 0, &pinned, message_id)?;
+            Ok(diesel::insert_into(pins::table)
+                .values((
+                    pins::group_id.eq(group_id),
+                    pins::message_id.eq(message_id),
+                    pins::pinned_by.eq(user_id),
+                ))
+                .get_result::<Pin>(connection)?)
+        })
+    }
+
+    /// Unpins message of group
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] when message is not pinned
+    pub fn delete(
+        connection: &PgConnection,
+        group_id: &str,
+        message_id: &str,
+    ) -> Result<(), ShopError> {
+        let deleted = diesel::delete(
+            pins::table
+                .filter(pins::group_id.eq(group_id))
+                .filter(pins::message_id.eq(message_id)),
+        )
+        .execute(connection)?;
+        check_unpin(deleted)
+    }
+
+    /// Deletes every pin of group
+    pub fn delete_by_group(connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        diesel::delete(pins::table.filter(pins::group_id.eq(group_id))).execute(connection)?;
+        Ok(())
+    }
+}
+
+/// Checks if message can be pinned
+/// # Arguments
+/// * in_group - message exists in the group and was not deleted
+/// * pinned - ids of messages pinned in the group
+fn check_pin(in_group: bool, pinned: &[String], message_id: &str) -> Result<(), ShopError> {
+    if !in_group {
+        return Err(ShopError::NotFoundError(
+            "Message does not exist in that group".to_string(),
+        ));
+    }
+    if pinned.iter().any(|pinned| pinned == message_id) {
+        return Err(ShopError::AlreadyExistsError);
+    }
+    if pinned.len() as i64 >= MAX_PINS {
+        return Err(ShopError::Conflict(format!(
+            "Group can have at most {} pinned messages, unpin some first",
+            MAX_PINS
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that unpinning removed pin, `deleted` is number of removed rows
+fn check_unpin(deleted: usize) -> Result<(), ShopError> {
+    if deleted == 0 {
+        return Err(ShopError::NotFoundError(
+            "Message is not pinned".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl PinnedMessage {
+    /// Pinned messages of group, newest pin first, deleted messages are left out
+    pub fn list(
+        connection: &PgConnection,
+        group_id: &str,
+    ) -> Result<Vec<PinnedMessage>, ShopError> {
+        let rows = pins::table
+            .inner_join(messages::table.left_join(users::table))
+            .filter(pins::group_id.eq(group_id))
+            .filter(messages::body.is_not_null())
+            .order(pins::pinned_at.desc())
+            .select((
+                pins::message_id,
+                messages::sender_id,
+                users::username.nullable(),
+                messages::body,
+                messages::created_at,
+                pins::pinned_by,
+                pins::pinned_at,
+            ))
+            .load::<(
+                String,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                NaiveDateTime,
+                Option<String>,
+                NaiveDateTime,
+            )>(connection)?;
+        let pinned_by = rows
+            .iter()
+            .filter_map(|row| row.5.clone())
+            .collect::<Vec<_>>();
+        let usernames: HashMap<String, String> = users::table
+            .filter(users::id.eq_any(&pinned_by))
+            .select((users::id, users::username))
+            .load::<(String, String)>(connection)?
+            .into_iter()
+            .collect();
+        Ok(rows
+            .into_iter()
+            .map(
+                |(message_id, sender_id, sender_username, body, sent_at, pinned_by, pinned_at)| {
+                    PinnedMessage {
+                        message_id,
+                        sender_id,
+                        sender_username,
+                        body: body.unwrap_or_default(),
+                        sent_at,
+                        pinned_by_username: pinned_by
+                            .as_ref()
+                            .and_then(|id| usernames.get(id).cloned()),
+                        pinned_by,
+                        pinned_at,
+                    }
+                },
+            )
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    fn pinned(count: i64) -> Vec<String> {
+        (0..count).map(|i| format!("message-{}", i)).collect()
+    }
+
+    #[test]
+    fn test_pin() {
+        assert!(check_pin(true, &pinned(3), "new").is_ok());
+        assert!(matches!(
+            check_pin(true, &pinned(3), "message-1"),
+            Err(ShopError::AlreadyExistsError)
+        ));
+    }
+
+    #[test]
+    fn test_pin_of_other_group_is_not_found() {
+        assert!(matches!(
+            check_pin(false, &pinned(0), "new"),
+            Err(ShopError::NotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn test_pins_are_capped() {
+        assert!(check_pin(true, &pinned(MAX_PINS - 1), "new").is_ok());
+        let error = check_pin(true, &pinned(MAX_PINS), "new").unwrap_err();
+        assert!(matches!(error, ShopError::Conflict(_)));
+        assert_eq!(error.status_code(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_unpin() {
+        assert!(check_unpin(1).is_ok());
+        assert!(matches!(check_unpin(0), Err(ShopError::NotFoundError(_))));
+    }
+}
@@ -0,0 +1,154 @@
+use super::user::User;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{profiles, users};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::ValidationError;
+
+pub const MAX_DISPLAY_NAME_LENGTH: u64 = 64;
+pub const MAX_BIO_LENGTH: u64 = 1000;
+
+/// Struct for representing optional profile data of [User]
+#[derive(Debug, Queryable)]
+pub struct Profile {
+    pub user_id: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub status_text: Option<String>,
+    pub status_emoji: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Public view of [User] together with his profile
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub id: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub status_text: Option<String>,
+    pub status_emoji: Option<String>,
+}
+
+impl UserProfile {
+    /// Get [UserProfile] of user with provided id
+    /// # Returns
+    /// ## On success
+    /// * user with profile data: [UserProfile]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn get(connection: &PgConnection, user_id: &str) -> Result<Self, ShopError> {
+        let user = users::table
+            .filter(users::id.eq(user_id))
+            .first::<User>(connection)?;
+        let profile = profiles::table
+            .find(user_id)
+            .first::<Profile>(connection)
+            .optional()?;
+        Ok(UserProfile::from_parts(user, profile))
+    }
+
+    fn from_parts(user: User, profile: Option<Profile>) -> Self {
+        match profile {
+            Some(profile) => UserProfile {
+                id: user.id,
+                username: user.username,
+                display_name: profile.display_name,
+                avatar_url: profile.avatar_url,
+                bio: profile.bio,
+                status_text: profile.status_text,
+                status_emoji: profile.status_emoji,
+            },
+            None => UserProfile {
+                id: user.id,
+                username: user.username,
+                display_name: None,
+                avatar_url: None,
+                bio: None,
+                status_text: None,
+                status_emoji: None,
+            },
+        }
+    }
+}
+
+/// Struct received from request for updating profile.
+/// Missing fields are left unchanged, empty strings clear the field.
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct ProfileUpdate {
+    #[validate(length(max = "MAX_DISPLAY_NAME_LENGTH"))]
+    pub display_name: Option<String>,
+    #[validate(length(max = 512), custom = "validate_avatar_url")]
+    pub avatar_url: Option<String>,
+    #[validate(length(max = "MAX_BIO_LENGTH"))]
+    pub bio: Option<String>,
+    #[validate(length(max = 128))]
+    pub status_text: Option<String>,
+    #[validate(length(max = 32))]
+    pub status_emoji: Option<String>,
+}
+
+/// Avatar must be empty (for clearing it) or http(s) url
+fn validate_avatar_url(url: &str) -> Result<(), ValidationError> {
+    if url.is_empty() || url.starts_with("https://") || url.starts_with("http://") {
+        return Ok(());
+    }
+    Err(ValidationError::new("avatar_url"))
+}
+
+/// Struct for updating profile row in database
+#[derive(AsChangeset)]
+#[table_name = "profiles"]
+struct ProfileChangeset {
+    display_name: Option<Option<String>>,
+    avatar_url: Option<Option<String>>,
+    bio: Option<Option<String>>,
+    status_text: Option<Option<String>>,
+    status_emoji: Option<Option<String>>,
+    updated_at: NaiveDateTime,
+}
+
+/// Empty string means that field should be cleared
+fn clearable(value: Option<String>) -> Option<Option<String>> {
+    value.map(|value| {
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    })
+}
+
+impl ProfileUpdate {
+    /// Applies update to profile of user with provided id, creating profile if needed
+    /// # Returns
+    /// ## On success
+    /// * updated user with profile data: [UserProfile]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn apply(self, connection: &PgConnection, user_id: &str) -> Result<UserProfile, ShopError> {
+        let changeset = ProfileChangeset {
+            display_name: clearable(self.display_name),
+            avatar_url: clearable(self.avatar_url),
+            bio: clearable(self.bio),
+            status_text: clearable(self.status_text),
+            status_emoji: clearable(self.status_emoji),
+            updated_at: Utc::now().naive_utc(),
+        };
+        connection.transaction::<_, ShopError, _>(|| {
+            diesel::insert_into(profiles::table)
+                .values(profiles::user_id.eq(user_id))
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+            diesel::update(profiles::table.find(user_id))
+                .set(&changeset)
+                .execute(connection)?;
+            Ok(())
+        })?;
+        UserProfile::get(connection, user_id)
+    }
+}
@@ -0,0 +1,341 @@
+//! Replay of events missed by clients which reconnected
+//!
+//! Every event broadcast to room, which is not ephemeral, gets next sequence number of
+//! room from [Group::next_seq]. Lobby keeps latest of them in [ReplayBuffer] of each room,
+//! and client reconnecting with `last_seq` receives events it missed, either from buffer
+//! or, when buffer does not reach far enough, from messages stored in database.
+//! Client connecting without `last_seq` receives messages it missed since it was last
+//! connected to room, see [GroupMember::last_seen_seq](super::member::GroupMember::last_seen_seq).
+use super::chat_message::ChatMessage;
+use super::events::{ChatEvent, Sender};
+use super::integration::{Attachment, Integration};
+use super::profile::UserProfile;
+use crate::errors::ShopError;
+use diesel::PgConnection;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Limits of replayed events, read from .env file
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayLimits {
+    /// Events kept in memory for every room
+    pub buffer_size: usize,
+    /// Most events replayed to client reconnecting with `last_seq`, older ones are skipped
+    pub max_replayed: usize,
+    /// Most events replayed to client connecting after it was offline
+    pub max_offline_replayed: usize,
+    /// Buffer of room nobody is connected to is dropped after this time without events
+    pub idle_timeout: Duration,
+}
+
+impl ReplayLimits {
+    /// Reads limits from `WS_REPLAY_BUFFER_SIZE` (default 256), `WS_MAX_REPLAYED_EVENTS`
+    /// (1000), `WS_MAX_OFFLINE_REPLAYED_EVENTS` (50) and `WS_REPLAY_IDLE_TIMEOUT_IN_SECONDS`
+    /// (300)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: usize| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        ReplayLimits {
+            buffer_size: read("WS_REPLAY_BUFFER_SIZE", 256),
+            max_replayed: read("WS_MAX_REPLAYED_EVENTS", 1000).max(1),
+            max_offline_replayed: read("WS_MAX_OFFLINE_REPLAYED_EVENTS", 50),
+            idle_timeout: Duration::from_secs(read("WS_REPLAY_IDLE_TIMEOUT_IN_SECONDS", 300) as u64),
+        }
+    }
+}
+
+/// Serialized event kept in [ReplayBuffer]
+#[derive(Debug)]
+struct Buffered {
+    seq: i64,
+    /// Id of message announced by event
+    message_id: Option<String>,
+    /// [None] once the message was deleted, so it is not replayed anymore
+    json: Option<String>,
+}
+
+/// Latest serialized events of room together with their sequence numbers
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    events: VecDeque<Buffered>,
+    /// When the last event was pushed
+    updated: Instant,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize, now: Instant) -> Self {
+        ReplayBuffer {
+            capacity,
+            events: VecDeque::new(),
+            updated: now,
+        }
+    }
+
+    /// True when no event was pushed for `timeout`
+    pub fn is_idle(&self, timeout: Duration, now: Instant) -> bool {
+        now.saturating_duration_since(self.updated) >= timeout
+    }
+
+    /// Remembers event, forgetting the oldest one when buffer is full
+    pub fn push(&mut self, seq: i64, message_id: Option<&str>, json: String, now: Instant) {
+        self.updated = now;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(Buffered {
+            seq,
+            message_id: message_id.map(str::to_string),
+            json: Some(json),
+        });
+    }
+
+    /// Drops content of events announcing deleted messages, their sequence numbers are
+    /// kept so the rest of buffer can still be replayed
+    pub fn tombstone(&mut self, message_ids: &[String]) {
+        for event in &mut self.events {
+            if matches!(&event.message_id, Some(id) if message_ids.contains(id)) {
+                event.json = None;
+            }
+        }
+    }
+
+    /// Events after `last_seq` up to `current` sequence number of room, deleted messages
+    /// are left out
+    /// # Returns
+    /// * events in order: [Vec] of [String], or [None] when buffer does not hold all of them
+    pub fn since(&self, last_seq: i64, current: i64) -> Option<Vec<String>> {
+        if last_seq >= current {
+            return Some(Vec::new());
+        }
+        let missed = self
+            .events
+            .iter()
+            .skip_while(|event| event.seq <= last_seq)
+            .collect::<Vec<_>>();
+        let contiguous = missed
+            .iter()
+            .enumerate()
+            .all(|(i, event)| event.seq == last_seq + 1 + i as i64);
+        if !contiguous || missed.last().map(|event| event.seq) != Some(current) {
+            return None;
+        }
+        Some(
+            missed
+                .into_iter()
+                .filter_map(|event| event.json.clone())
+                .collect(),
+        )
+    }
+}
+
+/// Events replayed to reconnected client
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub events: Vec<String>,
+    /// False when some events could not be replayed (e.g. topic changes are not stored,
+    /// or there were more than `max` of them)
+    pub complete: bool,
+    /// Events after `seen_until` up to `skipped_until` were left out because of `max`, so
+    /// they must not be taken as received. Client got the rest of missed events, both are
+    /// current sequence number of room when nothing was left out.
+    pub seen_until: i64,
+    pub skipped_until: i64,
+}
+
+/// Loads at most `max` events missed by client from buffer of room, or from stored messages
+/// # Returns
+/// ## On success
+/// * events to replay: [Replay]
+/// ## On faliure
+/// * error: [ShopError]
+pub fn missed_events(
+    connection: &PgConnection,
+    buffer: Option<&ReplayBuffer>,
+    group_id: &str,
+    last_seq: i64,
+    current: i64,
+    max: usize,
+) -> Result<Replay, ShopError> {
+    if last_seq > current {
+        return Ok(Replay {
+            seen_until: current,
+            skipped_until: current,
+            ..Replay::default()
+        });
+    }
+    if let Some(events) = buffer.and_then(|buffer| buffer.since(last_seq, current)) {
+        if events.len() <= max {
+            return Ok(Replay {
+                events,
+                complete: true,
+                seen_until: current,
+                skipped_until: current,
+            });
+        }
+    }
+    let stored = ChatMessage::since_seq(connection, group_id, last_seq, max as i64)?;
+    let seqs = stored.iter().map(|message| message.seq).collect::<Vec<_>>();
+    let complete = seqs.iter().copied().eq((last_seq + 1..=current).map(Some));
+    let (seen_until, skipped_until) = skipped(&seqs, max, last_seq, current);
+    let mut replay = replay_stored(connection, stored, complete);
+    replay.seen_until = seen_until;
+    replay.skipped_until = skipped_until;
+    Ok(replay)
+}
+
+/// Range of events skipped before the latest stored messages with `seqs`, which were
+/// loaded with limit `max`
+/// # Returns
+/// * skipped events are after first and up to second number: ([i64], [i64])
+fn skipped(seqs: &[Option<i64>], max: usize, last_seq: i64, current: i64) -> (i64, i64) {
+    if seqs.len() < max {
+        return (current, current);
+    }
+    let first = seqs.iter().find_map(|seq| *seq).unwrap_or(current + 1);
+    if first <= last_seq + 1 {
+        return (current, current);
+    }
+    (last_seq, first - 1)
+}
+
+/// Turns stored messages back into events, tombstones and messages whose sender cannot be
+/// loaded are skipped and make replay incomplete
+fn replay_stored(
+    connection: &PgConnection,
+    stored: Vec<ChatMessage>,
+    mut complete: bool,
+) -> Replay {
+    let mut senders: HashMap<String, Option<Sender>> = HashMap::new();
+    let mut events = Vec::with_capacity(stored.len());
+    for message in stored {
+        let seq = match message.seq {
+            Some(seq) => seq,
+            None => continue,
+        };
+        match stored_event(connection, &mut senders, message) {
+            Some(event) => events.push(event.to_json_with_seq(seq)),
+            None => complete = false,
+        }
+    }
+    Replay {
+        events,
+        complete,
+        ..Replay::default()
+    }
+}
+
+/// Event which announced stored message
+fn stored_event(
+    connection: &PgConnection,
+    senders: &mut HashMap<String, Option<Sender>>,
+    message: ChatMessage,
+) -> Option<ChatEvent> {
+    let body = message.body?;
+    if let Some(integration_id) = &message.integration_id {
+        let integration = Integration::get_including_deleted(connection, integration_id).ok()?;
+        let attachments = message
+            .attachments
+            .and_then(|attachments| serde_json::from_str::<Vec<Attachment>>(&attachments).ok())
+            .unwrap_or_default();
+        return Some(ChatEvent::Integration {
+            id: message.id,
+            integration: (&integration).into(),
+            body,
+            attachments,
+            sent_at: message.created_at,
+        });
+    }
+    let sender_id = message.sender_id?;
+    let sender = senders
+        .entry(sender_id)
+        .or_insert_with_key(|id| UserProfile::get(connection, id).ok().map(Sender::from))
+        .clone()?;
+    Some(match body.strip_prefix("/me ") {
+        Some(action) => ChatEvent::Action {
+            id: message.id,
+            sender,
+            body: action.to_string(),
+            sent_at: message.created_at,
+        },
+        None => ChatEvent::Message {
+            id: message.id,
+            sender,
+            body,
+            sent_at: message.created_at,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(seqs: &[i64]) -> ReplayBuffer {
+        let now = Instant::now();
+        let mut buffer = ReplayBuffer::new(3, now);
+        for seq in seqs {
+            let message_id = format!("message-{}", seq);
+            buffer.push(*seq, Some(&message_id), seq.to_string(), now);
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_buffer_becomes_idle() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut buffer = ReplayBuffer::new(3, now);
+        assert!(!buffer.is_idle(timeout, now + Duration::from_secs(30)));
+        buffer.push(1, None, "1".to_string(), now + Duration::from_secs(30));
+        assert!(!buffer.is_idle(timeout, now + Duration::from_secs(60)));
+        assert!(buffer.is_idle(timeout, now + Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn test_buffer_since() {
+        let buffer = buffer(&[1, 2, 3, 4, 5]);
+        assert_eq!(
+            buffer.since(3, 5),
+            Some(vec!["4".to_string(), "5".to_string()])
+        );
+        assert_eq!(buffer.since(2, 5).map(|events| events.len()), Some(3));
+        assert_eq!(buffer.since(5, 5), Some(Vec::new()));
+        assert_eq!(buffer.since(1, 5), None);
+        assert_eq!(buffer.since(4, 6), None);
+    }
+
+    #[test]
+    fn test_buffer_with_gap() {
+        let buffer = buffer(&[1, 2, 4]);
+        assert_eq!(buffer.since(2, 4), None);
+        assert_eq!(buffer.since(3, 4), Some(vec!["4".to_string()]));
+    }
+
+    #[test]
+    fn test_replay_cut_short_by_limit() {
+        assert_eq!(skipped(&[Some(4), Some(5)], 3, 3, 7), (7, 7));
+        assert_eq!(skipped(&[Some(4), Some(5), Some(6)], 3, 3, 6), (6, 6));
+        assert_eq!(skipped(&[Some(8), Some(9)], 2, 3, 9), (3, 7));
+        assert_eq!(skipped(&[], 0, 3, 9), (3, 9));
+        assert_eq!(skipped(&[], 0, 9, 9), (9, 9));
+    }
+
+    #[test]
+    fn test_deleted_messages_are_not_replayed() {
+        let mut buffer = buffer(&[1, 2, 3]);
+        buffer.tombstone(&["message-2".to_string(), "unknown".to_string()]);
+        assert_eq!(
+            buffer.since(0, 3),
+            Some(vec!["1".to_string(), "3".to_string()])
+        );
+        assert_eq!(buffer.since(1, 3), Some(vec!["3".to_string()]));
+    }
+}
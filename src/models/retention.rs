@@ -0,0 +1,383 @@
+//! Retention policies of groups and purging of messages they expire
+//!
+//! Expired messages are replaced with tombstones by [purge_expired], which is run
+//! periodically by [crate::retention] job, and every purged message is recorded in
+//! `message_purges` table.
+use super::pagination::{Page, Pagination};
+use super::webhook::WebhookDelivery;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{groups, mentions, message_purges, messages, notification_outbox, pins};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Longest retention of messages, ten years
+pub const MAX_RETENTION_DAYS: i32 = 3650;
+/// Longest lifetime of read ephemeral message, one week
+pub const MAX_EPHEMERAL_SECONDS: i32 = 7 * 24 * 60 * 60;
+
+/// How long messages of group are kept, also received from request for changing it
+/// ```
+/// { "mode": "forever" }
+/// { "mode": "days", "days": 30 }
+/// { "mode": "ephemeral", "seconds": 60 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum RetentionPolicy {
+    Forever,
+    /// Messages are purged `days` after they were sent
+    Days {
+        days: i32,
+    },
+    /// Messages are purged `seconds` after they were first read by someone else than sender,
+    /// i.e. delivered to him live or after reconnecting
+    Ephemeral {
+        seconds: i32,
+    },
+}
+
+impl RetentionPolicy {
+    /// Policy stored in `groups.retention_mode` and `groups.retention_value`
+    fn from_columns(mode: &str, value: i32) -> Result<Self, ShopError> {
+        match mode {
+            "forever" => Ok(RetentionPolicy::Forever),
+            "days" => Ok(RetentionPolicy::Days { days: value }),
+            "ephemeral" => Ok(RetentionPolicy::Ephemeral { seconds: value }),
+            _ => Err(ShopError::ParseError(format!(
+                "Unknown retention mode: {}",
+                mode
+            ))),
+        }
+    }
+
+    /// Lifetime of read messages, when group is ephemeral
+    pub fn ephemeral_lifetime(&self) -> Option<Duration> {
+        match self {
+            RetentionPolicy::Ephemeral { seconds } => Some(Duration::seconds(*seconds as i64)),
+            _ => None,
+        }
+    }
+
+    fn columns(&self) -> (&'static str, i32) {
+        match self {
+            RetentionPolicy::Forever => ("forever", 0),
+            RetentionPolicy::Days { days } => ("days", *days),
+            RetentionPolicy::Ephemeral { seconds } => ("ephemeral", *seconds),
+        }
+    }
+
+    /// Checks that lifetime of messages is within limits
+    pub fn validate(&self) -> Result<(), ShopError> {
+        match self {
+            RetentionPolicy::Days { days } if !(1..=MAX_RETENTION_DAYS).contains(days) => {
+                Err(ShopError::ValidationErrors(format!(
+                    "days: must be from 1 to {}",
+                    MAX_RETENTION_DAYS
+                )))
+            }
+            RetentionPolicy::Ephemeral { seconds }
+                if !(1..=MAX_EPHEMERAL_SECONDS).contains(seconds) =>
+            {
+                Err(ShopError::ValidationErrors(format!(
+                    "seconds: must be from 1 to {}",
+                    MAX_EPHEMERAL_SECONDS
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get retention policy of group
+    pub fn get(connection: &PgConnection, group_id: &str) -> Result<RetentionPolicy, ShopError> {
+        let (mode, value) = groups::table
+            .find(group_id)
+            .select((groups::retention_mode, groups::retention_value))
+            .first::<(String, i32)>(connection)?;
+        RetentionPolicy::from_columns(&mode, value)
+    }
+
+    /// Changes retention policy of group. Read messages keep their expiry only while group
+    /// stays ephemeral, messages read before group became ephemeral do not expire.
+    pub fn set(&self, connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        let (mode, value) = self.columns();
+        connection.transaction(|| {
+            diesel::update(groups::table.find(group_id))
+                .set((
+                    groups::retention_mode.eq(mode),
+                    groups::retention_value.eq(value),
+                ))
+                .execute(connection)?;
+            if !matches!(self, RetentionPolicy::Ephemeral { .. }) {
+                diesel::update(
+                    messages::table
+                        .filter(messages::group_id.eq(group_id))
+                        .filter(messages::expires_at.is_not_null()),
+                )
+                .set(messages::expires_at.eq(None::<NaiveDateTime>))
+                .execute(connection)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Starts expiry of message of ephemeral group with read messages living for `lifetime`,
+/// which was delivered live to someone else than its sender
+pub fn message_read(
+    connection: &PgConnection,
+    message_id: &str,
+    lifetime: Duration,
+) -> Result<(), ShopError> {
+    diesel::update(
+        messages::table
+            .filter(messages::id.eq(message_id))
+            .filter(messages::expires_at.is_null()),
+    )
+    .set(messages::expires_at.eq(Utc::now().naive_utc() + lifetime))
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Starts expiry of messages of group with sequence number in `after..=up_to` which member
+/// `reader_id` received and did not send himself
+pub fn messages_read(
+    connection: &PgConnection,
+    group_id: &str,
+    reader_id: &str,
+    after: i64,
+    up_to: i64,
+) -> Result<(), ShopError> {
+    if after >= up_to {
+        return Ok(());
+    }
+    if let Some(lifetime) = RetentionPolicy::get(connection, group_id)?.ephemeral_lifetime() {
+        diesel::update(
+            messages::table
+                .filter(messages::group_id.eq(group_id))
+                .filter(messages::seq.gt(after))
+                .filter(messages::seq.le(up_to))
+                .filter(messages::expires_at.is_null())
+                .filter(messages::body.is_not_null())
+                .filter(
+                    messages::sender_id
+                        .ne(reader_id)
+                        .or(messages::sender_id.is_null()),
+                ),
+        )
+        .set(messages::expires_at.eq(Utc::now().naive_utc() + lifetime))
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Why message was purged, stored in `message_purges.reason`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PurgeReason {
+    /// Message was older than [RetentionPolicy::Days] allow
+    Retention,
+    /// Message was read in [RetentionPolicy::Ephemeral] group
+    Ephemeral,
+}
+
+impl PurgeReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PurgeReason::Retention => "retention",
+            PurgeReason::Ephemeral => "ephemeral",
+        }
+    }
+}
+
+/// Messages of single group purged together
+#[derive(Debug)]
+pub struct Purged {
+    pub group_id: String,
+    pub message_ids: Vec<String>,
+}
+
+/// Purges at most `limit` messages whose group policy expired them, together with their
+/// mentions, pins and queued notifications
+/// # Returns
+/// ## On success
+/// * purged messages, grouped by group: [Vec] of [Purged]
+/// ## On faliure
+/// * error: [ShopError]
+pub fn purge_expired(connection: &PgConnection, limit: i64) -> Result<Vec<Purged>, ShopError> {
+    let now = Utc::now().naive_utc();
+    let mut expired: Vec<(String, String, NaiveDateTime, PurgeReason)> = messages::table
+        .filter(messages::expires_at.le(now))
+        .filter(messages::body.is_not_null())
+        .order(messages::expires_at.asc())
+        .limit(limit)
+        .select((messages::id, messages::group_id, messages::created_at))
+        .load::<(String, String, NaiveDateTime)>(connection)?
+        .into_iter()
+        .map(|(id, group_id, sent_at)| (id, group_id, sent_at, PurgeReason::Ephemeral))
+        .collect();
+    let kept_for_days = groups::table
+        .filter(groups::retention_mode.eq("days"))
+        .select((groups::id, groups::retention_value))
+        .load::<(String, i32)>(connection)?;
+    for (group_id, days) in kept_for_days {
+        let remaining = limit - expired.len() as i64;
+        if remaining <= 0 {
+            break;
+        }
+        let old = messages::table
+            .filter(messages::group_id.eq(&group_id))
+            .filter(messages::created_at.lt(now - Duration::days(days as i64)))
+            .filter(messages::body.is_not_null())
+            .order(messages::created_at.asc())
+            .limit(remaining)
+            .select((messages::id, messages::created_at))
+            .load::<(String, NaiveDateTime)>(connection)?;
+        expired.extend(
+            old.into_iter()
+                .map(|(id, sent_at)| (id, group_id.clone(), sent_at, PurgeReason::Retention)),
+        );
+    }
+    let mut by_group: BTreeMap<String, Vec<(String, NaiveDateTime, PurgeReason)>> = BTreeMap::new();
+    for (id, group_id, sent_at, reason) in expired {
+        by_group
+            .entry(group_id)
+            .or_default()
+            .push((id, sent_at, reason));
+    }
+    by_group
+        .into_iter()
+        .map(|(group_id, expired)| {
+            purge(connection, &group_id, &expired)?;
+            Ok(Purged {
+                group_id,
+                message_ids: expired.into_iter().map(|(id, _, _)| id).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Replaces messages of group with tombstones, removes their content from webhook
+/// deliveries and records them
+fn purge(
+    connection: &PgConnection,
+    group_id: &str,
+    expired: &[(String, NaiveDateTime, PurgeReason)],
+) -> Result<(), ShopError> {
+    let ids = expired
+        .iter()
+        .map(|(id, _, _)| id.as_str())
+        .collect::<Vec<_>>();
+    connection.transaction(|| {
+        WebhookDelivery::purge_messages(connection, &ids)?;
+        diesel::delete(mentions::table.filter(mentions::message_id.eq_any(&ids)))
+            .execute(connection)?;
+        diesel::delete(pins::table.filter(pins::message_id.eq_any(&ids))).execute(connection)?;
+        diesel::delete(
+            notification_outbox::table.filter(notification_outbox::message_id.eq_any(&ids)),
+        )
+        .execute(connection)?;
+        diesel::update(messages::table.filter(messages::id.eq_any(&ids)))
+            .set((
+                messages::body.eq(None::<String>),
+                messages::attachments.eq(None::<String>),
+                messages::deleted_at.eq(Utc::now().naive_utc()),
+                messages::expires_at.eq(None::<NaiveDateTime>),
+            ))
+            .execute(connection)?;
+        let records = expired
+            .iter()
+            .map(|(id, sent_at, reason)| {
+                (
+                    message_purges::group_id.eq(group_id),
+                    message_purges::message_id.eq(id),
+                    message_purges::reason.eq(reason.as_str()),
+                    message_purges::sent_at.eq(sent_at),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(message_purges::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+        Ok(())
+    })
+}
+
+/// Record of purged message, content of message is not kept
+#[derive(Debug, Queryable, Serialize)]
+pub struct PurgeRecord {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub group_id: String,
+    pub message_id: String,
+    pub reason: String,
+    pub sent_at: NaiveDateTime,
+    pub purged_at: NaiveDateTime,
+}
+
+impl PurgeRecord {
+    /// Purged messages of group, most recently purged first
+    pub fn list(
+        connection: &PgConnection,
+        group_id: &str,
+        pagination: &Pagination,
+    ) -> Result<Page<PurgeRecord>, ShopError> {
+        let total = message_purges::table
+            .filter(message_purges::group_id.eq(group_id))
+            .count()
+            .get_result::<i64>(connection)?;
+        let items = message_purges::table
+            .filter(message_purges::group_id.eq(group_id))
+            .order(message_purges::purged_at.desc())
+            .limit(pagination.per_page())
+            .offset(pagination.offset())
+            .load::<PurgeRecord>(connection)?;
+        Ok(Page::new(pagination, total, items))
+    }
+
+    /// Deletes every record of group
+    pub fn delete_by_group(connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        diesel::delete(message_purges::table.filter(message_purges::group_id.eq(group_id)))
+            .execute(connection)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_columns() {
+        for policy in [
+            RetentionPolicy::Forever,
+            RetentionPolicy::Days { days: 30 },
+            RetentionPolicy::Ephemeral { seconds: 60 },
+        ] {
+            let (mode, value) = policy.columns();
+            assert_eq!(RetentionPolicy::from_columns(mode, value).unwrap(), policy);
+        }
+        assert_eq!(
+            serde_json::to_string(&RetentionPolicy::Days { days: 30 }).unwrap(),
+            r#"{"mode":"days","days":30}"#
+        );
+    }
+
+    #[test]
+    fn test_policy_limits() {
+        assert!(RetentionPolicy::Forever.validate().is_ok());
+        assert!(RetentionPolicy::Days { days: 0 }.validate().is_err());
+        assert!(RetentionPolicy::Days {
+            days: MAX_RETENTION_DAYS
+        }
+        .validate()
+        .is_ok());
+        assert!(RetentionPolicy::Ephemeral {
+            seconds: MAX_EPHEMERAL_SECONDS + 1
+        }
+        .validate()
+        .is_err());
+    }
+}
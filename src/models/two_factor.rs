@@ -0,0 +1,223 @@
+use super::user::User;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{recovery_codes, user_totp};
+use crate::totp;
+use crate::utils::token;
+use chrono::{NaiveDateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Number of recovery codes issued on enrollment
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Characters of recovery codes, without easily confused ones
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Struct for representing TOTP secret of user, active once `enabled_at` is set
+#[derive(Debug, Queryable)]
+pub struct UserTotp {
+    pub user_id: String,
+    pub secret: String,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Struct returned on enrollment, secret and recovery codes are shown only once
+#[derive(Debug, Serialize)]
+pub struct Enrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub recovery_codes: Vec<String>,
+}
+
+/// Response to login of user with two-factor authentication enabled
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub pending_token: String,
+}
+
+impl TwoFactorChallenge {
+    pub fn new(pending_token: String) -> Self {
+        TwoFactorChallenge {
+            two_factor_required: true,
+            pending_token,
+        }
+    }
+}
+
+/// Struct received from request containing TOTP or recovery code
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+/// Struct received from request for disabling two-factor authentication
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorDisable {
+    /// Not needed by users without password, see [User::confirm_identity]
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Struct received from request for finishing two-step login
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLogin {
+    pub pending_token: String,
+    pub code: String,
+}
+
+/// Recovery codes are compared without dashes and case
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_lowercase()
+}
+
+/// Generates recovery code in `xxxx-xxxx` format
+fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let mut code: String = (0..8)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+    code.insert(4, '-');
+    code
+}
+
+impl UserTotp {
+    /// Get TOTP secret of user, if he started enrollment
+    pub fn get(connection: &PgConnection, user_id: &str) -> Result<Option<UserTotp>, ShopError> {
+        Ok(user_totp::table
+            .find(user_id)
+            .first::<UserTotp>(connection)
+            .optional()?)
+    }
+
+    /// Check if user has activated two-factor authentication
+    pub fn is_enabled(connection: &PgConnection, user_id: &str) -> Result<bool, ShopError> {
+        Ok(UserTotp::get(connection, user_id)?
+            .map(|totp| totp.enabled_at.is_some())
+            .unwrap_or(false))
+    }
+
+    /// Starts enrollment by generating new secret and recovery codes.
+    /// Two-factor authentication is not required until it is activated with valid code.
+    /// # Returns
+    /// ## On success
+    /// * secret, otpauth uri and recovery codes: [Enrollment]
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::AlreadyExistsError] if it is already enabled
+    pub fn enroll(connection: &PgConnection, user: &User) -> Result<Enrollment, ShopError> {
+        if UserTotp::is_enabled(connection, &user.id)? {
+            return Err(ShopError::AlreadyExistsError);
+        }
+        let secret = totp::generate_secret();
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        connection.transaction::<_, ShopError, _>(|| {
+            diesel::insert_into(user_totp::table)
+                .values((
+                    user_totp::user_id.eq(&user.id),
+                    user_totp::secret.eq(&secret),
+                ))
+                .on_conflict(user_totp::user_id)
+                .do_update()
+                .set((
+                    user_totp::secret.eq(&secret),
+                    user_totp::last_used_step.eq(None::<i64>),
+                    user_totp::created_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(connection)?;
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(&user.id)))
+                .execute(connection)?;
+            let rows: Vec<_> = recovery_codes
+                .iter()
+                .map(|code| {
+                    (
+                        recovery_codes::user_id.eq(&user.id),
+                        recovery_codes::code_hash.eq(token::hash(&normalize_recovery_code(code))),
+                    )
+                })
+                .collect();
+            diesel::insert_into(recovery_codes::table)
+                .values(&rows)
+                .execute(connection)?;
+            Ok(())
+        })?;
+        let issuer = dotenv::var("TOTP_ISSUER").unwrap_or_else(|_| "web_chat".into());
+        Ok(Enrollment {
+            otpauth_uri: totp::otpauth_uri(&issuer, &user.username, &secret),
+            secret,
+            recovery_codes,
+        })
+    }
+
+    /// Activates two-factor authentication, after user proves that he set up his app
+    pub fn activate(connection: &PgConnection, user_id: &str, code: &str) -> Result<(), ShopError> {
+        let pending = UserTotp::get(connection, user_id)?
+            .ok_or_else(|| ShopError::NotFoundError("Enrollment not started".to_string()))?;
+        if pending.enabled_at.is_some() {
+            return Err(ShopError::AlreadyExistsError);
+        }
+        let step = totp::verify(&pending.secret, code, Utc::now().timestamp())
+            .ok_or_else(|| ShopError::NoPermission("Invalid code".to_string()))?;
+        diesel::update(user_totp::table.find(user_id))
+            .set((
+                user_totp::enabled_at.eq(Utc::now().naive_utc()),
+                user_totp::last_used_step.eq(step),
+            ))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    /// Turns off two-factor authentication and removes secret and recovery codes
+    pub fn disable(connection: &PgConnection, user_id: &str) -> Result<(), ShopError> {
+        connection.transaction(|| {
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+                .execute(connection)?;
+            diesel::delete(user_totp::table.find(user_id)).execute(connection)?;
+            Ok(())
+        })
+    }
+
+    /// Verifies TOTP code (each time step is accepted only once) or unused recovery code
+    /// # Returns
+    /// ## On success
+    /// * if code is valid: [bool]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn verify(connection: &PgConnection, user_id: &str, code: &str) -> Result<bool, ShopError> {
+        connection.transaction(|| {
+            let enabled = user_totp::table
+                .find(user_id)
+                .filter(user_totp::enabled_at.is_not_null())
+                .for_update()
+                .first::<UserTotp>(connection)
+                .optional()?;
+            let enabled = match enabled {
+                Some(enabled) => enabled,
+                None => return Ok(false),
+            };
+            if let Some(step) = totp::verify(&enabled.secret, code, Utc::now().timestamp()) {
+                if matches!(enabled.last_used_step, Some(last) if step <= last) {
+                    return Ok(false);
+                }
+                diesel::update(user_totp::table.find(user_id))
+                    .set(user_totp::last_used_step.eq(step))
+                    .execute(connection)?;
+                return Ok(true);
+            }
+            let used = diesel::update(
+                recovery_codes::table
+                    .filter(recovery_codes::user_id.eq(user_id))
+                    .filter(
+                        recovery_codes::code_hash.eq(token::hash(&normalize_recovery_code(code))),
+                    )
+                    .filter(recovery_codes::used_at.is_null()),
+            )
+            .set(recovery_codes::used_at.eq(Utc::now().naive_utc()))
+            .execute(connection)?;
+            Ok(used > 0)
+        })
+    }
+}
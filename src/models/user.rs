@@ -1,13 +1,37 @@
 use crate::diesel::prelude::*;
 use crate::errors::ShopError;
-use crate::jwt::{self, UserClaims};
+use crate::jwt::{self, PendingClaims, UserClaims};
+use crate::models::bot::{ApiKey, ApiScope};
+use crate::models::external_identity::ExternalIdentity;
+use crate::models::group::{Group, GroupRole};
+use crate::models::two_factor::UserTotp;
+use crate::password;
 use crate::schema::{groups_users, users};
-use actix_web::HttpRequest;
-use bcrypt::verify;
+use crate::utils::AppState;
+use actix_web::{web::Data, HttpRequest};
 use serde::{Deserialize, Serialize};
 
 pub const MIN_USERNAME_LENGTH: u8 = 5;
 pub const MIN_PASSWORD_LENGTH: u8 = 8;
+/// Same error is returned for unknown username and wrong password
+pub const INVALID_CREDENTIALS: &str = "Invalid username or password";
+/// Header with API key of bot, accepted in place of jwt by [User::is_logged_with_scope]
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+lazy_static! {
+    static ref DUMMY_HASH: String =
+        password::hash("dummy password").expect("Failed to hash dummy password");
+}
+
+/// Result of successful password check
+#[derive(Debug)]
+pub enum Authentication {
+    /// User is logged in with issued token
+    Complete(User, String),
+    /// User has two-factor authentication enabled, pending token must be exchanged
+    /// together with valid code for real token
+    TwoFactorPending(String),
+}
 
 /// Main struct for manipulating with user data
 #[derive(Queryable, Debug, Deserialize, Serialize)]
@@ -16,6 +40,9 @@ pub struct User {
     pub username: String,
     #[serde(skip_serializing)]
     pub password: String,
+    /// Incremented whenever all issued tokens of user should be revoked
+    #[serde(skip_serializing)]
+    pub token_version: i32,
 }
 
 impl User {
@@ -25,11 +52,13 @@ impl User {
             .select(users::all_columns)
             .filter(users::username.eq(username))
             .first::<Self>(connection)?;
-        Ok(User {
-            id: result.id.clone(),
-            username: result.username.clone(),
-            password: result.password.clone(),
-        })
+        Ok(result)
+    }
+    /// Get [User] by id from database
+    pub fn get_by_id(connection: &PgConnection, id: &str) -> Result<Self, ShopError> {
+        Ok(users::table
+            .filter(users::id.eq(id))
+            .first::<Self>(connection)?)
     }
     /// Get username: [String] of user with provided id: [String]
     pub fn get_username(connection: &PgConnection, id: &str) -> Result<String, ShopError> {
@@ -40,36 +69,75 @@ impl User {
         Ok(username)
     }
     /// Check if username: [String] is available for use
-    fn is_available_username(connection: &PgConnection, username: &str) -> bool {
-        let result = User::get_by_username(connection, username);
-        if result.is_err() {
-            return true;
-        }
-        return false;
+    pub(crate) fn is_available_username(connection: &PgConnection, username: &str) -> bool {
+        User::get_by_username(connection, username).is_err()
     }
-    /// Check if user is registered
+    /// Check if user is registered.
+    /// Password hash made with outdated algorithm or parameters is replaced on success.
+    /// Unknown username and wrong password are not distinguished.
     /// # Returns
     /// ## On success
-    /// * Tuple of registered user and generated token, (user: [User], token: [String])
+    /// * [Authentication::Complete] with user and generated token, or
+    ///   [Authentication::TwoFactorPending] with short-lived pending token
     /// ## On faliure
     /// * error: [ShopError]
     pub fn authenticate(
         connection: &PgConnection,
         username: &str,
         password: &str,
+    ) -> Result<Authentication, ShopError> {
+        let user = match User::get_by_username(connection, username) {
+            Ok(user) => user,
+            Err(ShopError::NotFoundError(_)) => {
+                // same amount of work as for existing user, so timing does not reveal it
+                let _ = password::verify(password, &DUMMY_HASH);
+                return Err(ShopError::NoPermission(INVALID_CREDENTIALS.to_string()));
+            }
+            Err(e) => return Err(e),
+        };
+        if !password::verify(password, &user.password)? {
+            return Err(ShopError::NoPermission(INVALID_CREDENTIALS.to_string()));
+        }
+        if password::needs_rehash(&user.password) {
+            if let Err(e) = user.rehash_password(connection, password) {
+                println!("Couldn't rehash password of {}: {}", user.id, e);
+            }
+        }
+        if UserTotp::is_enabled(connection, &user.id)? {
+            return Ok(Authentication::TwoFactorPending(jwt::generate_pending(
+                &user,
+            )?));
+        }
+        let token = user.generate_jwt()?;
+        Ok(Authentication::Complete(user, token))
+    }
+    /// Second step of login, exchanges verified pending token and TOTP or recovery code
+    /// for real token
+    /// # Returns
+    /// ## On success
+    /// * Tuple of user and generated token, (user: [User], token: [String])
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NoPermission] if code is not valid
+    pub fn complete_two_factor(
+        connection: &PgConnection,
+        claims: &PendingClaims,
+        code: &str,
     ) -> Result<(User, String), ShopError> {
-        let user = User::get_by_username(&connection, &username)?;
-        if !verify(password, &user.password)? {
+        let user = User::get_by_id(connection, &claims.sub)?;
+        if user.token_version != claims.ver {
             return Err(ShopError::NoPermission(
-                "No permission for that action".to_string(),
+                "Session is no longer valid".to_string(),
             ));
         }
+        if !UserTotp::verify(connection, &user.id, code)? {
+            return Err(ShopError::NoPermission("Invalid code".to_string()));
+        }
         let token = user.generate_jwt()?;
         Ok((user, token))
     }
     /// Method for generating token: [String] on current user object
     fn generate_jwt(&self) -> Result<String, ShopError> {
-        crate::jwt::generate(&self)
+        crate::jwt::generate(self)
     }
     /// Function for creating [User] struct from [UserClaims] struct
     pub fn from_jwt(claims: &UserClaims) -> Self {
@@ -77,9 +145,10 @@ impl User {
             id: String::from(&claims.id),
             username: String::from(&claims.username),
             password: String::new(),
+            token_version: claims.ver,
         }
     }
-    ///Check if user is currently logged in
+    ///Check if user is currently logged in and his token is not revoked
     /// # Returns
     /// ## On success
     /// * Currently logged in user: [User]
@@ -90,9 +159,132 @@ impl User {
             Some(jwt) => jwt.to_str()?,
             None => return Err(ShopError::InvalidInput),
         };
-        Ok(jwt::verify(String::from(user_jwt))?)
+        let user = jwt::verify(String::from(user_jwt))?;
+        let state = req
+            .app_data::<Data<AppState>>()
+            .ok_or_else(|| ShopError::ConnectionError("App state is missing".to_string()))?;
+        let connection = state.get_pg_connection()?;
+        let token_version = users::table
+            .select(users::token_version)
+            .filter(users::id.eq(&user.id))
+            .first::<i32>(&connection)
+            .optional()?;
+        if token_version != Some(user.token_version) {
+            return Err(ShopError::NoPermission(
+                "Session is no longer valid".to_string(),
+            ));
+        }
+        Ok(user)
+    }
+    ///Check if request is made by logged in user, or by bot with API key
+    /// # Returns
+    /// ## On success
+    /// * Tuple of user and API key, which is [None] for users logged in with jwt
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn is_logged_or_bot(req: &HttpRequest) -> Result<(User, Option<ApiKey>), ShopError> {
+        let api_key = match req.headers().get(API_KEY_HEADER) {
+            Some(api_key) => api_key.to_str()?,
+            None => return Ok((User::is_logged(req)?, None)),
+        };
+        let state = req
+            .app_data::<Data<AppState>>()
+            .ok_or_else(|| ShopError::ConnectionError("App state is missing".to_string()))?;
+        let connection = state.get_pg_connection()?;
+        let (user, api_key) = ApiKey::authenticate(&connection, api_key)?;
+        Ok((user, Some(api_key)))
     }
-    /// Method on User object, joins self to provided group
+    ///Check if request is made by logged in user, or by bot with API key allowing `scope`.
+    /// Routes not using it are available only with jwt.
+    /// # Returns
+    /// ## On success
+    /// * Currently logged in user or bot: [User]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn is_logged_with_scope(req: &HttpRequest, scope: ApiScope) -> Result<User, ShopError> {
+        let (user, api_key) = User::is_logged_or_bot(req)?;
+        if let Some(api_key) = api_key {
+            api_key.require(scope)?;
+        }
+        Ok(user)
+    }
+    /// Method for confirming identity of current user before sensitive action, by his
+    /// password, or by recent login through single sign-on for users who have no password
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NoPermission] if password does not match or user
+    ///   without password did not log in through single sign-on recently
+    pub fn confirm_identity(
+        &self,
+        connection: &PgConnection,
+        password: Option<&str>,
+    ) -> Result<(), ShopError> {
+        let stored = users::table
+            .select(users::password)
+            .filter(users::id.eq(&self.id))
+            .first::<String>(connection)?;
+        if stored == password::NO_PASSWORD {
+            if ExternalIdentity::recently_authenticated(connection, &self.id)? {
+                return Ok(());
+            }
+            return Err(ShopError::NoPermission(
+                "Log in again with single sign-on to confirm that action".to_string(),
+            ));
+        }
+        match password {
+            Some(password) if password::verify(password, &stored)? => Ok(()),
+            _ => Err(ShopError::NoPermission(
+                "No permission for that action".to_string(),
+            )),
+        }
+    }
+    /// Method for replacing stored hash of current (already verified) password,
+    /// sessions are not revoked
+    fn rehash_password(&self, connection: &PgConnection, password: &str) -> Result<(), ShopError> {
+        diesel::update(users::table.filter(users::id.eq(&self.id)))
+            .set(users::password.eq(password::hash(password)?))
+            .execute(connection)?;
+        Ok(())
+    }
+    /// Method for changing password of current user, revoking all his issued tokens
+    /// # Returns
+    /// ## On success
+    /// * Updated user: [User]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn set_password(
+        &self,
+        connection: &PgConnection,
+        new_password: &str,
+    ) -> Result<User, ShopError> {
+        Ok(diesel::update(users::table.filter(users::id.eq(&self.id)))
+            .set((
+                users::password.eq(password::hash(new_password)?),
+                users::token_version.eq(users::token_version + 1),
+            ))
+            .get_result::<User>(connection)?)
+    }
+    /// Method for changing password of current user after checking old one, users without
+    /// password set it after recent login through single sign-on instead.
+    /// All other sessions are revoked, so new token for current session is returned.
+    /// # Returns
+    /// ## On success
+    /// * Tuple of updated user and new token, (user: [User], token: [String])
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn change_password(
+        &self,
+        connection: &PgConnection,
+        old_password: Option<&str>,
+        new_password: &str,
+    ) -> Result<(User, String), ShopError> {
+        self.confirm_identity(connection, old_password)?;
+        let user = self.set_password(connection, new_password)?;
+        let token = user.generate_jwt()?;
+        Ok((user, token))
+    }
+    /// Method on User object, joins self to provided group with given role,
+    /// messages sent before joining are not counted as missed
     /// # Returns
     /// ## On success
     /// * number of inserted rows: [usize]
@@ -101,20 +293,43 @@ impl User {
     pub fn join_group(
         &self,
         connection: &PgConnection,
-        group_id: &str
+        group_id: &str,
+        role: GroupRole,
     ) -> Result<usize, ShopError> {
+        let last_seq = Group::last_seq(connection, group_id)?;
         Ok(diesel::insert_into(groups_users::table)
             .values((
                 groups_users::user_id.eq(self.id.clone()),
                 groups_users::group_id.eq(group_id),
+                groups_users::role.eq(role.as_str()),
+                groups_users::last_seen_seq.eq(last_seq),
             ))
             .execute(connection)?)
     }
+    /// Get role of current user in provided group
+    /// # Returns
+    /// ## On success
+    /// * role: [GroupRole], or [None] if user is not a member of the group
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn group_role(
+        &self,
+        connection: &PgConnection,
+        group_id: &str,
+    ) -> Result<Option<GroupRole>, ShopError> {
+        let role = groups_users::table
+            .select(groups_users::role)
+            .filter(groups_users::group_id.eq(group_id))
+            .filter(groups_users::user_id.eq(&self.id))
+            .first::<String>(connection)
+            .optional()?;
+        role.map(|role| role.parse()).transpose()
+    }
 
     pub fn is_group_owner(
         &self,
         connection: &PgConnection,
-        group_id: &str
+        group_id: &str,
     ) -> Result<bool, ShopError> {
         let owner = groups_users::table
             .select(groups_users::id)
@@ -126,6 +341,27 @@ impl User {
         }
         Ok(true)
     }
+    /// Check if user is admin or owner of group
+    pub fn is_group_admin(
+        &self,
+        connection: &PgConnection,
+        group_id: &str,
+    ) -> Result<bool, ShopError> {
+        Ok(matches!(
+            self.group_role(connection, group_id)?,
+            Some(GroupRole::Admin) | Some(GroupRole::Owner)
+        ))
+    }
+}
+
+/// Struct received from request for changing password
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct PasswordChange {
+    /// Not needed by users without password, see [User::confirm_identity]
+    #[serde(default)]
+    pub old_password: Option<String>,
+    #[validate(length(min = "MIN_PASSWORD_LENGTH"))]
+    pub new_password: String,
 }
 
 /// Struct for validating and inserting [User] into database
@@ -150,12 +386,12 @@ impl NewUser {
         username: &str,
         pass: &str,
     ) -> Result<User, ShopError> {
-        if !User::is_available_username(&connection, username) {
+        if !User::is_available_username(connection, username) {
             return Err(ShopError::AlreadyExistsError);
         }
         let user = Self {
             username: username.to_string(),
-            password: bcrypt::hash(&pass, bcrypt::DEFAULT_COST).unwrap(),
+            password: password::hash(pass)?,
         };
         Ok(diesel::insert_into(users::table)
             .values(&user)
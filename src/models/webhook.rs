@@ -0,0 +1,601 @@
+use super::events::Sender;
+use super::pagination::{Page, Pagination};
+use super::profile::UserProfile;
+use super::user::User;
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::schema::{groups, webhook_dead_letters, webhook_deliveries, webhooks};
+use crate::utils::token;
+use crate::webhooks::WebhookPolicy;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Status of delivery waiting for (next) attempt
+pub const STATUS_PENDING: &str = "pending";
+/// Status of delivery accepted by receiver with 2xx response
+pub const STATUS_DELIVERED: &str = "delivered";
+/// Status of delivery which ran out of attempts and was moved to dead letters
+pub const STATUS_DEAD: &str = "dead";
+
+/// Group events webhooks can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// Message was sent to group
+    Message,
+    /// User joined group
+    Join,
+    /// User left group
+    Leave,
+    /// Group was renamed, got new topic or new owner
+    GroupUpdate,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Message => "message",
+            WebhookEvent::Join => "join",
+            WebhookEvent::Leave => "leave",
+            WebhookEvent::GroupUpdate => "group_update",
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message" => Ok(WebhookEvent::Message),
+            "join" => Ok(WebhookEvent::Join),
+            "leave" => Ok(WebhookEvent::Leave),
+            "group_update" => Ok(WebhookEvent::GroupUpdate),
+            _ => Err(ShopError::ParseError(format!("Unknown event {}", s))),
+        }
+    }
+}
+
+/// Events are stored space separated, the same way as scopes of API keys
+fn parse_events(events: &str) -> Result<Vec<WebhookEvent>, ShopError> {
+    events
+        .split_whitespace()
+        .map(|event| event.parse())
+        .collect()
+}
+
+/// Struct for representing HTTP endpoint receiving events of group.
+/// Secret is used for signing payloads, so unlike API keys it has to be stored in plain text.
+#[derive(Debug, Queryable, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub group_id: String,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    #[serde(skip_serializing)]
+    pub events: String,
+    pub created_by: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Webhook with parsed events, returned from routes
+#[derive(Debug, Serialize)]
+pub struct WebhookInfo {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    #[serde(rename = "events")]
+    pub event_list: Vec<WebhookEvent>,
+}
+
+/// Newly created webhook, secret is returned only once
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub info: WebhookInfo,
+    pub secret: String,
+}
+
+/// Struct received from request for registering webhook
+#[derive(Debug, Deserialize, validator::Validate)]
+pub struct NewWebhook {
+    #[validate(url, length(max = 2048))]
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Group after update, with owner which is otherwise not serialized
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdatedGroup {
+    pub id: String,
+    pub name: String,
+    pub topic: Option<String>,
+    pub owner_id: String,
+}
+
+/// Event specific part of payload, serialized into `data` field
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum WebhookData {
+    Message {
+        id: String,
+        sender: Sender,
+        body: String,
+        sent_at: NaiveDateTime,
+    },
+    Join {
+        user: Sender,
+    },
+    Leave {
+        user: Sender,
+    },
+    GroupUpdate {
+        group: UpdatedGroup,
+    },
+}
+
+impl WebhookData {
+    /// Id of message carried in payload
+    pub fn message_id(&self) -> Option<&str> {
+        match self {
+            WebhookData::Message { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn event(&self) -> WebhookEvent {
+        match self {
+            WebhookData::Message { .. } => WebhookEvent::Message,
+            WebhookData::Join { .. } => WebhookEvent::Join,
+            WebhookData::Leave { .. } => WebhookEvent::Leave,
+            WebhookData::GroupUpdate { .. } => WebhookEvent::GroupUpdate,
+        }
+    }
+}
+
+/// Body of every request sent to webhook
+/// ```
+/// {
+///     "id": "9a1c1f0e-0b8e-4d6e-9b7e-2f4f1c3d5e6a",
+///     "event": "join",
+///     "group_id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "created_at": "2022-09-30T10:00:00",
+///     "data": {
+///         "user": {
+///             "id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///             "username": "test_user",
+///             "display_name": "Test User",
+///             "avatar_url": null
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload<'a> {
+    /// Id of delivery, the same for all attempts so receiver can deduplicate them
+    pub id: &'a str,
+    pub event: WebhookEvent,
+    pub group_id: &'a str,
+    pub created_at: NaiveDateTime,
+    pub data: &'a WebhookData,
+}
+
+impl Webhook {
+    /// Check if webhook is subscribed to provided event
+    pub fn has_event(&self, event: WebhookEvent) -> bool {
+        self.events.split_whitespace().any(|e| e == event.as_str())
+    }
+
+    fn into_info(self) -> Result<WebhookInfo, ShopError> {
+        Ok(WebhookInfo {
+            event_list: parse_events(&self.events)?,
+            webhook: self,
+        })
+    }
+
+    /// Get webhook of provided group
+    /// # Returns
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::NotFoundError] if group has no such webhook
+    pub fn get(
+        connection: &PgConnection,
+        group_id: &str,
+        webhook_id: &str,
+    ) -> Result<Webhook, ShopError> {
+        webhooks::table
+            .find(webhook_id)
+            .filter(webhooks::group_id.eq(group_id))
+            .first::<Webhook>(connection)
+            .optional()?
+            .ok_or_else(|| ShopError::NotFoundError("Webhook not found".to_string()))
+    }
+
+    /// Registers webhook for group, limited by `WEBHOOKS_PER_GROUP` (default 10)
+    /// # Returns
+    /// ## On success
+    /// * webhook with its signing secret: [CreatedWebhook]
+    /// ## On faliure
+    /// * error: [ShopError], [ShopError::ParseError] for URL which is not http(s) or
+    ///   for empty list of events
+    pub fn create(
+        connection: &PgConnection,
+        group_id: &str,
+        creator: &User,
+        new_webhook: NewWebhook,
+    ) -> Result<CreatedWebhook, ShopError> {
+        if !new_webhook.url.starts_with("https://") && !new_webhook.url.starts_with("http://") {
+            return Err(ShopError::ParseError(
+                "Webhook URL must use http or https".to_string(),
+            ));
+        }
+        if new_webhook.events.is_empty() {
+            return Err(ShopError::ParseError(
+                "At least one event is required".to_string(),
+            ));
+        }
+        let limit: i64 = dotenv::var("WEBHOOKS_PER_GROUP")
+            .unwrap_or_else(|_| "10".into())
+            .parse()?;
+        let registered: i64 = webhooks::table
+            .filter(webhooks::group_id.eq(group_id))
+            .count()
+            .get_result(connection)?;
+        if registered >= limit {
+            return Err(ShopError::NoPermission(format!(
+                "Only {} webhooks per group are allowed",
+                limit
+            )));
+        }
+        let mut events: Vec<&str> = new_webhook.events.iter().map(|e| e.as_str()).collect();
+        events.sort_unstable();
+        events.dedup();
+        let secret = token::generate();
+        let webhook = diesel::insert_into(webhooks::table)
+            .values((
+                webhooks::group_id.eq(group_id),
+                webhooks::url.eq(&new_webhook.url),
+                webhooks::secret.eq(&secret),
+                webhooks::events.eq(events.join(" ")),
+                webhooks::created_by.eq(&creator.id),
+            ))
+            .get_result::<Webhook>(connection)?;
+        Ok(CreatedWebhook {
+            info: webhook.into_info()?,
+            secret,
+        })
+    }
+
+    /// List webhooks of group
+    pub fn list(connection: &PgConnection, group_id: &str) -> Result<Vec<WebhookInfo>, ShopError> {
+        webhooks::table
+            .filter(webhooks::group_id.eq(group_id))
+            .order(webhooks::created_at)
+            .load::<Webhook>(connection)?
+            .into_iter()
+            .map(Webhook::into_info)
+            .collect()
+    }
+
+    /// Deletes webhook together with its delivery log and dead letters
+    pub fn delete(connection: &PgConnection, webhook: &Webhook) -> Result<(), ShopError> {
+        connection.transaction(|| {
+            diesel::delete(
+                webhook_dead_letters::table
+                    .filter(webhook_dead_letters::webhook_id.eq(&webhook.id)),
+            )
+            .execute(connection)?;
+            diesel::delete(
+                webhook_deliveries::table.filter(webhook_deliveries::webhook_id.eq(&webhook.id)),
+            )
+            .execute(connection)?;
+            diesel::delete(webhooks::table.find(&webhook.id)).execute(connection)?;
+            Ok(())
+        })
+    }
+
+    /// Deletes all webhooks of group, used when group itself is deleted
+    pub fn delete_by_group(connection: &PgConnection, group_id: &str) -> Result<(), ShopError> {
+        let registered = webhooks::table
+            .filter(webhooks::group_id.eq(group_id))
+            .load::<Webhook>(connection)?;
+        for webhook in registered {
+            Webhook::delete(connection, &webhook)?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct for representing single event queued for webhook, with result of last attempt
+#[derive(Debug, Queryable, Serialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    #[serde(skip_serializing)]
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    /// Message announced by `message` event, so that payload can be purged with it
+    #[serde(skip_serializing)]
+    pub message_id: Option<String>,
+}
+
+/// Delivery due for an attempt, together with target of its webhook
+#[derive(Debug)]
+pub struct DueDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+}
+
+impl WebhookDelivery {
+    /// Queues event for every webhook of group subscribed to it.
+    /// Requests are sent later by [crate::webhooks] worker.
+    /// # Returns
+    /// ## On success
+    /// * number of queued deliveries: [usize]
+    /// ## On faliure
+    /// * error: [ShopError]
+    pub fn enqueue(
+        connection: &PgConnection,
+        group_id: &str,
+        data: &WebhookData,
+    ) -> Result<usize, ShopError> {
+        let event = data.event();
+        let subscribed: Vec<Webhook> = webhooks::table
+            .filter(webhooks::group_id.eq(group_id))
+            .load::<Webhook>(connection)?
+            .into_iter()
+            .filter(|webhook| webhook.has_event(event))
+            .collect();
+        let now = Utc::now().naive_utc();
+        for webhook in &subscribed {
+            let id = Uuid::new_v4().to_string();
+            let payload = serde_json::to_string(&WebhookPayload {
+                id: &id,
+                event,
+                group_id,
+                created_at: now,
+                data,
+            })?;
+            diesel::insert_into(webhook_deliveries::table)
+                .values((
+                    webhook_deliveries::id.eq(&id),
+                    webhook_deliveries::webhook_id.eq(&webhook.id),
+                    webhook_deliveries::event.eq(event.as_str()),
+                    webhook_deliveries::payload.eq(payload),
+                    webhook_deliveries::next_attempt_at.eq(now),
+                    webhook_deliveries::message_id.eq(data.message_id()),
+                ))
+                .execute(connection)?;
+        }
+        Ok(subscribed.len())
+    }
+
+    /// Same as [WebhookDelivery::enqueue], but only logs failure, so that failing webhooks
+    /// never break chat itself
+    pub fn emit(connection: &PgConnection, group_id: &str, data: &WebhookData) {
+        if let Err(e) = WebhookDelivery::enqueue(connection, group_id, data) {
+            println!(
+                "Couldn't queue {} webhooks of group {}: {}",
+                data.event().as_str(),
+                group_id,
+                e
+            );
+        }
+    }
+
+    /// Emits [WebhookEvent::Join] or [WebhookEvent::Leave] of user with provided id
+    pub fn emit_member(
+        connection: &PgConnection,
+        group_id: &str,
+        user_id: &str,
+        event: WebhookEvent,
+    ) {
+        let user: Sender = match UserProfile::get(connection, user_id) {
+            Ok(profile) => profile.into(),
+            Err(e) => {
+                println!("Couldn't load member {} for webhooks: {}", user_id, e);
+                return;
+            }
+        };
+        let data = match event {
+            WebhookEvent::Leave => WebhookData::Leave { user },
+            _ => WebhookData::Join { user },
+        };
+        WebhookDelivery::emit(connection, group_id, &data);
+    }
+
+    /// Emits [WebhookEvent::GroupUpdate] with current state of group
+    pub fn emit_group_update(connection: &PgConnection, group_id: &str) {
+        let group = groups::table
+            .find(group_id)
+            .select((groups::id, groups::name, groups::topic, groups::owner_id))
+            .first::<(String, String, Option<String>, String)>(connection);
+        match group {
+            Ok((id, name, topic, owner_id)) => {
+                let group = UpdatedGroup {
+                    id,
+                    name,
+                    topic,
+                    owner_id,
+                };
+                WebhookDelivery::emit(connection, group_id, &WebhookData::GroupUpdate { group })
+            }
+            Err(e) => println!("Couldn't load group {} for webhooks: {}", group_id, e),
+        }
+    }
+
+    /// Claims pending deliveries whose next attempt is due, oldest first. Claimed deliveries
+    /// are postponed by `lease`, so that other workers skip them while they are attempted.
+    pub fn claim_due(
+        connection: &PgConnection,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueDelivery>, ShopError> {
+        let now = Utc::now().naive_utc();
+        let lease_until =
+            now + chrono::Duration::from_std(lease).unwrap_or_else(|_| chrono::Duration::zero());
+        connection.transaction(|| {
+            let ids = webhook_deliveries::table
+                .filter(webhook_deliveries::status.eq(STATUS_PENDING))
+                .filter(webhook_deliveries::next_attempt_at.le(now))
+                .order(webhook_deliveries::next_attempt_at.asc())
+                .limit(limit)
+                .select(webhook_deliveries::id)
+                .for_update()
+                .skip_locked()
+                .load::<String>(connection)?;
+            diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq_any(&ids)))
+                .set(webhook_deliveries::next_attempt_at.eq(lease_until))
+                .execute(connection)?;
+            Ok(webhook_deliveries::table
+                .inner_join(webhooks::table)
+                .filter(webhook_deliveries::id.eq_any(&ids))
+                .order(webhook_deliveries::created_at.asc())
+                .select((
+                    webhook_deliveries::all_columns,
+                    webhooks::url,
+                    webhooks::secret,
+                ))
+                .load::<(WebhookDelivery, String, String)>(connection)?
+                .into_iter()
+                .map(|(delivery, url, secret)| DueDelivery {
+                    delivery,
+                    url,
+                    secret,
+                })
+                .collect())
+        })
+    }
+
+    /// Marks delivery as accepted by receiver, it has to be [saved](WebhookDelivery::save)
+    pub fn succeeded(&mut self, status_code: u16, now: NaiveDateTime) {
+        self.status = STATUS_DELIVERED.to_string();
+        self.attempts += 1;
+        self.last_status_code = Some(status_code as i32);
+        self.last_error = None;
+        self.delivered_at = Some(now);
+    }
+
+    /// Marks attempt as failed and schedules next one with exponential backoff, after last
+    /// allowed attempt delivery is given up instead. It has to be [saved](WebhookDelivery::save).
+    /// # Returns
+    /// * `true` if delivery was given up and belongs to dead letters: [bool]
+    pub fn failed(
+        &mut self,
+        policy: &WebhookPolicy,
+        status_code: Option<u16>,
+        error: &str,
+        now: NaiveDateTime,
+    ) -> bool {
+        self.attempts += 1;
+        let dead = policy.gives_up(self.attempts as u32);
+        self.status = if dead { STATUS_DEAD } else { STATUS_PENDING }.to_string();
+        self.next_attempt_at = now
+            + chrono::Duration::from_std(policy.backoff(self.attempts as u32))
+                .unwrap_or_else(|_| chrono::Duration::zero());
+        self.last_status_code = status_code.map(i32::from);
+        self.last_error = Some(error.to_string());
+        dead
+    }
+
+    /// Saves result of attempt, given up delivery is copied to dead letters
+    pub fn save(&self, connection: &PgConnection) -> Result<(), ShopError> {
+        connection.transaction(|| {
+            diesel::update(webhook_deliveries::table.find(&self.id))
+                .set((
+                    webhook_deliveries::status.eq(&self.status),
+                    webhook_deliveries::attempts.eq(self.attempts),
+                    webhook_deliveries::next_attempt_at.eq(self.next_attempt_at),
+                    webhook_deliveries::last_status_code.eq(self.last_status_code),
+                    webhook_deliveries::last_error.eq(&self.last_error),
+                    webhook_deliveries::delivered_at.eq(self.delivered_at),
+                ))
+                .execute(connection)?;
+            if self.status == STATUS_DEAD {
+                diesel::insert_into(webhook_dead_letters::table)
+                    .values((
+                        webhook_dead_letters::delivery_id.eq(&self.id),
+                        webhook_dead_letters::webhook_id.eq(&self.webhook_id),
+                        webhook_dead_letters::event.eq(&self.event),
+                        webhook_dead_letters::payload.eq(&self.payload),
+                        webhook_dead_letters::attempts.eq(self.attempts),
+                        webhook_dead_letters::last_error.eq(&self.last_error),
+                        webhook_dead_letters::message_id.eq(&self.message_id),
+                    ))
+                    .execute(connection)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes content of purged messages from deliveries announcing them. Deliveries which
+    /// were not sent yet are dropped, payload of the rest is replaced with `{}`.
+    pub fn purge_messages(
+        connection: &PgConnection,
+        message_ids: &[&str],
+    ) -> Result<(), ShopError> {
+        diesel::delete(
+            webhook_deliveries::table
+                .filter(webhook_deliveries::message_id.eq_any(message_ids))
+                .filter(webhook_deliveries::status.eq(STATUS_PENDING)),
+        )
+        .execute(connection)?;
+        diesel::update(
+            webhook_deliveries::table.filter(webhook_deliveries::message_id.eq_any(message_ids)),
+        )
+        .set(webhook_deliveries::payload.eq("{}"))
+        .execute(connection)?;
+        diesel::update(
+            webhook_dead_letters::table
+                .filter(webhook_dead_letters::message_id.eq_any(message_ids)),
+        )
+        .set(webhook_dead_letters::payload.eq("{}"))
+        .execute(connection)?;
+        Ok(())
+    }
+
+    /// Delivery log of webhook, newest first
+    pub fn list(
+        connection: &PgConnection,
+        webhook: &Webhook,
+        pagination: &Pagination,
+    ) -> Result<Page<WebhookDelivery>, ShopError> {
+        let total: i64 = webhook_deliveries::table
+            .filter(webhook_deliveries::webhook_id.eq(&webhook.id))
+            .count()
+            .get_result(connection)?;
+        let items = webhook_deliveries::table
+            .filter(webhook_deliveries::webhook_id.eq(&webhook.id))
+            .order(webhook_deliveries::created_at.desc())
+            .limit(pagination.per_page())
+            .offset(pagination.offset())
+            .load::<WebhookDelivery>(connection)?;
+        Ok(Page::new(pagination, total, items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_round_trip() {
+        let events = parse_events("message group_update").unwrap();
+        assert_eq!(
+            events,
+            vec![WebhookEvent::Message, WebhookEvent::GroupUpdate]
+        );
+        assert!(parse_events("message typing").is_err());
+        let json = serde_json::to_string(&events).unwrap();
+        assert_eq!(json, r#"["message","group_update"]"#);
+    }
+}
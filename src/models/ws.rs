@@ -1,15 +1,124 @@
+use crate::metrics::Metrics;
+use crate::models::events::{ChatEvent, ErrorCode};
 use crate::models::lobby::Lobby;
-use crate::models::messages::{ClientActorMessage, Connect, Disconnect, WsMessage};
+use crate::models::messages::{ClientActorMessage, CloseSession, Connect, Disconnect, WsMessage};
+use crate::models::outbound::{InFlight, OutboundQueue, Pushed, QueueLimits};
+use crate::ratelimit::{LimitScope, Limited, MessagePolicy, Strikes, TokenBucket};
+use crate::utils::StaticData;
 use actix::{fut, ActorContext, ActorFutureExt, ContextFutureSpawner, WrapFuture};
 use actix::{Actor, Addr, Running, StreamHandler};
 use actix::{AsyncContext, Handler};
+use actix_http::ws::Item;
 use actix_web_actors::ws;
 use actix_web_actors::ws::Message::Text;
+use serde::Deserialize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often queued events are written, when network did not take earlier ones right away
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size limits of inbound websocket data, read from .env file
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLimits {
+    /// Largest single frame in bytes, larger frames close socket with 1009
+    pub max_frame_size: usize,
+    /// Largest message in bytes, after continuation frames are put together
+    pub max_message_size: usize,
+}
+
+impl FrameLimits {
+    /// Reads limits from `WS_MAX_FRAME_SIZE` (default 16384) and `WS_MAX_MESSAGE_SIZE` (65536)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: usize| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        FrameLimits {
+            max_frame_size: read("WS_MAX_FRAME_SIZE", 16 * 1024).max(125),
+            max_message_size: read("WS_MAX_MESSAGE_SIZE", 64 * 1024).max(1),
+        }
+    }
+}
+
+/// Message split into continuation frames, collected until last frame arrives
+struct Fragments {
+    text: bool,
+    data: Vec<u8>,
+}
+
+/// Close code for protocol error reported by frame decoder.
+/// Text frames with invalid UTF-8 are reported as [ws::ProtocolError::Io], its only other
+/// use is failed read from connection, after which close frame cannot be delivered anyway.
+fn close_code(error: &ws::ProtocolError) -> ws::CloseCode {
+    match error {
+        ws::ProtocolError::Overflow => ws::CloseCode::Size,
+        ws::ProtocolError::Io(_) => ws::CloseCode::Invalid,
+        _ => ws::CloseCode::Protocol,
+    }
+}
+
+/// Checks content of chat message
+/// # Returns
+/// ## On faliure
+/// * reason why message is not accepted: [&str]
+pub fn validate_text(text: &str) -> Result<(), &'static str> {
+    if text.trim().is_empty() {
+        return Err("Message is empty");
+    }
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        return Err("Message contains control characters");
+    }
+    Ok(())
+}
+
+/// Longest client generated message id
+const MAX_CLIENT_ID_LENGTH: usize = 64;
+
+/// Text frame sent as json by clients which want every message acknowledged,
+/// frames which are not [ClientFrame] are plain messages
+/// ```
+/// {
+///     "type": "message",
+///     "client_id": "3b0c2b9e-8d8f-4f57-bb5f-6b3a2c0d9e41",
+///     "body": "Hello!"
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    Message { client_id: String, body: String },
+}
+
+/// Splits text frame into client id and message
+/// # Returns
+/// ## On success
+/// * client id: [Option] of [String] and message: [String]
+/// ## On faliure
+/// * reason why frame is not accepted: [&str]
+pub fn parse_frame(text: &str) -> Result<(Option<String>, String), &'static str> {
+    if !text.starts_with('{') {
+        return Ok((None, text.to_string()));
+    }
+    match serde_json::from_str::<ClientFrame>(text) {
+        Ok(ClientFrame::Message { client_id, body }) => {
+            if client_id.is_empty() || client_id.len() > MAX_CLIENT_ID_LENGTH {
+                return Err("Client id must have 1 to 64 characters");
+            }
+            Ok((Some(client_id), body))
+        }
+        Err(_) => Ok((None, text.to_string())),
+    }
+}
 
 /// Struct for representing web socket connection
 pub struct WsConn {
@@ -17,18 +126,59 @@ pub struct WsConn {
     lobby_addr: Addr<Lobby>,
     hb: Instant,
     id: Uuid,
+    /// False for bots whose API key cannot send messages
+    can_send: bool,
+    policy: MessagePolicy,
+    /// Limit of messages sent through this socket
+    bucket: TokenBucket,
+    strikes: Strikes,
+    limits: FrameLimits,
+    /// Message which is being received in continuation frames
+    fragments: Option<Fragments>,
+    queue_limits: QueueLimits,
+    /// Events waiting until client takes earlier ones
+    outbox: OutboundQueue,
+    in_flight: Arc<InFlight>,
+    metrics: Arc<Metrics>,
+    /// Sequence number of last event received before reconnecting
+    last_seq: Option<i64>,
 }
 
 impl WsConn {
-    /// Function that creates new [WsConn] instance for currently logged in user
-    pub fn new(room: Uuid, lobby: Addr<Lobby>, user_id: Uuid) -> WsConn {
+    /// Function that creates new [WsConn] instance for currently logged in user,
+    /// with limits configured in `data`. Events after `last_seq` are replayed on connect.
+    pub fn new(
+        room: Uuid,
+        lobby: Addr<Lobby>,
+        user_id: Uuid,
+        can_send: bool,
+        last_seq: Option<i64>,
+        data: &StaticData,
+    ) -> WsConn {
+        let policy = data.message_policy.clone();
         WsConn {
             id: user_id, //Uuid::new_v4(),
             room,
             hb: Instant::now(),
             lobby_addr: lobby,
+            can_send,
+            bucket: TokenBucket::new(policy.connection, Instant::now()),
+            policy,
+            strikes: Strikes::default(),
+            limits: data.frame_limits,
+            fragments: None,
+            queue_limits: data.queue_limits,
+            outbox: OutboundQueue::new(data.queue_limits.max_queued_events),
+            in_flight: Arc::default(),
+            metrics: data.metrics.clone(),
+            last_seq,
         }
     }
+
+    /// Counter of bytes written to socket, for [OutboundStream](super::outbound::OutboundStream)
+    pub fn in_flight(&self) -> Arc<InFlight> {
+        self.in_flight.clone()
+    }
 }
 
 impl Actor for WsConn {
@@ -36,13 +186,16 @@ impl Actor for WsConn {
     /// Actor state function, gets called first when [ws::start] function is called
     fn started(&mut self, ctx: &mut Self::Context) {
         self.hb(ctx);
+        self.metrics.ws_sessions.fetch_add(1, Ordering::Relaxed);
+        ctx.run_interval(FLUSH_INTERVAL, |act, ctx| act.flush(ctx));
 
         let addr = ctx.address();
         self.lobby_addr
             .send(Connect {
-                addr: addr.recipient(),
+                addr,
                 lobby_id: self.room,
                 self_id: self.id,
+                last_seq: self.last_seq,
             })
             .into_actor(self)
             .then(|res, _, ctx| {
@@ -62,12 +215,19 @@ impl Actor for WsConn {
         });
         Running::Stop
     }
+    /// Actor state function, releases queued events
+    fn stopped(&mut self, _: &mut Self::Context) {
+        self.metrics.ws_sessions.fetch_sub(1, Ordering::Relaxed);
+        self.metrics
+            .ws_queued_events
+            .fetch_sub(self.outbox.len() as i64, Ordering::Relaxed);
+    }
 }
 
 impl WsConn {
     /// Function that preforms heartbeating
     /// * Heartbeating is occasionally checking if client is still responsive
-    /// by pinging him by some message.
+    ///   by pinging him by some message.
     /// * [HEARTBEAT_INTERVAL] constant defines period of heartbeating
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
         ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
@@ -84,6 +244,160 @@ impl WsConn {
             ctx.ping(b"hi");
         });
     }
+
+    /// Takes token from bucket of socket, rejected frames are answered with error
+    /// and socket is closed after too many of them
+    fn allow(&mut self, client_id: Option<&str>, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        let now = Instant::now();
+        let retry_after = match self.bucket.try_take(now) {
+            Ok(()) => return true,
+            Err(retry_after) => retry_after,
+        };
+        let limited = Limited {
+            scope: LimitScope::Connection,
+            retry_after,
+        };
+        ctx.text(limited.into_event().into_reply(client_id).to_json());
+        if self.strikes.hit(now, self.policy.strike_window) >= self.policy.max_strikes {
+            close(ctx, ws::CloseCode::Policy, "Too many messages");
+        }
+        false
+    }
+
+    /// Queues event for client, dropping ephemeral events or closing socket
+    /// when client does not keep up
+    fn enqueue(&mut self, msg: WsMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        match self.outbox.push(msg) {
+            Pushed::Queued => {
+                self.metrics
+                    .ws_queued_events
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            Pushed::DroppedOldest | Pushed::DroppedNew => {
+                self.metrics
+                    .ws_dropped_events
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            Pushed::Overflow => {
+                println!("Closing websocket of {}, client is too slow", self.id);
+                self.metrics
+                    .ws_slow_disconnects
+                    .fetch_add(1, Ordering::Relaxed);
+                return close(ctx, ws::CloseCode::Again, "Too slow");
+            }
+        }
+        self.metrics.observe_queue_depth(self.outbox.len());
+        self.flush(ctx);
+    }
+
+    /// Writes queued events while network takes them
+    fn flush(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        while self.in_flight.get() < self.queue_limits.max_in_flight_bytes {
+            let msg = match self.outbox.pop() {
+                Some(msg) => msg,
+                None => break,
+            };
+            self.in_flight.add(msg.text.len());
+            self.metrics
+                .ws_in_flight_bytes
+                .fetch_add(msg.text.len() as i64, Ordering::Relaxed);
+            self.metrics
+                .ws_queued_events
+                .fetch_sub(1, Ordering::Relaxed);
+            ctx.text(msg.text);
+        }
+    }
+
+    /// Validates complete text message and forwards it to lobby
+    fn text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        if text.len() > self.limits.max_message_size {
+            return close(ctx, ws::CloseCode::Size, "Message is too large");
+        }
+        let (client_id, body) = match parse_frame(text) {
+            Ok(frame) => frame,
+            Err(reason) => {
+                let error = ChatEvent::Error {
+                    code: ErrorCode::InvalidMessage,
+                    body: reason.to_string(),
+                };
+                return ctx.text(error.to_json());
+            }
+        };
+        let client_id = client_id.as_deref();
+        if !self.can_send {
+            let notice = ChatEvent::Notice {
+                body: "API key is missing scope chat:write".to_string(),
+            };
+            return ctx.text(notice.into_reply(client_id).to_json());
+        }
+        if let Err(reason) = validate_text(&body) {
+            let error = ChatEvent::Error {
+                code: ErrorCode::InvalidMessage,
+                body: reason.to_string(),
+            };
+            return ctx.text(error.into_reply(client_id).to_json());
+        }
+        if self.allow(client_id, ctx) {
+            self.lobby_addr.do_send(ClientActorMessage {
+                id: self.id,
+                msg: body,
+                room_id: self.room,
+                client_id: client_id.map(str::to_string),
+            });
+        }
+    }
+
+    /// Collects message split into continuation frames, up to [FrameLimits::max_message_size]
+    fn continuation(&mut self, item: Item, ctx: &mut ws::WebsocketContext<Self>) {
+        let (first, text, data, last) = match item {
+            Item::FirstText(data) => (true, true, data, false),
+            Item::FirstBinary(data) => (true, false, data, false),
+            Item::Continue(data) => (false, false, data, false),
+            Item::Last(data) => (false, false, data, true),
+        };
+        if first {
+            if self.fragments.is_some() {
+                return close(ctx, ws::CloseCode::Protocol, "Continuation already started");
+            }
+            self.fragments = Some(Fragments {
+                text,
+                data: Vec::new(),
+            });
+        }
+        let fragments = match self.fragments.as_mut() {
+            Some(fragments) => fragments,
+            None => return close(ctx, ws::CloseCode::Protocol, "Continuation not started"),
+        };
+        if fragments.data.len() + data.len() > self.limits.max_message_size {
+            self.fragments = None;
+            return close(ctx, ws::CloseCode::Size, "Message is too large");
+        }
+        fragments.data.extend_from_slice(&data);
+        if !last {
+            return;
+        }
+        if let Some(fragments) = self.fragments.take() {
+            if !fragments.text {
+                return ctx.binary(fragments.data);
+            }
+            match String::from_utf8(fragments.data) {
+                Ok(text) => self.text(&text, ctx),
+                Err(_) => close(ctx, ws::CloseCode::Invalid, "Message is not valid UTF-8"),
+            }
+        }
+    }
+}
+
+/// Closes socket with provided code and stops actor
+fn close<A>(ctx: &mut ws::WebsocketContext<A>, code: ws::CloseCode, description: &str)
+where
+    A: Actor<Context = ws::WebsocketContext<A>>,
+{
+    ctx.close(Some(ws::CloseReason {
+        code,
+        description: Some(description.to_string()),
+    }));
+    ctx.stop();
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
@@ -102,17 +416,13 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
                 ctx.close(reason);
                 ctx.stop();
             }
-            Ok(ws::Message::Continuation(_)) => {
-                ctx.stop();
-            }
+            Ok(ws::Message::Continuation(item)) => self.continuation(item, ctx),
             Ok(ws::Message::Nop) => (),
-            Ok(Text(s)) => self.lobby_addr.do_send(ClientActorMessage {
-                id: self.id,
-                msg: s.to_string(),
-                room_id: self.room,
-            }),
-
-            Err(e) => panic!("{}", e),
+            Ok(Text(s)) => self.text(&s, ctx),
+            Err(e) => {
+                println!("Closing websocket of {}: {}", self.id, e);
+                close(ctx, close_code(&e), &e.to_string());
+            }
         }
     }
 }
@@ -121,6 +431,85 @@ impl Handler<WsMessage> for WsConn {
     type Result = ();
     /// Method that specifies how WsConn should handle WsMessage
     fn handle(&mut self, msg: WsMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        self.enqueue(msg, ctx);
+    }
+}
+
+impl Handler<CloseSession> for WsConn {
+    type Result = ();
+    /// Method that closes socket, e.g. when user was kicked from group
+    fn handle(&mut self, msg: CloseSession, ctx: &mut Self::Context) {
+        close(ctx, msg.code, &msg.reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_text() {
+        assert!(validate_text("hello\nworld").is_ok());
+        assert!(validate_text(" \t ").is_err());
+        assert!(validate_text("bell\u{7}").is_err());
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        assert_eq!(parse_frame("hello"), Ok((None, "hello".to_string())));
+        assert_eq!(
+            parse_frame(r#"{"type":"message","client_id":"c1","body":"hi"}"#),
+            Ok((Some("c1".to_string()), "hi".to_string()))
+        );
+        assert_eq!(
+            parse_frame(r#"{"json": "pasted as message"}"#),
+            Ok((None, r#"{"json": "pasted as message"}"#.to_string()))
+        );
+        assert!(parse_frame(r#"{"type":"message","client_id":"","body":"hi"}"#).is_err());
+    }
+
+    #[test]
+    fn test_close_codes() {
+        assert_eq!(
+            close_code(&ws::ProtocolError::Overflow),
+            ws::CloseCode::Size
+        );
+        assert_eq!(
+            close_code(&ws::ProtocolError::BadOpCode),
+            ws::CloseCode::Protocol
+        );
+    }
+
+    /// Actor closing socket on decoder errors the same way as [WsConn]
+    struct Probe;
+
+    impl Actor for Probe {
+        type Context = ws::WebsocketContext<Self>;
+    }
+
+    impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Probe {
+        fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+            if let Err(e) = msg {
+                close(ctx, close_code(&e), &e.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_closes_with_1007() {
+        // masked text frame, zero mask leaves payload as is
+        let frame = actix_web::web::Bytes::from_static(&[0x81, 0x82, 0, 0, 0, 0, 0xc3, 0x28]);
+        let (_, payload) = actix_web::test::TestRequest::default()
+            .set_payload(frame)
+            .to_http_parts();
+        let written = actix_web::rt::System::new().block_on(async {
+            let output = ws::WebsocketContext::create(Probe, payload);
+            actix_web::body::to_bytes(actix_web::body::BodyStream::new(output))
+                .await
+                .unwrap()
+        });
+        // close frame starts with its code, 1007
+        assert_eq!(written[0], 0x88);
+        assert_eq!(&written[2..4], &1007u16.to_be_bytes());
     }
 }
@@ -0,0 +1,323 @@
+//! Background delivery of notifications queued in outbox for offline users
+//!
+//! Pending notifications of user are collected for [NotificationSettings::digest_minutes]
+//! after the oldest of them and then sent together as single digest through configured
+//! [Notifier](super::Notifier). Notifications are held back during do not disturb window
+//! of user, and ones about messages he has read, or which were deleted, in the meantime
+//! are skipped. Failed digests are retried with exponential backoff, only through channels
+//! which have not delivered them yet.
+use super::{Delivery, Notification};
+use crate::errors::ShopError;
+use crate::models::notification::NotificationSettings;
+use crate::models::outbox::QueuedNotification;
+use crate::models::user::User;
+use crate::utils::AppState;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+
+/// Length of single message in digest, longer ones are shortened
+const DIGEST_LINE_LENGTH: usize = 200;
+
+/// Limits of notification dispatcher, read from .env file
+#[derive(Debug, Clone)]
+pub struct DispatchPolicy {
+    /// How often dispatcher looks for due notifications
+    pub poll_interval: Duration,
+    /// Users whose notifications are sent in single poll
+    pub batch_size: i64,
+    /// Attempts of single notification before it is given up
+    pub max_attempts: u32,
+    /// Delay after first failed attempt, doubled with every next one
+    pub base_backoff: Duration,
+    /// Upper limit of delay between attempts
+    pub max_backoff: Duration,
+    /// Most messages listed in digest, the rest is only counted
+    pub digest_max_items: usize,
+}
+
+impl DispatchPolicy {
+    /// Reads policy from `NOTIFY_POLL_INTERVAL_IN_MILLIS` (default 5000),
+    /// `NOTIFY_BATCH_SIZE` (50), `NOTIFY_MAX_ATTEMPTS` (5), `NOTIFY_BASE_BACKOFF_IN_SECONDS`
+    /// (60), `NOTIFY_MAX_BACKOFF_IN_SECONDS` (3600) and `NOTIFY_DIGEST_MAX_ITEMS` (20)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u64| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        DispatchPolicy {
+            poll_interval: Duration::from_millis(read("NOTIFY_POLL_INTERVAL_IN_MILLIS", 5000)),
+            batch_size: read("NOTIFY_BATCH_SIZE", 50) as i64,
+            max_attempts: read("NOTIFY_MAX_ATTEMPTS", 5).max(1) as u32,
+            base_backoff: Duration::from_secs(read("NOTIFY_BASE_BACKOFF_IN_SECONDS", 60)),
+            max_backoff: Duration::from_secs(read("NOTIFY_MAX_BACKOFF_IN_SECONDS", 3600)),
+            digest_max_items: read("NOTIFY_DIGEST_MAX_ITEMS", 20).max(1) as usize,
+        }
+    }
+
+    /// Delay before next attempt after `attempts` failed ones
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Subject and body of notification about `queued` notifications, single one is sent
+/// as it is, more of them are listed in digest
+pub fn digest(queued: &[QueuedNotification], max_items: usize) -> (String, String) {
+    if let [single] = queued {
+        return (single.title.clone(), single.body.clone());
+    }
+    let mentions = queued
+        .iter()
+        .filter(|queued| queued.reason == "mention")
+        .count();
+    let subject = match mentions {
+        0 => format!("{} new messages", queued.len()),
+        1 => format!("{} new messages, 1 mention", queued.len()),
+        _ => format!("{} new messages, {} mentions", queued.len(), mentions),
+    };
+    let mut lines = queued
+        .iter()
+        .take(max_items)
+        .map(|queued| format!("{}: {}", queued.title, shorten(&queued.body)))
+        .collect::<Vec<_>>();
+    if queued.len() > max_items {
+        lines.push(format!("...and {} more", queued.len() - max_items));
+    }
+    (subject, lines.join("\n"))
+}
+
+/// Message on single line, shortened to [DIGEST_LINE_LENGTH] characters
+fn shorten(body: &str) -> String {
+    let line = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(DIGEST_LINE_LENGTH) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+/// Starts dispatcher of queued notifications, it runs as long as the server
+pub fn start(state: AppState) {
+    let policy = DispatchPolicy::from_env();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(policy.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = dispatch_due(&state, &policy).await {
+                println!("Notification dispatcher failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Sends digest to every user with due notifications
+async fn dispatch_due(state: &AppState, policy: &DispatchPolicy) -> Result<(), ShopError> {
+    let users = {
+        let connection = state.get_pg_connection()?;
+        QueuedNotification::due_users(&connection, policy.batch_size)?
+    };
+    for user_id in users {
+        if let Err(e) = dispatch(state, policy, &user_id).await {
+            println!("Couldn't notify user {}: {}", user_id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Sends pending notifications of single user, unless they should wait
+async fn dispatch(
+    state: &AppState,
+    policy: &DispatchPolicy,
+    user_id: &str,
+) -> Result<(), ShopError> {
+    let now = Utc::now().naive_utc();
+    let (queued, user, settings) = {
+        let connection = state.get_pg_connection()?;
+        let pending = QueuedNotification::pending(&connection, user_id)?;
+        if !pending.stale.is_empty() {
+            QueuedNotification::skip(&connection, &pending.stale)?;
+        }
+        let queued = pending.current;
+        let ids = queued
+            .iter()
+            .map(|queued| queued.id.clone())
+            .collect::<Vec<_>>();
+        let oldest = match queued.first() {
+            Some(oldest) => oldest,
+            None => return Ok(()),
+        };
+        let settings = NotificationSettings::get(&connection, user_id)?;
+        if let Some(until) = settings.do_not_disturb_until(now) {
+            QueuedNotification::postpone(&connection, &ids, until)?;
+            return Ok(());
+        }
+        let collected_until =
+            oldest.created_at + ChronoDuration::minutes(settings.digest_minutes as i64);
+        let ready_at = queued
+            .iter()
+            .map(|queued| queued.next_attempt_at)
+            .fold(collected_until, |ready_at, at| ready_at.max(at));
+        if ready_at > now {
+            QueuedNotification::postpone(&connection, &ids, ready_at)?;
+            return Ok(());
+        }
+        let user = User::get_by_id(&connection, user_id)?;
+        (queued, user, settings)
+    };
+    for batch in batches(queued) {
+        let (subject, body) = digest(&batch, policy.digest_max_items);
+        let notification = Notification {
+            user_id: user.id.clone(),
+            username: user.username.clone(),
+            subject,
+            body,
+            email: settings.email.clone(),
+            push_token: settings.push_token.clone(),
+        };
+        send(state, policy, &batch, notification).await?;
+    }
+    Ok(())
+}
+
+/// Splits notifications by channels which already delivered them, so each part is sent
+/// as separate digest only through channels which have not delivered it yet
+fn batches(queued: Vec<QueuedNotification>) -> Vec<Vec<QueuedNotification>> {
+    let mut batches: Vec<Vec<QueuedNotification>> = Vec::new();
+    for queued in queued {
+        let batch = batches
+            .iter_mut()
+            .find(|batch| batch[0].delivered_channels == queued.delivered_channels);
+        match batch {
+            Some(batch) => batch.push(queued),
+            None => batches.push(vec![queued]),
+        }
+    }
+    batches
+}
+
+/// Delivers digest of `queued` notifications through channels which have not delivered
+/// them yet and records the outcome
+async fn send(
+    state: &AppState,
+    policy: &DispatchPolicy,
+    queued: &[QueuedNotification],
+    notification: Notification,
+) -> Result<(), ShopError> {
+    let notifier = state.static_data.notifier.clone();
+    let delivered = queued[0].delivered_channels.clone();
+    let delivery = actix_web::web::block(move || {
+        let delivered = delivered.split_whitespace().collect::<Vec<_>>();
+        notifier.notify_except(&notification, &delivered)
+    })
+    .await
+    .unwrap_or_else(|e| Delivery {
+        delivered: Vec::new(),
+        error: Some(e.into()),
+    });
+    let connection = state.get_pg_connection()?;
+    match delivery.error {
+        None => {
+            let ids = queued
+                .iter()
+                .map(|queued| queued.id.clone())
+                .collect::<Vec<_>>();
+            QueuedNotification::record_sent(&connection, &ids)?
+        }
+        Some(e) => {
+            let error = e.to_string();
+            let now = Utc::now().naive_utc();
+            for queued in queued {
+                let attempts = queued.attempts as u32 + 1;
+                let retry_at = now
+                    + ChronoDuration::from_std(policy.backoff(attempts))
+                        .unwrap_or_else(|_| ChronoDuration::zero());
+                queued.record_failure(
+                    &connection,
+                    attempts >= policy.max_attempts,
+                    retry_at,
+                    &error,
+                    &delivery.delivered,
+                )?;
+            }
+            println!(
+                "Notification for user {} failed: {}",
+                queued[0].user_id, error
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn queued(reason: &str, title: &str, body: &str) -> QueuedNotification {
+        let at = NaiveDateTime::from_timestamp(0, 0);
+        QueuedNotification {
+            id: "id".to_string(),
+            user_id: "user".to_string(),
+            group_id: "group".to_string(),
+            message_id: "message".to_string(),
+            reason: reason.to_string(),
+            title: title.to_string(),
+            body: body.to_string(),
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: at,
+            last_error: None,
+            created_at: at,
+            sent_at: None,
+            delivered_channels: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_single_notification_is_not_digest() {
+        let single = [queued("mention", "alice mentioned you in Rust", "@bob hi")];
+        assert_eq!(
+            digest(&single, 20),
+            (
+                "alice mentioned you in Rust".to_string(),
+                "@bob hi".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_digest_lists_messages() {
+        let long = "x".repeat(DIGEST_LINE_LENGTH + 10);
+        let queued = [
+            queued("message", "alice in Rust", "first\nline"),
+            queued("mention", "alice mentioned you in Rust", &long),
+            queued("message", "carol in Go", "third"),
+        ];
+        let (subject, body) = digest(&queued, 2);
+        assert_eq!(subject, "3 new messages, 1 mention");
+        let lines = body.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "alice in Rust: first line");
+        assert!(lines[1].ends_with("x..."));
+        assert_eq!(lines[2], "...and 1 more");
+    }
+
+    #[test]
+    fn test_batches_by_delivered_channels() {
+        let mut retried = queued("message", "alice in Rust", "first");
+        retried.delivered_channels = "smtp".to_string();
+        let batches = batches(vec![
+            retried.clone(),
+            queued("message", "alice in Rust", "second"),
+            retried,
+        ]);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[0][0].delivered_channels(), ["smtp"]);
+        assert!(batches[1][0].delivered_channels().is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+//! Pluggable delivery of notifications (password reset links, digests of missed messages
+//! etc.) to users
+//!
+//! Notifiers block while delivering, so they should be called from
+//! [actix_web::web::block] rather than directly from async code.
+use crate::errors::ShopError;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub mod dispatcher;
+pub mod push;
+pub mod smtp;
+
+/// Single notification for user
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub user_id: String,
+    pub username: String,
+    pub subject: String,
+    pub body: String,
+    /// E-mail address from notification settings of user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Push device token from notification settings of user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_token: Option<String>,
+}
+
+/// Outcome of delivering notification through one or more channels
+#[derive(Debug, Default)]
+pub struct Delivery {
+    /// Channels which delivered notification
+    pub delivered: Vec<&'static str>,
+    /// First error of channels which failed, [None] when every channel succeeded
+    pub error: Option<ShopError>,
+}
+
+/// Trait implemented by every notification delivery channel. Channels which need contact
+/// of user (e-mail, push token) succeed without sending anything when user has none.
+pub trait Notifier: Send + Sync {
+    /// Name of channel, as remembered for notifications delivered through it
+    fn channel(&self) -> &'static str;
+
+    /// Delivers notification to user
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError>;
+
+    /// Checks if channel would deliver notification to user with provided contacts, users
+    /// no channel reaches are not queued for
+    fn reaches(&self, _email: Option<&str>, _push_token: Option<&str>) -> bool {
+        true
+    }
+
+    /// Delivers notification through channels which are not in `delivered` yet, so retry
+    /// of notification is not repeated on channels which succeeded before
+    fn notify_except(&self, notification: &Notification, delivered: &[&str]) -> Delivery {
+        let mut delivery = Delivery::default();
+        if delivered.contains(&self.channel()) {
+            return delivery;
+        }
+        match self.notify(notification) {
+            Ok(()) => delivery.delivered.push(self.channel()),
+            Err(e) => delivery.error = Some(e),
+        }
+        delivery
+    }
+}
+
+/// Notifier that only prints notifications to stdout, used for development
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn channel(&self) -> &'static str {
+        "log"
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError> {
+        println!(
+            "Notification for {} ({}): {}\n{}",
+            notification.username, notification.user_id, notification.subject, notification.body
+        );
+        Ok(())
+    }
+}
+
+/// Notifier that appends notifications as json lines to file, used for development and tests
+pub struct FileNotifier {
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileNotifier { path: path.into() }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn channel(&self) -> &'static str {
+        "file"
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError> {
+        let line = serde_json::to_string(notification)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| ShopError::ConnectionError(e.to_string()))
+    }
+}
+
+/// Notifier that delivers every notification through all of its notifiers
+pub struct MultiNotifier {
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl MultiNotifier {
+    pub fn new(notifiers: Vec<Arc<dyn Notifier>>) -> Self {
+        MultiNotifier { notifiers }
+    }
+}
+
+impl Notifier for MultiNotifier {
+    fn channel(&self) -> &'static str {
+        "multi"
+    }
+
+    /// Tries every notifier even if some of them fail, first error is returned
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError> {
+        match self.notify_except(notification, &[]).error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn reaches(&self, email: Option<&str>, push_token: Option<&str>) -> bool {
+        self.notifiers
+            .iter()
+            .any(|notifier| notifier.reaches(email, push_token))
+    }
+
+    fn notify_except(&self, notification: &Notification, delivered: &[&str]) -> Delivery {
+        let mut delivery = Delivery::default();
+        for notifier in &self.notifiers {
+            let single = notifier.notify_except(notification, delivered);
+            delivery.delivered.extend(single.delivered);
+            if delivery.error.is_none() {
+                delivery.error = single.error;
+            }
+        }
+        delivery
+    }
+}
+
+/// Creates notifier configured by `NOTIFIER` variable from .env file, which is comma
+/// separated list of channels, e.g. `smtp,push`
+/// * `log` (default) - [LogNotifier]
+/// * `file` - [FileNotifier] writing into `NOTIFIER_FILE` (default `notifications.log`)
+/// * `smtp` - [smtp::SmtpNotifier], see [smtp::SmtpNotifier::from_env]
+/// * `push` - [push::PushNotifier], see [push::PushNotifier::from_env]
+pub fn from_env() -> Arc<dyn Notifier> {
+    let channels = dotenv::var("NOTIFIER").unwrap_or_else(|_| "log".into());
+    let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+    for channel in channels.split(',').map(str::trim) {
+        match channel {
+            "log" => notifiers.push(Arc::new(LogNotifier)),
+            "file" => notifiers.push(Arc::new(FileNotifier::new(
+                dotenv::var("NOTIFIER_FILE").unwrap_or_else(|_| "notifications.log".into()),
+            ))),
+            "smtp" => notifiers.push(Arc::new(smtp::SmtpNotifier::from_env())),
+            "push" => match push::PushNotifier::from_env() {
+                Some(notifier) => notifiers.push(Arc::new(notifier)),
+                None => println!("Push notifier needs PUSH_ENDPOINT, it is turned off"),
+            },
+            "" => (),
+            channel => println!("Unknown notifier {}, it is ignored", channel),
+        }
+    }
+    match notifiers.len() {
+        0 => Arc::new(LogNotifier),
+        1 => notifiers.remove(0),
+        _ => Arc::new(MultiNotifier::new(notifiers)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_notifier_appends_lines() {
+        let path = std::env::temp_dir().join(format!("notifier-{}.log", uuid::Uuid::new_v4()));
+        let notifier = FileNotifier::new(&path);
+        let notification = Notification {
+            user_id: "id".to_string(),
+            username: "test_user".to_string(),
+            subject: "Subject".to_string(),
+            body: "Body".to_string(),
+            email: None,
+            push_token: None,
+        };
+        notifier.notify(&notification).unwrap();
+        notifier.notify(&notification).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("\"subject\":\"Subject\""));
+    }
+
+    /// Notifier counting its deliveries, optionally failing every one
+    struct CountingNotifier {
+        channel: &'static str,
+        fails: bool,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl Notifier for CountingNotifier {
+        fn channel(&self) -> &'static str {
+            self.channel
+        }
+
+        fn notify(&self, _: &Notification) -> Result<(), ShopError> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if self.fails {
+                return Err(ShopError::ConnectionError("down".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_multi_notifier_skips_delivered_channels() {
+        let counting = |channel, fails| {
+            Arc::new(CountingNotifier {
+                channel,
+                fails,
+                calls: Default::default(),
+            })
+        };
+        let smtp = counting("smtp", false);
+        let push = counting("push", true);
+        let notifier = MultiNotifier::new(vec![smtp.clone(), push.clone()]);
+        let notification = Notification {
+            user_id: "id".to_string(),
+            username: "test_user".to_string(),
+            subject: "Subject".to_string(),
+            body: "Body".to_string(),
+            email: None,
+            push_token: None,
+        };
+        let first = notifier.notify_except(&notification, &[]);
+        assert_eq!(first.delivered, ["smtp"]);
+        assert!(first.error.is_some());
+        let retry = notifier.notify_except(&notification, &first.delivered);
+        assert!(retry.delivered.is_empty());
+        assert!(retry.error.is_some());
+        let calls =
+            |notifier: &CountingNotifier| notifier.calls.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(calls(&smtp), 1);
+        assert_eq!(calls(&push), 2);
+    }
+}
@@ -0,0 +1,150 @@
+//! Push notifications sent as JSON to HTTP gateway
+//!
+//! Payload has the shape of FCM legacy HTTP API, which is also accepted by most Web Push
+//! gateways:
+//! ```
+//! {
+//!     "to": "device token of user",
+//!     "notification": { "title": "alice mentioned you in Rust", "body": "@bob hi" },
+//!     "data": { "user_id": "36ba8e1c-4ef8-4b0b-9d25-25c2c0a89b7b" }
+//! }
+//! ```
+use super::{Notification, Notifier};
+use crate::errors::ShopError;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Notifier that posts payload to configured endpoint for users with push token
+pub struct PushNotifier {
+    endpoint: String,
+    /// Value of `Authorization` header, e.g. `key=<server key>`
+    authorization: Option<String>,
+    timeout: Duration,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushPayload<'a> {
+    pub to: &'a str,
+    pub notification: PushContent<'a>,
+    pub data: PushData<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushContent<'a> {
+    pub title: &'a str,
+    pub body: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushData<'a> {
+    pub user_id: &'a str,
+}
+
+impl<'a> PushPayload<'a> {
+    /// Payload for notification, [None] when user has no push token
+    pub fn new(notification: &'a Notification) -> Option<Self> {
+        Some(PushPayload {
+            to: notification.push_token.as_deref()?,
+            notification: PushContent {
+                title: &notification.subject,
+                body: &notification.body,
+            },
+            data: PushData {
+                user_id: &notification.user_id,
+            },
+        })
+    }
+}
+
+impl PushNotifier {
+    pub fn new(
+        endpoint: impl Into<String>,
+        authorization: Option<String>,
+        timeout: Duration,
+    ) -> Self {
+        PushNotifier {
+            endpoint: endpoint.into(),
+            authorization,
+            timeout,
+        }
+    }
+
+    /// Reads configuration from `PUSH_ENDPOINT`, `PUSH_AUTHORIZATION` (optional) and
+    /// `PUSH_TIMEOUT_IN_SECONDS` (default 10)
+    /// # Returns
+    /// * notifier, or [None] when endpoint is not configured: [Option]
+    pub fn from_env() -> Option<Self> {
+        let endpoint = dotenv::var("PUSH_ENDPOINT").ok()?;
+        let timeout = dotenv::var("PUSH_TIMEOUT_IN_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10);
+        Some(PushNotifier::new(
+            endpoint,
+            dotenv::var("PUSH_AUTHORIZATION").ok(),
+            Duration::from_secs(timeout),
+        ))
+    }
+}
+
+impl Notifier for PushNotifier {
+    fn channel(&self) -> &'static str {
+        "push"
+    }
+
+    fn reaches(&self, _email: Option<&str>, push_token: Option<&str>) -> bool {
+        push_token.is_some()
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError> {
+        let payload = match PushPayload::new(notification) {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+        // blocking client runs its own runtime, so it is created on the calling thread
+        // instead of being shared with async code
+        let client = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?;
+        let mut request = client.post(&self.endpoint).json(&payload);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+        let status = request.send()?.status();
+        if !status.is_success() {
+            return Err(ShopError::ConnectionError(format!(
+                "Push gateway responded with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_payload() {
+        let mut notification = Notification {
+            user_id: "id".to_string(),
+            username: "test_user".to_string(),
+            subject: "Subject".to_string(),
+            body: "Body".to_string(),
+            email: None,
+            push_token: None,
+        };
+        assert!(PushPayload::new(&notification).is_none());
+        notification.push_token = Some("token".to_string());
+        let payload = serde_json::to_value(PushPayload::new(&notification)).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "to": "token",
+                "notification": { "title": "Subject", "body": "Body" },
+                "data": { "user_id": "id" }
+            })
+        );
+    }
+}
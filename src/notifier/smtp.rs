@@ -0,0 +1,246 @@
+//! E-mail notifications sent over plain SMTP
+//!
+//! Client speaks only the basic protocol (no TLS, no authentication), so it is meant for
+//! local relay or SMTP sink used in development, e.g. MailHog.
+use super::{Notification, Notifier};
+use crate::errors::ShopError;
+use chrono::Utc;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Notifier that sends e-mail to address from notification settings of user
+pub struct SmtpNotifier {
+    /// Address of SMTP server, `host:port`
+    server: String,
+    /// Sender address
+    from: String,
+    timeout: Duration,
+}
+
+impl SmtpNotifier {
+    pub fn new(server: impl Into<String>, from: impl Into<String>, timeout: Duration) -> Self {
+        SmtpNotifier {
+            server: server.into(),
+            from: from.into(),
+            timeout,
+        }
+    }
+
+    /// Reads configuration from `SMTP_HOST` (default `localhost`), `SMTP_PORT` (25),
+    /// `SMTP_FROM` (`chat@localhost`) and `SMTP_TIMEOUT_IN_SECONDS` (10)
+    pub fn from_env() -> Self {
+        let host = dotenv::var("SMTP_HOST").unwrap_or_else(|_| "localhost".into());
+        let port = dotenv::var("SMTP_PORT").unwrap_or_else(|_| "25".into());
+        let timeout = dotenv::var("SMTP_TIMEOUT_IN_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10);
+        SmtpNotifier::new(
+            format!("{}:{}", host, port),
+            dotenv::var("SMTP_FROM").unwrap_or_else(|_| "chat@localhost".into()),
+            Duration::from_secs(timeout),
+        )
+    }
+
+    /// Runs single SMTP session delivering `message` to `to`
+    fn send(&self, to: &str, message: &str) -> io::Result<()> {
+        let stream = TcpStream::connect(&self.server)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        expect(&mut reader, 220)?;
+        command(&mut writer, &mut reader, "EHLO localhost", 250)?;
+        command(
+            &mut writer,
+            &mut reader,
+            &format!("MAIL FROM:<{}>", self.from),
+            250,
+        )?;
+        command(&mut writer, &mut reader, &format!("RCPT TO:<{}>", to), 250)?;
+        command(&mut writer, &mut reader, "DATA", 354)?;
+        writer.write_all(message.as_bytes())?;
+        command(&mut writer, &mut reader, ".", 250)?;
+        // message is accepted already, failing goodbye does not matter
+        let _ = command(&mut writer, &mut reader, "QUIT", 221);
+        Ok(())
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn channel(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn reaches(&self, email: Option<&str>, _push_token: Option<&str>) -> bool {
+        email.is_some()
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<(), ShopError> {
+        let to = match &notification.email {
+            Some(email) => email,
+            None => return Ok(()),
+        };
+        let message = message(&self.from, to, notification);
+        self.send(to, &message)
+            .map_err(|e| ShopError::ConnectionError(format!("SMTP delivery failed: {}", e)))
+    }
+}
+
+/// Sends single command line and checks reply code
+fn command(
+    writer: &mut impl Write,
+    reader: &mut impl BufRead,
+    line: &str,
+    code: u16,
+) -> io::Result<()> {
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\r\n")?;
+    writer.flush()?;
+    expect(reader, code)
+}
+
+/// Reads reply, which may span more lines (`250-...`), and checks its code
+fn expect(reader: &mut impl BufRead, code: u16) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SMTP server closed connection",
+            ));
+        }
+        let line = line.trim_end();
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match line.get(..3).and_then(|reply| reply.parse::<u16>().ok()) {
+            Some(reply) if reply == code => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("SMTP server replied: {}", line),
+            )),
+        };
+    }
+}
+
+/// Whole e-mail in format sent after `DATA`, with lines starting with `.` escaped
+fn message(from: &str, to: &str, notification: &Notification) -> String {
+    let domain = from.rsplit('@').next().unwrap_or("localhost");
+    let mut message = format!(
+        "Date: {}\r\nFrom: {}\r\nTo: {}\r\nSubject: {}\r\nMessage-ID: <{}@{}>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n",
+        Utc::now().to_rfc2822(),
+        from,
+        to,
+        encode_header(&notification.subject),
+        Uuid::new_v4(),
+        domain
+    );
+    for line in notification.body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+/// Header value on single line, encoded as RFC 2047 word when it is not plain ASCII
+fn encode_header(value: &str) -> String {
+    let value = value.replace(|c: char| c.is_control(), " ");
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::encode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accepts single SMTP session and returns everything client sent
+    fn sink(listener: TcpListener) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = Vec::new();
+            let mut in_data = false;
+            writer.write_all(b"220 sink ready\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-sink\r\n250 8BITMIME\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                received.push(line);
+                writer.write_all(reply).unwrap();
+                if received.last().map(String::as_str) == Some("QUIT") {
+                    break;
+                }
+            }
+            received
+        })
+    }
+
+    #[test]
+    fn test_smtp_notifier_sends_to_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let session = sink(listener);
+        let notifier = SmtpNotifier::new(server, "chat@example.com", Duration::from_secs(5));
+        let notification = Notification {
+            user_id: "id".to_string(),
+            username: "test_user".to_string(),
+            subject: "Zpráva".to_string(),
+            body: "Hello\n.hidden".to_string(),
+            email: Some("user@example.com".to_string()),
+            push_token: None,
+        };
+        notifier.notify(&notification).unwrap();
+        let received = session.join().unwrap();
+        assert!(received.contains(&"RCPT TO:<user@example.com>".to_string()));
+        assert!(received.contains(&"Subject: =?UTF-8?B?WnByw6F2YQ==?=".to_string()));
+        assert!(received.contains(&"..hidden".to_string()));
+        assert_eq!(received.last().map(String::as_str), Some("QUIT"));
+    }
+
+    #[test]
+    fn test_smtp_notifier_skips_users_without_email() {
+        let notifier = SmtpNotifier::new("127.0.0.1:1", "chat@example.com", Duration::from_secs(1));
+        let notification = Notification {
+            user_id: "id".to_string(),
+            username: "test_user".to_string(),
+            subject: "Subject".to_string(),
+            body: "Body".to_string(),
+            email: None,
+            push_token: None,
+        };
+        assert!(notifier.notify(&notification).is_ok());
+    }
+}
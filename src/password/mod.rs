@@ -0,0 +1,164 @@
+//! Password hashing module, relying on [argon2] crate.
+//!
+//! New hashes are always argon2id, with parameters read from .env file.
+//! Legacy bcrypt hashes can still be verified, [needs_rehash] reports them so they
+//! can be replaced on next successful login.
+use crate::errors::ShopError;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+
+/// Default memory cost in KiB, as recommended by OWASP for argon2id
+pub const DEFAULT_MEMORY_KIB: u32 = 19456;
+/// Default number of iterations
+pub const DEFAULT_ITERATIONS: u32 = 2;
+/// Default degree of parallelism
+pub const DEFAULT_PARALLELISM: u32 = 1;
+/// Stored instead of hash for accounts that cannot log in with password
+/// (created by single sign-on), no password matches it
+pub const NO_PASSWORD: &str = "!";
+
+lazy_static! {
+    static ref POLICY: HashPolicy = HashPolicy::from_env();
+}
+
+/// Parameters used for hashing new passwords
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashPolicy {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl HashPolicy {
+    /// Reads policy from `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`
+    /// variables, falling back to defaults
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u32| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        HashPolicy {
+            memory_kib: read("ARGON2_MEMORY_KIB", DEFAULT_MEMORY_KIB),
+            iterations: read("ARGON2_ITERATIONS", DEFAULT_ITERATIONS),
+            parallelism: read("ARGON2_PARALLELISM", DEFAULT_PARALLELISM),
+        }
+    }
+
+    fn hasher(&self) -> Result<Argon2<'static>, ShopError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    /// Hashes password into PHC string
+    pub fn hash(&self, password: &str) -> Result<String, ShopError> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(self
+            .hasher()?
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    }
+
+    /// Checks if stored hash was made with other algorithm or weaker parameters than this
+    /// policy, hashes stronger than policy are kept
+    pub fn needs_rehash(&self, stored: &str) -> bool {
+        let hash = match PasswordHash::new(stored) {
+            Ok(hash) => hash,
+            // bcrypt hashes are not PHC strings
+            Err(_) => return true,
+        };
+        if hash.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                params.m_cost() < self.memory_kib
+                    || params.t_cost() < self.iterations
+                    || params.p_cost() < self.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// Checks if stored hash is legacy bcrypt hash
+fn is_bcrypt(stored: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| stored.starts_with(prefix))
+}
+
+/// Hashes password with argon2id and configured parameters
+pub fn hash(password: &str) -> Result<String, ShopError> {
+    POLICY.hash(password)
+}
+
+/// Verifies password against stored argon2 or legacy bcrypt hash
+pub fn verify(password: &str, stored: &str) -> Result<bool, ShopError> {
+    if stored == NO_PASSWORD {
+        return Ok(false);
+    }
+    if is_bcrypt(stored) {
+        return Ok(bcrypt::verify(password, stored)?);
+    }
+    let hash = PasswordHash::new(stored)?;
+    match Argon2::default().verify_password(password.as_bytes(), &hash) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks if stored hash should be replaced by hash made with current configuration
+pub fn needs_rehash(stored: &str) -> bool {
+    if stored == NO_PASSWORD {
+        return false;
+    }
+    POLICY.needs_rehash(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: HashPolicy = HashPolicy {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_argon2_hash_verifies() {
+        let stored = POLICY.hash("legit_pass").unwrap();
+        assert!(stored.starts_with("$argon2id$"));
+        assert!(verify("legit_pass", &stored).unwrap());
+        assert!(!verify("wrong_pass", &stored).unwrap());
+        assert!(!POLICY.needs_rehash(&stored));
+    }
+
+    #[test]
+    fn test_legacy_bcrypt_hash_verifies_and_needs_rehash() {
+        let stored = bcrypt::hash("legit_pass", 4).unwrap();
+        assert!(verify("legit_pass", &stored).unwrap());
+        assert!(!verify("wrong_pass", &stored).unwrap());
+        assert!(POLICY.needs_rehash(&stored));
+    }
+
+    #[test]
+    fn test_only_weaker_parameters_need_rehash() {
+        let stored = POLICY.hash("legit_pass").unwrap();
+        let stronger = HashPolicy {
+            iterations: 2,
+            ..POLICY
+        };
+        assert!(stronger.needs_rehash(&stored));
+        let weaker = HashPolicy {
+            memory_kib: 512,
+            ..POLICY
+        };
+        assert!(!weaker.needs_rehash(&stored));
+    }
+}
@@ -0,0 +1,488 @@
+//! Throttling of HTTP API requests
+//!
+//! [HttpRateLimiter] is actix middleware which matches every request against [RouteRule]s,
+//! counts it in [RateLimitBackend] and answers with 429 [ShopError::TooManyRequests] when
+//! quota of the rule is used up. Every response of limited route carries `RateLimit-Limit`,
+//! `RateLimit-Remaining` and `RateLimit-Reset` headers, rejected ones also `Retry-After`.
+//!
+//! Counters are kept in memory by default ([MemoryBackend]), other storage (e.g. shared
+//! between several instances of the app) can be plugged in by implementing
+//! [RateLimitBackend].
+use crate::errors::ShopError;
+use crate::jwt;
+use crate::models::bot::ApiKey;
+use crate::models::user::API_KEY_HEADER;
+use crate::utils::{service_client_ip, token, AppState};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::web::{self, Data};
+use actix_web::{Error, ResponseError};
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const LIMIT_HEADER: &str = "ratelimit-limit";
+pub const REMAINING_HEADER: &str = "ratelimit-remaining";
+pub const RESET_HEADER: &str = "ratelimit-reset";
+
+/// Map is cleaned from expired windows once it grows over this size
+const PRUNE_THRESHOLD: usize = 10_000;
+/// How long verified API key is trusted without asking database again
+const VERIFIED_KEY_TTL: Duration = Duration::from_secs(60);
+
+/// Number of requests allowed within window, written as `requests/seconds`, e.g. `10/60`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub requests: u32,
+    pub window: Duration,
+}
+
+impl FromStr for Quota {
+    type Err = ShopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ShopError::ParseError(format!("Invalid rate limit quota: {}", s));
+        let (requests, seconds) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse()?;
+        let seconds: u64 = seconds.trim().parse()?;
+        if requests == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(Quota {
+            requests,
+            window: Duration::from_secs(seconds),
+        })
+    }
+}
+
+/// What requests are counted together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBy {
+    Ip,
+    /// Logged in user or bot of valid API key, other requests are counted by IP
+    User,
+}
+
+/// Quota of group of routes, matched by path prefix
+#[derive(Debug, Clone)]
+pub struct RouteRule {
+    pub name: &'static str,
+    /// Prefixes of path without leading slashes, e.g. `chat/`
+    pub prefixes: &'static [&'static str],
+    pub key_by: KeyBy,
+    /// [None] when routes are not limited
+    pub quota: Option<Quota>,
+}
+
+impl RouteRule {
+    fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        self.prefixes.iter().any(|prefix| path.starts_with(prefix))
+    }
+}
+
+/// Result of counting single request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until quota is renewed
+    pub reset: Duration,
+}
+
+impl Decision {
+    /// Seconds until reset, rounded up
+    fn reset_seconds(&self) -> u64 {
+        self.reset.as_secs() + u64::from(self.reset.subsec_nanos() > 0)
+    }
+
+    fn write_headers(&self, headers: &mut HeaderMap) {
+        let values = [
+            (LIMIT_HEADER, self.limit as u64),
+            (REMAINING_HEADER, self.remaining as u64),
+            (RESET_HEADER, self.reset_seconds()),
+        ];
+        for (name, value) in values {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        }
+    }
+}
+
+/// Storage of request counters
+pub trait RateLimitBackend: Send + Sync {
+    /// Counts request identified by `key` against `quota`
+    fn hit(&self, key: &str, quota: &Quota) -> Decision;
+}
+
+/// Fixed window counter
+#[derive(Debug)]
+struct Window {
+    count: u32,
+    resets_at: Instant,
+}
+
+/// Backend keeping counters in memory of single process
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl MemoryBackend {
+    fn hit_at(&self, key: &str, quota: &Quota, now: Instant) -> Decision {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_, window| window.resets_at > now);
+        }
+        let window = windows.entry(key.to_string()).or_insert(Window {
+            count: 0,
+            resets_at: now + quota.window,
+        });
+        if window.resets_at <= now {
+            window.count = 0;
+            window.resets_at = now + quota.window;
+        }
+        window.count = window.count.saturating_add(1);
+        Decision {
+            allowed: window.count <= quota.requests,
+            limit: quota.requests,
+            remaining: quota.requests.saturating_sub(window.count),
+            reset: window.resets_at.saturating_duration_since(now),
+        }
+    }
+}
+
+impl RateLimitBackend for MemoryBackend {
+    fn hit(&self, key: &str, quota: &Quota) -> Decision {
+        self.hit_at(key, quota, Instant::now())
+    }
+}
+
+/// API keys which were found in database recently, by hash of the key
+#[derive(Debug, Default)]
+struct VerifiedKeys {
+    owners: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl VerifiedKeys {
+    /// Id of owner of the key, [None] if key wasn't verified within [VERIFIED_KEY_TTL]
+    fn get_at(&self, key_hash: &str, now: Instant) -> Option<String> {
+        let owners = self.owners.lock().unwrap();
+        let (user_id, verified_at) = owners.get(key_hash)?;
+        (now.duration_since(*verified_at) < VERIFIED_KEY_TTL).then(|| user_id.clone())
+    }
+
+    fn insert_at(&self, key_hash: String, user_id: String, now: Instant) {
+        let mut owners = self.owners.lock().unwrap();
+        if owners.len() > PRUNE_THRESHOLD {
+            owners
+                .retain(|_, (_, verified_at)| now.duration_since(*verified_at) < VERIFIED_KEY_TTL);
+        }
+        owners.insert(key_hash, (user_id, now));
+    }
+}
+
+/// Middleware limiting requests, shared between all workers
+#[derive(Clone)]
+pub struct HttpRateLimiter {
+    rules: Arc<Vec<RouteRule>>,
+    backend: Arc<dyn RateLimitBackend>,
+    verified_keys: Arc<VerifiedKeys>,
+}
+
+impl HttpRateLimiter {
+    pub fn new(rules: Vec<RouteRule>, backend: Arc<dyn RateLimitBackend>) -> Self {
+        HttpRateLimiter {
+            rules: Arc::new(rules),
+            backend,
+            verified_keys: Arc::default(),
+        }
+    }
+
+    /// Limiter with [MemoryBackend] and rules read from .env file, quotas are written as
+    /// `requests/seconds`, or `off` to not limit routes of the rule:
+    /// * `RATE_LIMIT_LOGIN` (default `10/60`) - login and single sign-on, by IP
+    /// * `RATE_LIMIT_REGISTER` (`5/600`) - registration and password reset, by IP
+    /// * `RATE_LIMIT_HOOKS` (`60/60`) - incoming webhooks, by IP
+    /// * `RATE_LIMIT_CHAT` (`120/60`) - chat routes, by user
+    /// * `RATE_LIMIT_DEFAULT` (`300/60`) - every other route, by user
+    pub fn from_env() -> Self {
+        let rule = |name: &'static str, prefixes, key_by, default: &str| {
+            let env = format!("RATE_LIMIT_{}", name.to_uppercase());
+            let quota = dotenv::var(&env).unwrap_or_else(|_| default.to_string());
+            let quota = match quota.as_str() {
+                "off" => None,
+                quota => Some(quota.parse().unwrap_or_else(|e| {
+                    println!("{}, using {}", e, default);
+                    default.parse().expect("Default quota is valid")
+                })),
+            };
+            RouteRule {
+                name,
+                prefixes,
+                key_by,
+                quota,
+            }
+        };
+        let rules = vec![
+            rule("login", &["login", "oidc/"], KeyBy::Ip, "10/60"),
+            rule("register", &["register", "password/"], KeyBy::Ip, "5/600"),
+            rule("hooks", &["hooks/"], KeyBy::Ip, "60/60"),
+            rule("chat", &["chat/"], KeyBy::User, "120/60"),
+            rule("default", &[""], KeyBy::User, "300/60"),
+        ];
+        HttpRateLimiter::new(rules, Arc::new(MemoryBackend::default()))
+    }
+
+    /// First rule matching path, rules are ordered from most specific
+    fn rule(&self, path: &str) -> Option<&RouteRule> {
+        self.rules.iter().find(|rule| rule.matches(path))
+    }
+
+    /// Id of bot owning active API key, looked up in database unless verified recently.
+    /// Lookups are counted by IP against quota of the rule, so that random keys can't
+    /// be used to load database.
+    async fn api_key_owner(
+        &self,
+        rule: &RouteRule,
+        quota: &Quota,
+        req: &ServiceRequest,
+        api_key: &str,
+    ) -> Option<String> {
+        let key_hash = token::hash(api_key);
+        if let Some(user_id) = self.verified_keys.get_at(&key_hash, Instant::now()) {
+            return Some(user_id);
+        }
+        let lookup = format!("{}:key-lookup:ip:{}", rule.name, service_client_ip(req));
+        if !self.backend.hit(&lookup, quota).allowed {
+            return None;
+        }
+        let db = req.app_data::<Data<AppState>>()?.static_data.db.clone();
+        let api_key = api_key.to_string();
+        let user_id = web::block(move || {
+            let connection = db.get().ok()?;
+            ApiKey::owner_id(&connection, &api_key).ok().flatten()
+        })
+        .await
+        .ok()??;
+        self.verified_keys
+            .insert_at(key_hash, user_id.clone(), Instant::now());
+        Some(user_id)
+    }
+
+    /// Key of counter of request. API key is used only if it is active, so random keys
+    /// cannot get fresh quota. JWT is only verified, without checking that user still
+    /// exists, because that would cost database query for every request
+    async fn key(&self, rule: &RouteRule, quota: &Quota, req: &ServiceRequest) -> String {
+        if rule.key_by == KeyBy::User {
+            let headers = req.headers();
+            if let Some(Ok(api_key)) = headers.get(API_KEY_HEADER).map(|key| key.to_str()) {
+                if let Some(user_id) = self.api_key_owner(rule, quota, req, api_key).await {
+                    return format!("{}:user:{}", rule.name, user_id);
+                }
+            }
+            if let Some(Ok(user_jwt)) = headers.get("jwt").map(|jwt| jwt.to_str()) {
+                if let Ok(user) = jwt::verify(user_jwt.to_string()) {
+                    return format!("{}:user:{}", rule.name, user.id);
+                }
+            }
+        }
+        format!("{}:ip:{}", rule.name, service_client_ip(req))
+    }
+
+    /// Counts request against quota of its rule
+    /// # Returns
+    /// * [None] when route is not limited
+    async fn check(&self, req: &ServiceRequest) -> Option<Decision> {
+        let rule = self.rule(req.path())?;
+        let quota = rule.quota.as_ref()?;
+        let key = self.key(rule, quota, req).await;
+        Some(self.backend.hit(&key, quota))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for HttpRateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+/// Service created by [HttpRateLimiter] for every worker
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: HttpRateLimiter,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let decision = match limiter.check(&req).await {
+                Some(decision) => decision,
+                None => return Ok(service.call(req).await?.map_into_left_body()),
+            };
+            if !decision.allowed {
+                let error = ShopError::TooManyRequests(decision.reset_seconds().max(1));
+                let mut response = error.error_response();
+                decision.write_headers(response.headers_mut());
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            let mut response = service.call(req).await?;
+            decision.write_headers(response.headers_mut());
+            Ok(response.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_parsing() {
+        assert_eq!(
+            "10/60".parse::<Quota>().unwrap(),
+            Quota {
+                requests: 10,
+                window: Duration::from_secs(60)
+            }
+        );
+        assert!("10".parse::<Quota>().is_err());
+        assert!("0/60".parse::<Quota>().is_err());
+        assert!("ten/60".parse::<Quota>().is_err());
+    }
+
+    #[test]
+    fn test_memory_backend_window() {
+        let backend = MemoryBackend::default();
+        let quota = Quota {
+            requests: 2,
+            window: Duration::from_secs(60),
+        };
+        let now = Instant::now();
+        let first = backend.hit_at("login:ip:1", &quota, now);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert!(backend.hit_at("login:ip:1", &quota, now).allowed);
+        let rejected = backend.hit_at("login:ip:1", &quota, now + Duration::from_secs(20));
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.reset_seconds(), 40);
+        assert!(backend.hit_at("login:ip:2", &quota, now).allowed);
+        assert!(
+            backend
+                .hit_at("login:ip:1", &quota, now + Duration::from_secs(60))
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let quota = Some("1/1".parse().unwrap());
+        let limiter = HttpRateLimiter::new(
+            vec![
+                RouteRule {
+                    name: "login",
+                    prefixes: &["login"],
+                    key_by: KeyBy::Ip,
+                    quota,
+                },
+                RouteRule {
+                    name: "default",
+                    prefixes: &[""],
+                    key_by: KeyBy::User,
+                    quota,
+                },
+            ],
+            Arc::new(MemoryBackend::default()),
+        );
+        assert_eq!(limiter.rule("//login/2fa").map(|r| r.name), Some("login"));
+        assert_eq!(
+            limiter.rule("//chat/addGroup").map(|r| r.name),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn test_unverified_api_key_is_counted_by_ip() {
+        let rule = RouteRule {
+            name: "chat",
+            prefixes: &["chat/"],
+            key_by: KeyBy::User,
+            quota: Some("1/60".parse().unwrap()),
+        };
+        let quota = rule.quota.unwrap();
+        let backend = Arc::new(MemoryBackend::default());
+        let limiter = HttpRateLimiter::new(vec![rule.clone()], backend.clone());
+        let request = |api_key: &str| {
+            actix_web::test::TestRequest::with_uri("/chat/send")
+                .insert_header((API_KEY_HEADER, api_key))
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .to_srv_request()
+        };
+        actix_web::rt::System::new().block_on(async {
+            // without database keys cannot be verified, so every random key shares IP quota
+            for api_key in ["random-1", "random-2"] {
+                assert_eq!(
+                    limiter.key(&rule, &quota, &request(api_key)).await,
+                    "chat:ip:203.0.113.7"
+                );
+            }
+            // lookups of unknown keys use up quota of IP too
+            let lookups = backend.hit_at("chat:key-lookup:ip:203.0.113.7", &quota, Instant::now());
+            assert!(!lookups.allowed);
+            assert!(limiter.check(&request("random-1")).await.unwrap().allowed);
+            assert!(!limiter.check(&request("random-2")).await.unwrap().allowed);
+
+            limiter.verified_keys.insert_at(
+                token::hash("valid"),
+                "bot-1".to_string(),
+                Instant::now(),
+            );
+            assert_eq!(
+                limiter.key(&rule, &quota, &request("valid")).await,
+                "chat:user:bot-1"
+            );
+        });
+    }
+
+    #[test]
+    fn test_verified_keys_expire() {
+        let keys = VerifiedKeys::default();
+        let now = Instant::now();
+        keys.insert_at("hash".to_string(), "bot-1".to_string(), now);
+        assert_eq!(keys.get_at("hash", now), Some("bot-1".to_string()));
+        assert_eq!(keys.get_at("other", now), None);
+        assert_eq!(keys.get_at("hash", now + VERIFIED_KEY_TTL), None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Rate limiting of chat messages sent over websocket
+//!
+//! Every socket has its own [TokenBucket], checked by [WsConn](crate::models::ws::WsConn)
+//! before frame reaches the lobby. [MessageLimiter] in the lobby additionally limits users
+//! (across all their sockets) and rooms. Group admins can also turn on slow mode, which
+//! lets members send only one message per configured number of seconds.
+//!
+//! Rejected frames are answered with [ChatEvent::RateLimited](crate::models::events::ChatEvent)
+//! and counted as strikes, socket exceeding [MessagePolicy::max_strikes] is closed.
+//!
+//! Requests to HTTP API are limited by [http::HttpRateLimiter] middleware.
+use crate::models::events::ChatEvent;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub mod http;
+
+/// Size and refill speed of [TokenBucket]
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    /// Messages which can be sent at once
+    pub burst: u32,
+    /// Messages refilled every minute
+    pub per_minute: u32,
+}
+
+/// Bucket which holds up to `burst` tokens and refills them continuously,
+/// every message takes one token
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    config: BucketConfig,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Creates full bucket
+    pub fn new(config: BucketConfig, now: Instant) -> Self {
+        TokenBucket {
+            config,
+            tokens: config.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let refilled = elapsed * self.config.per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + refilled).min(self.config.burst as f64);
+        self.updated = now;
+    }
+
+    /// Takes one token
+    /// # Returns
+    /// ## On faliure
+    /// * time after which token will be available: [Duration]
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if self.config.per_minute == 0 {
+            return Err(Duration::from_secs(60));
+        }
+        let missing = 1.0 - self.tokens;
+        Err(Duration::from_secs_f64(
+            missing * 60.0 / self.config.per_minute as f64,
+        ))
+    }
+
+    /// True when bucket refilled completely, so it can be forgotten
+    pub fn is_full(&self, now: Instant) -> bool {
+        let mut bucket = self.clone();
+        bucket.refill(now);
+        bucket.tokens >= bucket.config.burst as f64
+    }
+}
+
+/// Counter of rejected messages within sliding window
+#[derive(Debug, Clone, Default)]
+pub struct Strikes {
+    hits: Vec<Instant>,
+}
+
+impl Strikes {
+    /// Records strike and returns number of strikes within `window`
+    pub fn hit(&mut self, now: Instant, window: Duration) -> u32 {
+        self.hits
+            .retain(|hit| now.saturating_duration_since(*hit) < window);
+        self.hits.push(now);
+        self.hits.len() as u32
+    }
+}
+
+/// Limits of chat messages, read from .env file
+#[derive(Debug, Clone)]
+pub struct MessagePolicy {
+    pub connection: BucketConfig,
+    pub user: BucketConfig,
+    pub room: BucketConfig,
+    /// Rejected messages within [MessagePolicy::strike_window] before socket is closed
+    pub max_strikes: u32,
+    pub strike_window: Duration,
+}
+
+impl MessagePolicy {
+    /// Reads policy from `WS_CONNECTION_BURST` (default 10), `WS_CONNECTION_PER_MINUTE` (60),
+    /// `WS_USER_BURST` (15), `WS_USER_PER_MINUTE` (90), `WS_ROOM_BURST` (50),
+    /// `WS_ROOM_PER_MINUTE` (600), `WS_MAX_STRIKES` (10) and
+    /// `WS_STRIKE_WINDOW_IN_SECONDS` (60)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u32| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let bucket = |prefix: &str, burst: u32, per_minute: u32| BucketConfig {
+            burst: read(&format!("{}_BURST", prefix), burst).max(1),
+            per_minute: read(&format!("{}_PER_MINUTE", prefix), per_minute),
+        };
+        MessagePolicy {
+            connection: bucket("WS_CONNECTION", 10, 60),
+            user: bucket("WS_USER", 15, 90),
+            room: bucket("WS_ROOM", 50, 600),
+            max_strikes: read("WS_MAX_STRIKES", 10).max(1),
+            strike_window: Duration::from_secs(read("WS_STRIKE_WINDOW_IN_SECONDS", 60) as u64),
+        }
+    }
+}
+
+/// Which limit rejected message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitScope {
+    Connection,
+    User,
+    Room,
+    SlowMode,
+}
+
+/// Rejection of message by one of limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limited {
+    pub scope: LimitScope,
+    pub retry_after: Duration,
+}
+
+impl Limited {
+    /// Event sent to socket whose message was rejected
+    pub fn into_event(self) -> ChatEvent {
+        let seconds = self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        let body = match self.scope {
+            LimitScope::Connection | LimitScope::User => {
+                format!("You are sending messages too fast, wait {}s", seconds)
+            }
+            LimitScope::Room => format!("This room is too busy, wait {}s", seconds),
+            LimitScope::SlowMode => format!("Slow mode is on, wait {}s", seconds),
+        };
+        ChatEvent::RateLimited {
+            scope: self.scope,
+            retry_after_ms: self.retry_after.as_millis() as u64,
+            body,
+        }
+    }
+}
+
+/// Limits of users and rooms, kept by lobby
+#[derive(Debug)]
+pub struct MessageLimiter {
+    policy: MessagePolicy,
+    users: HashMap<Uuid, TokenBucket>,
+    rooms: HashMap<Uuid, TokenBucket>,
+    /// Last message of user in room with slow mode and interval of room at that time
+    slow_mode: HashMap<(Uuid, Uuid), (Instant, Duration)>,
+    strikes: HashMap<Uuid, Strikes>,
+}
+
+impl MessageLimiter {
+    pub fn new(policy: MessagePolicy) -> Self {
+        MessageLimiter {
+            policy,
+            users: HashMap::new(),
+            rooms: HashMap::new(),
+            slow_mode: HashMap::new(),
+            strikes: HashMap::new(),
+        }
+    }
+
+    /// Takes token of user and room, room token is not taken when user is limited
+    pub fn check(&mut self, user_id: Uuid, room_id: Uuid, now: Instant) -> Result<(), Limited> {
+        let policy = &self.policy;
+        let user = self
+            .users
+            .entry(user_id)
+            .or_insert_with(|| TokenBucket::new(policy.user, now));
+        user.try_take(now).map_err(|retry_after| Limited {
+            scope: LimitScope::User,
+            retry_after,
+        })?;
+        let room = self
+            .rooms
+            .entry(room_id)
+            .or_insert_with(|| TokenBucket::new(policy.room, now));
+        room.try_take(now).map_err(|retry_after| Limited {
+            scope: LimitScope::Room,
+            retry_after,
+        })
+    }
+
+    /// Lets user send message into room with slow mode only once per `interval`
+    pub fn check_slow_mode(
+        &mut self,
+        user_id: Uuid,
+        room_id: Uuid,
+        interval: Duration,
+        now: Instant,
+    ) -> Result<(), Limited> {
+        if let Some((last, _)) = self.slow_mode.get(&(room_id, user_id)) {
+            let elapsed = now.saturating_duration_since(*last);
+            if elapsed < interval {
+                return Err(Limited {
+                    scope: LimitScope::SlowMode,
+                    retry_after: interval - elapsed,
+                });
+            }
+        }
+        self.slow_mode.insert((room_id, user_id), (now, interval));
+        Ok(())
+    }
+
+    /// Records rejected message of user
+    /// # Returns
+    /// * true when user exceeded [MessagePolicy::max_strikes] and should be disconnected
+    pub fn strike(&mut self, user_id: Uuid, now: Instant) -> bool {
+        let strikes = self.strikes.entry(user_id).or_default();
+        if strikes.hit(now, self.policy.strike_window) >= self.policy.max_strikes {
+            self.strikes.remove(&user_id);
+            return true;
+        }
+        false
+    }
+
+    /// Forgets state which no longer limits anyone, called when user leaves room. Buckets
+    /// are kept until they refill and slow mode until its interval passes, so reconnecting
+    /// does not reset limits.
+    pub fn forget(&mut self, now: Instant) {
+        self.slow_mode
+            .retain(|_, (last, interval)| now.saturating_duration_since(*last) < *interval);
+        self.users.retain(|_, bucket| !bucket.is_full(now));
+        self.rooms.retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let config = BucketConfig {
+            burst: 2,
+            per_minute: 60,
+        };
+        let mut bucket = TokenBucket::new(config, now);
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_ok());
+        assert_eq!(bucket.try_take(now), Err(Duration::from_secs(1)));
+        let later = now + Duration::from_millis(1500);
+        assert!(bucket.try_take(later).is_ok());
+        assert_eq!(bucket.try_take(later), Err(Duration::from_millis(500)));
+        assert!(bucket.is_full(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_limiter_slow_mode_and_strikes() {
+        let now = Instant::now();
+        let policy = MessagePolicy {
+            connection: BucketConfig {
+                burst: 1,
+                per_minute: 1,
+            },
+            user: BucketConfig {
+                burst: 1,
+                per_minute: 1,
+            },
+            room: BucketConfig {
+                burst: 10,
+                per_minute: 1,
+            },
+            max_strikes: 2,
+            strike_window: Duration::from_secs(60),
+        };
+        let mut limiter = MessageLimiter::new(policy);
+        let (user, room) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(limiter.check(user, room, now).is_ok());
+        assert_eq!(
+            limiter
+                .check(user, room, now)
+                .map_err(|limited| limited.scope),
+            Err(LimitScope::User)
+        );
+        let interval = Duration::from_secs(30);
+        assert!(limiter.check_slow_mode(user, room, interval, now).is_ok());
+        let limited = limiter
+            .check_slow_mode(user, room, interval, now + Duration::from_secs(10))
+            .unwrap_err();
+        assert_eq!(limited.retry_after, Duration::from_secs(20));
+        // leaving room keeps slow mode until interval passes
+        limiter.forget(now + Duration::from_secs(10));
+        assert!(limiter
+            .check_slow_mode(user, room, interval, now + Duration::from_secs(20))
+            .is_err());
+        limiter.forget(now + Duration::from_secs(30));
+        assert!(limiter.slow_mode.is_empty());
+        assert!(!limiter.strike(user, now));
+        assert!(limiter.strike(user, now));
+    }
+}
@@ -0,0 +1,78 @@
+//! Background purging of messages expired by retention policies of groups
+//!
+//! Job periodically replaces expired messages with tombstones, see
+//! [purge_expired](crate::models::retention::purge_expired), and tells connected members of
+//! each group which messages are gone with [ChatEvent::Deleted] event.
+use crate::errors::ShopError;
+use crate::models::events::ChatEvent;
+use crate::models::lobby::Lobby;
+use crate::models::messages::RoomEvent;
+use crate::models::retention;
+use crate::utils::AppState;
+use actix::Addr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Limits of retention job, read from .env file
+#[derive(Debug, Clone)]
+pub struct RetentionJob {
+    /// How often job looks for expired messages
+    pub interval: Duration,
+    /// Most messages purged in single run, the rest waits for next one
+    pub batch_size: i64,
+}
+
+impl RetentionJob {
+    /// Reads limits from `RETENTION_INTERVAL_IN_SECONDS` (default 60) and
+    /// `RETENTION_BATCH_SIZE` (500)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u64| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        RetentionJob {
+            interval: Duration::from_secs(read("RETENTION_INTERVAL_IN_SECONDS", 60).max(1)),
+            batch_size: read("RETENTION_BATCH_SIZE", 500).max(1) as i64,
+        }
+    }
+}
+
+/// Starts retention job, it runs as long as the server
+pub fn start(state: AppState, lobby: Addr<Lobby>) {
+    let job = RetentionJob::from_env();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(job.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge(&state, &lobby, &job).await {
+                println!("Retention job failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Purges expired messages and announces them to rooms they were posted in
+async fn purge(state: &AppState, lobby: &Addr<Lobby>, job: &RetentionJob) -> Result<(), ShopError> {
+    let pool_state = state.clone();
+    let batch_size = job.batch_size;
+    let purged = actix_web::web::block(move || {
+        let connection = pool_state.get_pg_connection()?;
+        retention::purge_expired(&connection, batch_size)
+    })
+    .await??;
+    for purged in purged {
+        let room_id = match Uuid::parse_str(&purged.group_id) {
+            Ok(room_id) => room_id,
+            Err(_) => continue,
+        };
+        lobby.do_send(RoomEvent {
+            room_id,
+            event: ChatEvent::Deleted {
+                message_ids: purged.message_ids,
+            },
+        });
+    }
+    Ok(())
+}
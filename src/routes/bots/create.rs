@@ -0,0 +1,42 @@
+use crate::errors::ShopError;
+use crate::models::bot::{Bot, NewBot};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use validator::Validate;
+
+/// Creates bot owned by currently logged in (self) user.
+/// Bot cannot log in with password, it uses API keys created at `/bots/{bot_id}/keys`.
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * username: [String] - minimum 5 characters long
+/// * display_name: [String] - optional, maximum 64 characters long
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json] format
+/// ```
+/// {
+///     "id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "owner_id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///     "created_at": "2022-09-23T10:00:00",
+///     "username": "deploy_bot"
+/// }
+/// ```
+/// Error code: 208 if username is taken, 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    new_bot: Json<NewBot>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    new_bot.validate()?;
+    let connection = state.get_pg_connection()?;
+    let bot = Bot::create(&connection, &user, new_bot.into_inner())?;
+    Ok(HttpResponse::Ok().json(bot))
+}
@@ -0,0 +1,51 @@
+use crate::errors::ShopError;
+use crate::models::bot::{ApiKey, Bot, NewApiKey};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Creates API key for bot owned by currently logged in (self) user.
+/// Bot sends it in `x-api-key` header instead of jwt.
+///
+/// # HTTP request
+/// URL param {bot_id} - bot id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * name: [String] - name of the key, to tell keys apart
+/// * scopes: [Vec] of [String] - any of `chat:read`, `chat:write`, `groups:join`, `users:read`
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json] format, `key` is shown only once
+/// ```
+/// {
+///     "id": "5b0e3f0c-4f4e-4a4b-8f7a-6f1c6f7e2a11",
+///     "bot_id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "name": "ci",
+///     "prefix": "wck_3f1d2a7b",
+///     "created_at": "2022-09-23T10:00:00",
+///     "last_used_at": null,
+///     "revoked_at": null,
+///     "scopes": ["chat:read", "chat:write"],
+///     "key": "wck_3f1d2a7b..."
+/// }
+/// ```
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    bot_id: Path<Uuid>,
+    new_key: Json<NewApiKey>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    new_key.validate()?;
+    let connection = state.get_pg_connection()?;
+    let bot = Bot::get_owned(&connection, &user.id, &bot_id.to_string())?;
+    let key = ApiKey::create(&connection, &bot, new_key.into_inner())?;
+    Ok(HttpResponse::Ok().json(key))
+}
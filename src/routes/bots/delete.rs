@@ -0,0 +1,32 @@
+use crate::errors::ShopError;
+use crate::models::account;
+use crate::models::bot::Bot;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Deletes bot owned by currently logged in (self) user, together with its API keys.
+/// Messages of bot are replaced with tombstones, same as for deleted users.
+///
+/// # HTTP request
+/// URL param {bot_id} - bot id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    bot_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    let bot = Bot::get_owned(&connection, &user.id, &bot_id.to_string())?;
+    account::delete(&connection, &User::get_by_id(&connection, &bot.user_id)?)?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,23 @@
+use crate::errors::ShopError;
+use crate::models::bot::Bot;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Lists bots owned by currently logged in (self) user
+///
+/// # HTTP request
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format, list of bots as returned from `POST /bots`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    Ok(HttpResponse::Ok().json(Bot::list_owned(&connection, &user.id)?))
+}
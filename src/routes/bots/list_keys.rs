@@ -0,0 +1,31 @@
+use crate::errors::ShopError;
+use crate::models::bot::{ApiKey, Bot};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Lists API keys of bot owned by currently logged in (self) user, including revoked ones
+///
+/// # HTTP request
+/// URL param {bot_id} - bot id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format, list of keys as returned on creation,
+///   without `key`
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    bot_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    let bot = Bot::get_owned(&connection, &user.id, &bot_id.to_string())?;
+    Ok(HttpResponse::Ok().json(ApiKey::list(&connection, &bot)?))
+}
@@ -0,0 +1,7 @@
+//! Bot and API key route handling module
+pub mod create;
+pub mod create_key;
+pub mod delete;
+pub mod list;
+pub mod list_keys;
+pub mod revoke_key;
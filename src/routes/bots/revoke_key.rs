@@ -0,0 +1,31 @@
+use crate::errors::ShopError;
+use crate::models::bot::{ApiKey, Bot};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Revokes API key of bot owned by currently logged in (self) user
+///
+/// # HTTP request
+/// URL params {bot_id} - bot id, {key_id} - API key id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let (bot_id, key_id) = path.into_inner();
+    let connection = state.get_pg_connection()?;
+    let bot = Bot::get_owned(&connection, &user.id, &bot_id.to_string())?;
+    ApiKey::revoke(&connection, &bot, &key_id.to_string())?;
+    Ok(HttpResponse::Ok().finish())
+}
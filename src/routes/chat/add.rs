@@ -4,7 +4,7 @@ use crate::models::group::Group;
 use crate::utils::AppState;
 use crate::{
     models::{
-        group::{InsertableNewGroup, NewGroup},
+        group::{GroupRole, InsertableNewGroup, NewGroup},
         user::User,
     },
     schema::groups,
@@ -41,6 +41,6 @@ pub async fn handle(
     let new_group: Group = diesel::insert_into(groups::table)
         .values(insertable_group)
         .get_result::<Group>(&connection)?;
-    user.join_group(&connection, &new_group.id)?;
+    user.join_group(&connection, &new_group.id, GroupRole::Owner)?;
     Ok(HttpResponse::Ok().json("Successfully added new group!"))
 }
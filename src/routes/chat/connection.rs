@@ -3,22 +3,39 @@ use crate::errors::ShopError;
 use crate::utils::AppState;
 use crate::{diesel::RunQueryDsl, schema::groups_users};
 use crate::{
-    models::{group::Group, lobby::Lobby, user::User, ws::WsConn},
+    models::{
+        bot::ApiScope, group::Group, lobby::Lobby, outbound::OutboundStream, user::User, ws::WsConn,
+    },
     schema::{groups, users},
 };
 use actix::Addr;
+use actix_http::ws::Codec;
 use actix_web::{
-    web::{self, Data, Payload},
+    web::{self, Data, Payload, Query},
     HttpRequest, HttpResponse,
 };
 use actix_web_actors::ws;
 use diesel::QueryDsl;
+use serde::Deserialize;
 use uuid::Uuid;
 
+/// Struct received from query string of reconnecting client
+#[derive(Debug, Deserialize)]
+pub struct ResumeQuery {
+    /// Sequence number of last event client received
+    pub last_seq: Option<i64>,
+}
+
 /// Enters selected chat group
 /// # HTTP request
+/// URL params {group_id} - group id
+/// ## Query
+/// * last_seq: [Option] of [i64] - `seq` of last event received before connection dropped,
+///   events missed since then are sent before live ones, followed by `sync` event
 /// ## Header
-/// * jwt: [String] - JWT autorization token
+/// * jwt: [String] - JWT autorization token, or
+/// * x-api-key: [String] - API key of bot with `chat:read` scope, messages sent without
+///   `chat:write` scope are rejected
 ///
 /// # HTTP response
 /// Success code: 101
@@ -30,9 +47,20 @@ pub async fn handle(
     req: HttpRequest,
     stream: Payload,
     group_id: web::Path<Uuid>,
+    query: Query<ResumeQuery>,
     srv: Data<Addr<Lobby>>,
 ) -> Result<HttpResponse, ShopError> {
-    let user = User::is_logged(&req)?;
+    let (user, api_key) = User::is_logged_or_bot(&req)?;
+    if let Some(api_key) = &api_key {
+        api_key.require(ApiScope::ChatRead)?;
+    }
+    let can_send = match &api_key {
+        Some(api_key) => api_key.has_scope(ApiScope::ChatWrite),
+        None => true,
+    };
+    if matches!(query.last_seq, Some(last_seq) if last_seq < 0) {
+        return Err(ShopError::InvalidInput);
+    }
     let connection = state.get_pg_connection()?;
     let result = users::table
         .inner_join(groups_users::table.inner_join(groups::table))
@@ -40,12 +68,25 @@ pub async fn handle(
         .filter(groups::id.eq(&group_id.to_string()))
         .select(groups::all_columns)
         .load::<Group>(&connection)?;
-    if result.len() == 0 {
+    if result.is_empty() {
         return Err(ShopError::NoPermission(
             "No permission for that action".to_string(),
         ));
     }
-    let ws = WsConn::new(*group_id, srv.get_ref().clone(), Uuid::parse_str(&user.id)?);
-    let resp = ws::start(ws, &req, stream)?;
-    Ok(resp)
+    let data = &state.static_data;
+    let ws = WsConn::new(
+        *group_id,
+        srv.get_ref().clone(),
+        Uuid::parse_str(&user.id)?,
+        can_send,
+        query.last_seq,
+        data,
+    );
+    let in_flight = ws.in_flight();
+    let codec = Codec::new().max_size(data.frame_limits.max_frame_size);
+    let body = ws::WebsocketContext::with_codec(ws, stream, codec);
+    let body = OutboundStream::new(body, in_flight, data.metrics.clone());
+    Ok(ws::handshake(&req)
+        .map_err(actix_web::Error::from)?
+        .streaming(body))
 }
@@ -1,7 +1,9 @@
 use crate::diesel::ExpressionMethods;
 use crate::diesel::RunQueryDsl;
 use crate::errors::ShopError;
-use crate::models::group::JoinableGroup;
+use crate::models::bot::ApiScope;
+use crate::models::group::{GroupRole, JoinableGroup};
+use crate::models::webhook::{WebhookDelivery, WebhookEvent};
 use crate::schema::groups_users;
 use crate::utils::AppState;
 use crate::{models::user::User, schema::groups};
@@ -27,7 +29,7 @@ pub async fn handle(
     req: HttpRequest,
     group: Json<JoinableGroup>,
 ) -> Result<HttpResponse, ShopError> {
-    let user = User::is_logged(&req)?;
+    let user = User::is_logged_with_scope(&req, ApiScope::GroupsJoin)?;
     let connection = state.get_pg_connection()?;
     let group_count = groups::table
         .select(groups::id)
@@ -41,9 +43,10 @@ pub async fn handle(
         .filter(groups_users::group_id.eq(&group.id))
         .filter(groups_users::user_id.eq(&user.id))
         .load::<String>(&connection)?;
-    if user_count.len() != 0 {
+    if !user_count.is_empty() {
         return Err(ShopError::AlreadyExistsError);
     }
-    user.join_group(&connection, &group.id)?;
-    return Ok(HttpResponse::Ok().json("Successfully joined!"));
+    user.join_group(&connection, &group.id, GroupRole::Member)?;
+    WebhookDelivery::emit_member(&connection, &group.id, &user.id, WebhookEvent::Join);
+    Ok(HttpResponse::Ok().json("Successfully joined!"))
 }
@@ -0,0 +1,58 @@
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::models::group::{Group, GroupRole};
+use crate::models::user::User;
+use crate::models::webhook::{WebhookDelivery, WebhookEvent};
+use crate::schema::groups_users;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Removes current user from group. Leaving owner hands the group over to another member,
+/// group without other members is deleted.
+///
+/// # HTTP request
+/// URL param {group_id} - group id to leave
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    let group_id = group_id.to_string();
+    let role = user
+        .group_role(&connection, &group_id)?
+        .ok_or_else(|| ShopError::NotFoundError("You are not a member of that group".into()))?;
+    let removed = connection.transaction::<_, ShopError, _>(|| {
+        if role == GroupRole::Owner {
+            match Group::hand_over(&connection, &group_id, &user.id)? {
+                Some(_) => WebhookDelivery::emit_group_update(&connection, &group_id),
+                None => {
+                    Group::delete(&connection, &group_id)?;
+                    return Ok(true);
+                }
+            }
+        }
+        WebhookDelivery::emit_member(&connection, &group_id, &user.id, WebhookEvent::Leave);
+        diesel::delete(
+            groups_users::table
+                .filter(groups_users::group_id.eq(&group_id))
+                .filter(groups_users::user_id.eq(&user.id)),
+        )
+        .execute(&connection)?;
+        Ok(false)
+    })?;
+    if removed {
+        return Ok(HttpResponse::Ok().json("Successfully left and removed group!"));
+    }
+    Ok(HttpResponse::Ok().json("Successfully left group!"))
+}
@@ -0,0 +1,62 @@
+use crate::errors::ShopError;
+use crate::models::{
+    bot::ApiScope, lobby::Lobby, member::GroupMember, messages::RoomPresence,
+    pagination::Pagination, user::User,
+};
+use crate::utils::AppState;
+use actix::Addr;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Lists members of a group, with their role, join date and online status
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// ## Query
+/// * page: [i64] - page number, starting from 1 (default 1)
+/// * per_page: [i64] - page size, at most 100 (default 50)
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "page": 1,
+///     "per_page": 50,
+///     "total": 1,
+///     "items": [
+///         {
+///             "id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///             "username": "test_user",
+///             "role": "owner",
+///             "joined_at": "2022-08-05T10:15:00.000000",
+///             "online": true
+///         }
+///     ]
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    pagination: Query<Pagination>,
+    srv: Data<Addr<Lobby>>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::ChatRead)?;
+    let connection = state.get_pg_connection()?;
+    if user
+        .group_role(&connection, &group_id.to_string())?
+        .is_none()
+    {
+        return Err(ShopError::NoPermission(
+            "No permission for that action".to_string(),
+        ));
+    }
+    let online = srv.send(RoomPresence { room_id: *group_id }).await?;
+    let members = GroupMember::list(&connection, &group_id.to_string(), &pagination, &online)?;
+    Ok(HttpResponse::Ok().json(members))
+}
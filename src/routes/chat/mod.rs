@@ -2,4 +2,8 @@
 pub mod add;
 pub mod connection;
 pub mod join;
+pub mod leave;
+pub mod members;
+pub mod notifications;
 pub mod remove;
+pub mod rename;
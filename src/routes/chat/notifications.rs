@@ -0,0 +1,41 @@
+use crate::errors::ShopError;
+use crate::models::notification::GroupNotifications;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Changes notification settings of currently logged in user for group he is a member of
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * level: [String] - `all` messages, `mentions` only or `none`
+/// * muted_until: [Option] of timestamp - no notifications from group until then
+/// ```
+/// {
+///     "level": "mentions",
+///     "muted_until": "2022-11-18T18:00:00"
+/// }
+/// ```
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is stored settings in [Json] format
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    notifications: Json<GroupNotifications>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    notifications.set(&connection, &group_id.to_string(), &user.id)?;
+    Ok(HttpResponse::Ok().json(notifications.into_inner()))
+}
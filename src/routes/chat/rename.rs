@@ -0,0 +1,53 @@
+use crate::diesel::prelude::*;
+use crate::errors::ShopError;
+use crate::models::group::{Group, NewGroup};
+use crate::models::user::User;
+use crate::models::webhook::WebhookDelivery;
+use crate::schema::groups;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Renames group, allowed for group admins and owner
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * name: [String] - new group name, minimum 3 characters long
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json] format
+/// ```
+/// {
+///     "id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "name": "New name"
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    group: Json<NewGroup>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    group.validate()?;
+    let connection = state.get_pg_connection()?;
+    let group_id = group_id.to_string();
+    if !user.is_group_admin(&connection, &group_id)? {
+        return Err(ShopError::NoPermission(
+            "No permission for renaming that group!".to_string(),
+        ));
+    }
+    let renamed = diesel::update(groups::table.find(&group_id))
+        .set(groups::name.eq(&group.name))
+        .get_result::<Group>(&connection)?;
+    WebhookDelivery::emit_group_update(&connection, &group_id);
+    Ok(HttpResponse::Ok().json(renamed))
+}
@@ -1,8 +1,11 @@
 use crate::diesel::ExpressionMethods;
 use crate::diesel::RunQueryDsl;
 use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
 use crate::models::group::Group;
-use crate::models::group::UserGroups;
+use crate::models::group::{JoinedGroup, UserGroups};
+use crate::models::member::GroupMember;
+use crate::models::notification::GroupNotifications;
 use crate::utils::AppState;
 use crate::{
     models::user::User,
@@ -10,10 +13,13 @@ use crate::{
 };
 use actix_web::web::Data;
 use actix_web::{HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
 use diesel::result::Error;
 use diesel::QueryDsl;
 
-/// Gets currently logged in (self) user info
+/// Gets currently logged in (self) user info, together with his notification settings
+/// of every group and counts of messages sent to his groups while he was not connected
+/// to them
 /// # HTTP request
 /// Request must be in [Json](actix_web::web::Json) format
 /// ## Header
@@ -31,30 +37,58 @@ use diesel::QueryDsl;
 ///     "groups": [
 ///         {
 ///             "id": "9780f090-82a7-47dc-a64a-c4b1ad3c978d",
-///             "name": "group_1"
+///             "name": "group_1",
+///             "notifications": { "level": "all", "muted_until": null }
 ///         },
 ///         {
 ///             "id": "d819befb-c975-4a0d-bdcd-b619848f1b5b",
-///             "name": "group_2"
+///             "name": "group_2",
+///             "notifications": { "level": "mentions", "muted_until": "2022-11-18T18:00:00" }
+///         }
+///     ],
+///     "missed": [
+///         {
+///             "group_id": "9780f090-82a7-47dc-a64a-c4b1ad3c978d",
+///             "messages": 12,
+///             "mentions": 1
 ///         }
 ///     ]
 ///  }
 /// ```
 /// Error code: 403
 pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
-    if let Ok(user) = User::is_logged(&req) {
+    if let Ok(user) = User::is_logged_with_scope(&req, ApiScope::UsersRead) {
         let connection = state.get_pg_connection()?;
-        let data: Result<Vec<Group>, Error> = users::table
+        let data: Result<Vec<(Group, String, Option<NaiveDateTime>)>, Error> = users::table
             .inner_join(groups_users::table.inner_join(groups::table))
             .filter(users::id.eq(&user.id))
-            .select(groups::all_columns)
-            .load::<Group>(&connection);
-        let data = data.ok();
+            .select((
+                groups::all_columns,
+                groups_users::notify_level,
+                groups_users::notify_muted_until,
+            ))
+            .load(&connection);
+        let data = data.ok().and_then(|rows| {
+            rows.into_iter()
+                .map(|(group, level, muted_until)| {
+                    Ok(JoinedGroup {
+                        group,
+                        notifications: GroupNotifications {
+                            level: level.parse()?,
+                            muted_until,
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>, ShopError>>()
+                .ok()
+        });
+        let missed = GroupMember::missed(&connection, &user.id).ok();
         let info = UserGroups {
-            user: user,
+            user,
             groups: data,
+            missed,
         };
-        return Ok(HttpResponse::Ok().json(info));
+        Ok(HttpResponse::Ok().json(info))
     } else {
         Err(ShopError::NoPermission(
             "No permission for that action".to_string(),
@@ -0,0 +1,56 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::integration::{Integration, NewIntegration};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Creates incoming webhook of group, allowed for group admins and owner.
+/// External systems post messages into group by sending `POST` to returned `url`.
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * name: [String] - name shown as sender of posted messages
+/// * avatar_url: [Option] of [String] - picture shown as sender of posted messages
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json] format, `token` and `url` are shown only once
+/// ```
+/// {
+///     "id": "2d1b5c8e-7f3a-4c2e-9b1d-6e4f8a0c3b27",
+///     "group_id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "name": "CI",
+///     "avatar_url": null,
+///     "created_by": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///     "created_at": "2022-10-07T10:00:00",
+///     "token": "8c2f0e1a...",
+///     "url": "/hooks/2d1b5c8e-7f3a-4c2e-9b1d-6e4f8a0c3b27/8c2f0e1a..."
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    new_integration: Json<NewIntegration>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    new_integration.validate()?;
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let integration = Integration::create(
+        &connection,
+        &group_id.to_string(),
+        &user,
+        new_integration.into_inner(),
+    )?;
+    Ok(HttpResponse::Ok().json(integration))
+}
@@ -0,0 +1,38 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::integration::Integration;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Deletes incoming webhook of group, allowed for group admins and owner.
+/// Its URL stops working, already posted messages are kept.
+///
+/// # HTTP request
+/// URL params {group_id} - group id, {integration_id} - integration id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let (group_id, integration_id) = path.into_inner();
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let integration = Integration::get(
+        &connection,
+        &group_id.to_string(),
+        &integration_id.to_string(),
+    )?;
+    integration.delete(&connection)?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,72 @@
+use crate::errors::ShopError;
+use crate::models::chat_message::ChatMessage;
+use crate::models::events::ChatEvent;
+use crate::models::integration::{IncomingMessage, Integration};
+use crate::models::lobby::Lobby;
+use crate::models::messages::RoomEvent;
+use crate::utils::AppState;
+use actix::Addr;
+use actix_web::web::{Data, Json, Path};
+use actix_web::HttpResponse;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Posts message into group on behalf of integration, without any other authorization.
+/// Body has the same shape as Slack incoming webhooks, so existing tools (e.g. CI plugins)
+/// can be pointed to this URL.
+///
+/// # HTTP request
+/// URL params {integration_id} - integration id, {token} - token returned on creation
+/// Request must be in [Json] format
+/// ## Body
+/// * text: [Option] of [String] - message text, at most 4000 characters
+/// * attachments: [Option] of [Vec] of attachments, at most 20
+/// ```
+/// {
+///     "text": "Build finished",
+///     "attachments": [
+///         {
+///             "fallback": "Build #42 passed",
+///             "color": "good",
+///             "title": "Build #42",
+///             "title_link": "https://ci.example.com/builds/42",
+///             "fields": [{ "title": "Branch", "value": "main", "short": true }]
+///         }
+///     ]
+/// }
+/// ```
+///
+/// # HTTP response
+/// Success code: 200, body `ok`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    path: Path<(Uuid, String)>,
+    message: Json<IncomingMessage>,
+    srv: Data<Addr<Lobby>>,
+) -> Result<HttpResponse, ShopError> {
+    let (integration_id, token) = path.into_inner();
+    message.validate()?;
+    let body = message.body()?;
+    let connection = state.get_pg_connection()?;
+    let integration = Integration::authenticate(&connection, &integration_id.to_string(), &token)?;
+    let message = message.into_inner();
+    let stored = ChatMessage::create_from_integration(
+        &connection,
+        &integration,
+        &body,
+        &message.attachments,
+    )?;
+    srv.do_send(RoomEvent {
+        room_id: Uuid::parse_str(&integration.group_id)?,
+        event: ChatEvent::Integration {
+            id: stored.id,
+            integration: (&integration).into(),
+            body,
+            attachments: message.attachments,
+            sent_at: stored.created_at,
+        },
+    });
+    Ok(HttpResponse::Ok().body("ok"))
+}
@@ -0,0 +1,19 @@
+//! Incoming webhook (integration) route handling module
+use crate::errors::ShopError;
+use crate::models::user::User;
+use diesel::PgConnection;
+
+pub mod create;
+pub mod delete;
+pub mod incoming;
+pub mod list;
+
+/// Integrations of group are managed only by its admins and owner
+fn require_admin(connection: &PgConnection, user: &User, group_id: &str) -> Result<(), ShopError> {
+    if !user.is_group_admin(connection, group_id)? {
+        return Err(ShopError::NoPermission(
+            "No permission for managing integrations of that group!".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::jwt::KEYS;
+use actix_web::HttpResponse;
+
+/// Public keys for verifying tokens issued by this server, see [crate::jwt::keys]
+///
+/// Served at `/.well-known/jwks.json`. Shared HS256 secret is never published,
+/// so the key set is empty without `JWT_KEYS_DIR`.
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "keys": [
+///         {
+///             "kty": "OKP",
+///             "use": "sig",
+///             "alg": "EdDSA",
+///             "kid": "2022-09",
+///             "crv": "Ed25519",
+///             "x": "35k_QJUBg1g9GQMfKzXdnJKonm93zm04q_z7PWYljvQ"
+///         }
+///     ]
+/// }
+/// ```
+pub async fn handle() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(KEYS.jwks())
+}
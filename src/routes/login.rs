@@ -1,11 +1,12 @@
 use crate::{
     errors::ShopError,
-    models::user::{NewUser, User},
-    utils::AppState,
+    models::two_factor::TwoFactorChallenge,
+    models::user::{Authentication, NewUser, User},
+    utils::{client_ip, AppState},
 };
 use actix_web::{
     web::{Data, Json},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 
 /// Login user
@@ -30,9 +31,50 @@ use actix_web::{
 ///     "username": "test_user"
 /// }
 /// ```
-/// Error code: 208, 400, 404, 500
-pub async fn handle(state: Data<AppState>, user: Json<NewUser>) -> Result<HttpResponse, ShopError> {
+/// If user has two-factor authentication enabled, no jwt header is returned and
+/// pending token must be exchanged at `/login/2fa`
+/// ```
+/// {
+///     "two_factor_required": true,
+///     "pending_token": "eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9..."
+/// }
+/// ```
+/// Error code: 400, 403, 429, 500
+///
+/// Unknown username and wrong password both result in 403. Repeated failures per
+/// account and per IP are throttled with growing delay and then locked out for a while,
+/// see [crate::lockout]; such requests get 429 with `Retry-After` header.
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    user: Json<NewUser>,
+) -> Result<HttpResponse, ShopError> {
+    let guard = &state.static_data.login_guard;
+    let ip = client_ip(&req);
+    let attempt = guard.check(&user.username, &ip)?;
     let connection = state.get_pg_connection()?;
-    let (valid, token) = User::authenticate(&connection, &user.username, &user.password)?;
-    Ok(HttpResponse::Ok().append_header(("jwt", token)).json(valid))
+    match User::authenticate(&connection, &user.username, &user.password) {
+        Ok(Authentication::Complete(valid, token)) => {
+            attempt.succeeded();
+            Ok(HttpResponse::Ok().append_header(("jwt", token)).json(valid))
+        }
+        Ok(Authentication::TwoFactorPending(pending_token)) => {
+            // failures are forgotten only after second factor is verified at `/login/2fa`,
+            // otherwise password alone would allow guessing codes without ever being locked out
+            Ok(HttpResponse::Ok().json(TwoFactorChallenge::new(pending_token)))
+        }
+        Err(ShopError::NoPermission(reason)) => {
+            for lockout in attempt.failed() {
+                println!(
+                    "Locked out {} {} after {} failed logins",
+                    lockout.kind.as_str(),
+                    lockout.subject,
+                    lockout.failures
+                );
+                lockout.record(&connection)?;
+            }
+            Err(ShopError::NoPermission(reason))
+        }
+        Err(e) => Err(e),
+    }
 }
@@ -0,0 +1,41 @@
+use crate::errors::ShopError;
+use crate::utils::{token, AppState};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Runtime metrics in Prometheus text format, see [crate::metrics::Metrics]
+///
+/// Available only when `METRICS_TOKEN` is set in .env file.
+///
+/// # HTTP request
+/// ## Header
+/// * Authorization: [String] - `Bearer <METRICS_TOKEN>`
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in plain text format
+/// ```text
+/// # HELP ws_queued_events Events waiting in outbound queues
+/// # TYPE ws_queued_events gauge
+/// ws_queued_events 0
+/// ```
+/// Error code: 403, 404
+pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
+    let expected = dotenv::var("METRICS_TOKEN")
+        .map_err(|_| ShopError::NotFoundError("Metrics are disabled".to_string()))?;
+    let provided = req
+        .headers()
+        .get(AUTHORIZATION)
+        .map(|value| value.to_str())
+        .transpose()?
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // hashes have the same length, so comparing them does not reveal length of token
+    if token::hash(provided) != token::hash(&expected) {
+        return Err(ShopError::NoPermission("Invalid metrics token".to_string()));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.static_data.metrics.render()))
+}
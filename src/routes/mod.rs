@@ -1,21 +1,131 @@
 //! Route handlind module
 use actix_web::web::{self, ServiceConfig};
 
+pub mod bots;
 pub mod chat;
 pub mod index;
+pub mod integrations;
+pub mod jwks;
 pub mod login;
+pub mod metrics;
+pub mod oidc;
+pub mod password;
+pub mod pins;
 pub mod register;
+pub mod retention;
+pub mod two_factor;
+pub mod users;
+pub mod webhooks;
 
 /// Configuring and handling routes
 pub fn router(conf: &mut ServiceConfig) {
+    conf.service(web::resource("/metrics").route(web::get().to(metrics::handle)));
     conf.service(web::resource("/register").route(web::post().to(register::handle)));
     conf.service(web::resource("/login").route(web::post().to(login::handle)));
-    conf.service(web::resource("/self").route(web::get().to(index::handle)));
+    conf.service(web::resource("/oidc/login").route(web::get().to(oidc::login::handle)));
+    conf.service(web::resource("/oidc/callback").route(web::get().to(oidc::callback::handle)));
+    conf.service(web::resource("/login/2fa").route(web::post().to(two_factor::login::handle)));
+    conf.service(
+        web::resource("/self")
+            .route(web::get().to(index::handle))
+            .route(web::delete().to(users::delete::handle)),
+    );
+    conf.service(web::resource("/self/mentions").route(web::get().to(users::mentions::handle)));
+    conf.service(
+        web::resource("/self/notifications")
+            .route(web::get().to(users::notifications::handle))
+            .route(web::put().to(users::update_notifications::handle)),
+    );
+    conf.service(
+        web::resource("/self/email/confirm").route(web::post().to(users::confirm_email::handle)),
+    );
+    conf.service(web::resource("/self/export").route(web::get().to(users::export::handle)));
+    conf.service(web::resource("/self/profile").route(web::patch().to(users::profile::handle)));
+    conf.service(web::resource("/self/password").route(web::post().to(users::password::handle)));
+    conf.service(web::resource("/self/2fa").route(web::delete().to(two_factor::disable::handle)));
+    conf.service(
+        web::resource("/self/2fa/enroll").route(web::post().to(two_factor::enroll::handle)),
+    );
+    conf.service(
+        web::resource("/self/2fa/activate").route(web::post().to(two_factor::activate::handle)),
+    );
+    conf.service(web::resource("/password/forgot").route(web::post().to(password::forgot::handle)));
+    conf.service(web::resource("/password/reset").route(web::post().to(password::reset::handle)));
+    conf.service(
+        web::resource("/bots")
+            .route(web::get().to(bots::list::handle))
+            .route(web::post().to(bots::create::handle)),
+    );
+    conf.service(web::resource("/bots/{bot_id}").route(web::delete().to(bots::delete::handle)));
+    conf.service(
+        web::resource("/bots/{bot_id}/keys")
+            .route(web::get().to(bots::list_keys::handle))
+            .route(web::post().to(bots::create_key::handle)),
+    );
+    conf.service(
+        web::resource("/bots/{bot_id}/keys/{key_id}")
+            .route(web::delete().to(bots::revoke_key::handle)),
+    );
+    conf.service(web::resource("/users/{user_id}").route(web::get().to(users::show::handle)));
     conf.service(web::resource("/chat/addGroup").route(web::post().to(chat::add::handle)));
     conf.service(web::resource("/chat/joinGroup").route(web::post().to(chat::join::handle)));
+    conf.service(
+        web::resource("/chat/leaveGroup/{group_id}").route(web::post().to(chat::leave::handle)),
+    );
     conf.service(
         web::resource("/chat/removeGroup/{group_id}").route(web::get().to(chat::remove::handle)),
     );
+    conf.service(web::resource("/chat/{group_id}").route(web::patch().to(chat::rename::handle)));
+    conf.service(
+        web::resource("/chat/{group_id}/members").route(web::get().to(chat::members::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/notifications")
+            .route(web::put().to(chat::notifications::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/pins")
+            .route(web::get().to(pins::list::handle))
+            .route(web::post().to(pins::create::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/pins/{message_id}")
+            .route(web::delete().to(pins::delete::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/retention")
+            .route(web::get().to(retention::show::handle))
+            .route(web::put().to(retention::update::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/purges").route(web::get().to(retention::purges::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/webhooks")
+            .route(web::get().to(webhooks::list::handle))
+            .route(web::post().to(webhooks::create::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/webhooks/{webhook_id}")
+            .route(web::delete().to(webhooks::delete::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/webhooks/{webhook_id}/deliveries")
+            .route(web::get().to(webhooks::deliveries::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/integrations")
+            .route(web::get().to(integrations::list::handle))
+            .route(web::post().to(integrations::create::handle)),
+    );
+    conf.service(
+        web::resource("/chat/{group_id}/integrations/{integration_id}")
+            .route(web::delete().to(integrations::delete::handle)),
+    );
+    conf.service(
+        web::resource("/hooks/{integration_id}/{token}")
+            .route(web::post().to(integrations::incoming::handle)),
+    );
     conf.service(
         web::resource("/chat/enter/{group_id}").route(web::get().to(chat::connection::handle)),
     );
@@ -0,0 +1,75 @@
+use super::client;
+use crate::errors::ShopError;
+use crate::jwt;
+use crate::models::external_identity::ExternalIdentity;
+use crate::oidc::STATE_COOKIE;
+use crate::utils::AppState;
+use actix_web::cookie::Cookie;
+use actix_web::web::{Data, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Deserialize;
+
+/// Query parameters of redirect from provider
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Finishes login with single sign-on provider. User linked to provider subject is created
+/// on first login, then normal JWT is issued.
+///
+/// # HTTP request
+/// URL query params (set by provider)
+/// * code: [String] - authorization code
+/// * state: [String] - value from `/oidc/login`
+/// * error: [String] - set instead of code when login at provider failed
+///
+/// Cookie `oidc_state` set by `/oidc/login` in the same browser
+///
+/// # HTTP response
+/// ##Header
+/// * Success code: 200
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "id": "f7169845-4de5-470e-bb76-7117d4620d8c"
+///     "username": "test_user"
+/// }
+/// ```
+/// Error code: 400, 403 if login failed, expired or was started in other browser, 404 if single sign-on is not configured,
+/// 408 if provider is not reachable, 500
+pub async fn handle(
+    state: Data<AppState>,
+    query: Query<CallbackQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ShopError> {
+    let oidc = client(&state)?;
+    if let Some(error) = &query.error {
+        return Err(ShopError::NoPermission(format!(
+            "Provider refused login: {}",
+            error
+        )));
+    }
+    let (code, login_state) = match (&query.code, &query.state) {
+        (Some(code), Some(login_state)) => (code, login_state),
+        _ => return Err(ShopError::InvalidInput),
+    };
+    let browser_state = req.cookie(STATE_COOKIE);
+    let claims = oidc
+        .exchange(code, login_state, browser_state.as_ref().map(Cookie::value))
+        .await?;
+    let connection = state.get_pg_connection()?;
+    let user = ExternalIdentity::login(&connection, oidc.issuer(), &claims)?;
+    let token = jwt::generate(&user)?;
+    let mut used = Cookie::named(STATE_COOKIE);
+    used.set_path("/");
+    used.make_removal();
+    Ok(HttpResponse::Ok()
+        .append_header(("jwt", token))
+        .cookie(used)
+        .json(user))
+}
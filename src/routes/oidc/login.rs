@@ -0,0 +1,25 @@
+use super::client;
+use crate::errors::ShopError;
+use crate::utils::AppState;
+use actix_web::http::header::LOCATION;
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+
+/// Starts login with single sign-on provider
+///
+/// # HTTP response
+/// ## Header
+/// * Success code: 302
+/// * Location: [String] - authorization endpoint of provider, which redirects back
+///   to `/oidc/callback`
+/// * Set-Cookie: `oidc_state` - binds login to this browser, must be sent with callback
+///
+/// Error code: 404 if single sign-on is not configured, 408 if provider is not reachable
+pub async fn handle(state: Data<AppState>) -> Result<HttpResponse, ShopError> {
+    let oidc = client(&state)?;
+    let (url, login_state) = oidc.authorization_url().await?;
+    Ok(HttpResponse::Found()
+        .append_header((LOCATION, url))
+        .cookie(oidc.state_cookie(&login_state))
+        .finish())
+}
@@ -0,0 +1,16 @@
+//! Single sign-on route handling module, see [crate::oidc]
+use crate::errors::ShopError;
+use crate::oidc::OidcClient;
+use crate::utils::AppState;
+
+pub mod callback;
+pub mod login;
+
+/// Configured provider, or 404 when single sign-on is not enabled
+fn client(state: &AppState) -> Result<&OidcClient, ShopError> {
+    state
+        .static_data
+        .oidc
+        .as_ref()
+        .ok_or_else(|| ShopError::NotFoundError("Single sign-on is not configured".to_string()))
+}
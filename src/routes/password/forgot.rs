@@ -0,0 +1,44 @@
+use crate::errors::ShopError;
+use crate::models::password_reset::{PasswordReset, PasswordResetRequest};
+use crate::utils::AppState;
+use actix_web::{
+    web::{self, Data, Json},
+    HttpResponse,
+};
+
+/// Starts password reset, single-use reset token is delivered to user through configured notifier
+/// in background
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Body
+/// * username: [String]
+///
+/// # HTTP response
+/// * Success code: 200, returned even if user with that username does not exist or token
+///   could not be delivered
+///
+/// Error code: 400
+pub async fn handle(
+    state: Data<AppState>,
+    request: Json<PasswordResetRequest>,
+) -> Result<HttpResponse, ShopError> {
+    let db = state.static_data.db.clone();
+    let notifier = state.static_data.notifier.clone();
+    let username = request.into_inner().username;
+    // token is issued and delivered in background, so response time does not reveal
+    // whether user exists, notifiers block while delivering
+    actix_web::rt::spawn(async move {
+        let result = web::block(move || {
+            let connection = db.get()?;
+            PasswordReset::request(&connection, notifier.as_ref(), &username)
+        })
+        .await
+        .map_err(ShopError::from)
+        .and_then(|result| result);
+        if let Err(e) = result {
+            println!("Couldn't start password reset: {}", e);
+        }
+    });
+    Ok(HttpResponse::Ok().json("If that user exists, reset token has been sent!"))
+}
@@ -0,0 +1,3 @@
+//! Password reset route handling module
+pub mod forgot;
+pub mod reset;
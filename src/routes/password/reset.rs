@@ -0,0 +1,30 @@
+use crate::errors::ShopError;
+use crate::models::password_reset::{PasswordReset, PasswordResetConfirm};
+use crate::utils::AppState;
+use actix_web::{
+    web::{Data, Json},
+    HttpResponse,
+};
+use validator::Validate;
+
+/// Finishes password reset, setting new password and revoking all sessions of user
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Body
+/// * token: [String] - reset token received through notifier
+/// * new_password: [String] - minimum 8 characters long
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    request: Json<PasswordResetConfirm>,
+) -> Result<HttpResponse, ShopError> {
+    let connection = state.get_pg_connection()?;
+    request.validate()?;
+    PasswordReset::redeem(&connection, &request.token, &request.new_password)?;
+    Ok(HttpResponse::Ok().json("Successfully changed password!"))
+}
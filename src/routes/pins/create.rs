@@ -0,0 +1,70 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::events::ChatEvent;
+use crate::models::lobby::Lobby;
+use crate::models::messages::RoomEvent;
+use crate::models::pin::{NewPin, Pin};
+use crate::models::profile::UserProfile;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix::Addr;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Pins message of group and announces it to the room, allowed for group admins and owner.
+/// Group can have at most [MAX_PINS](crate::models::pin::MAX_PINS) pinned messages.
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * message_id: [String] - id of message posted in the group
+/// ```
+/// {
+///     "message_id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11"
+/// }
+/// ```
+///
+/// # HTTP response
+/// * Success code: 201
+/// * Response is in [Json] format
+/// ```
+/// {
+///     "id": "4a0f1f0e-8b8d-4f5e-9f55-0c2b4f7c1d2a",
+///     "group_id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "message_id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///     "pinned_by": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///     "pinned_at": "2022-12-02T10:30:00.000000"
+/// }
+/// ```
+/// Error code: 208 when message is pinned already, 409 when group has most pins allowed,
+/// 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    pin: Json<NewPin>,
+    srv: Data<Addr<Lobby>>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let pin = Pin::create(
+        &connection,
+        &group_id.to_string(),
+        &pin.message_id,
+        &user.id,
+    )?;
+    srv.do_send(RoomEvent {
+        room_id: *group_id,
+        event: ChatEvent::Pin {
+            message_id: pin.message_id.clone(),
+            pinned_by: UserProfile::get(&connection, &user.id)?.into(),
+            pinned_at: pin.pinned_at,
+        },
+    });
+    Ok(HttpResponse::Created().json(pin))
+}
@@ -0,0 +1,45 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::events::ChatEvent;
+use crate::models::lobby::Lobby;
+use crate::models::messages::RoomEvent;
+use crate::models::pin::Pin;
+use crate::models::profile::UserProfile;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix::Addr;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Unpins message of group and announces it to the room, allowed for group admins and owner
+///
+/// # HTTP request
+/// URL params {group_id} - group id, {message_id} - id of pinned message
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<(Uuid, Uuid)>,
+    srv: Data<Addr<Lobby>>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let (group_id, message_id) = path.into_inner();
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    Pin::delete(&connection, &group_id.to_string(), &message_id.to_string())?;
+    srv.do_send(RoomEvent {
+        room_id: group_id,
+        event: ChatEvent::Unpin {
+            message_id: message_id.to_string(),
+            unpinned_by: UserProfile::get(&connection, &user.id)?.into(),
+        },
+    });
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,51 @@
+use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
+use crate::models::pin::PinnedMessage;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Lists pinned messages of group, newest pin first, allowed for group members
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// [
+///     {
+///         "message_id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///         "sender_id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///         "sender_username": "test_user",
+///         "body": "Meeting moved to Friday",
+///         "sent_at": "2022-12-01T09:00:00.000000",
+///         "pinned_by": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///         "pinned_by_username": "test_user",
+///         "pinned_at": "2022-12-02T10:30:00.000000"
+///     }
+/// ]
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::ChatRead)?;
+    let connection = state.get_pg_connection()?;
+    if user
+        .group_role(&connection, &group_id.to_string())?
+        .is_none()
+    {
+        return Err(ShopError::NoPermission(
+            "No permission for that action".to_string(),
+        ));
+    }
+    Ok(HttpResponse::Ok().json(PinnedMessage::list(&connection, &group_id.to_string())?))
+}
@@ -0,0 +1,18 @@
+//! Pinned messages route handling module
+use crate::errors::ShopError;
+use crate::models::user::User;
+use diesel::PgConnection;
+
+pub mod create;
+pub mod delete;
+pub mod list;
+
+/// Messages are pinned and unpinned only by group admins and owner
+fn require_admin(connection: &PgConnection, user: &User, group_id: &str) -> Result<(), ShopError> {
+    if !user.is_group_admin(connection, group_id)? {
+        return Err(ShopError::NoPermission(
+            "No permission for pinning messages in that group!".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -34,19 +34,12 @@ pub async fn handle(state: Data<AppState>, user: Json<NewUser>) -> Result<HttpRe
 mod tests {
 
     use super::*;
-    use crate::{
-        models::user::NewUser,
-        utils::{get_connection_pool, StaticData},
-    };
+    use crate::{models::user::NewUser, utils::get_connection_pool};
     use actix_web::{test, web, App};
-    use std::sync::Arc;
 
     #[actix_web::test]
     async fn test_register_short_password() {
-        let db_pool = get_connection_pool();
-        let state = AppState {
-            static_data: Arc::new(StaticData { db: db_pool }),
-        };
+        let state = AppState::new(get_connection_pool());
         let app = test::init_service(App::new().route("/", web::get().to(handle))).await;
         let user = NewUser {
             username: String::from("legit_user"),
@@ -62,10 +55,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_register_invalid_password() {
-        let db_pool = get_connection_pool();
-        let state = AppState {
-            static_data: Arc::new(StaticData { db: db_pool }),
-        };
+        let state = AppState::new(get_connection_pool());
         let app = test::init_service(App::new().route("/", web::get().to(handle))).await;
         let user = NewUser {
             username: String::from("legit_user"),
@@ -81,10 +71,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_register_short_username() {
-        let db_pool = get_connection_pool();
-        let state = AppState {
-            static_data: Arc::new(StaticData { db: db_pool }),
-        };
+        let state = AppState::new(get_connection_pool());
         let app = test::init_service(App::new().route("/", web::get().to(handle))).await;
         let user = NewUser {
             username: String::from("usr"),
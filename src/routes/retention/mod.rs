@@ -0,0 +1,4 @@
+//! Retention policy route handling module
+pub mod purges;
+pub mod show;
+pub mod update;
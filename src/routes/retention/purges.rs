@@ -0,0 +1,58 @@
+use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
+use crate::models::pagination::Pagination;
+use crate::models::retention::PurgeRecord;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Messages of group purged by its retention policy, most recently purged first, allowed
+/// for group admins and owner. `reason` is `retention` for messages older than `days`
+/// and `ephemeral` for read messages of ephemeral group.
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// ## Query
+/// * page: [i64] - page number, starting from 1 (default 1)
+/// * per_page: [i64] - page size, at most 100 (default 50)
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "page": 1,
+///     "per_page": 50,
+///     "total": 1,
+///     "items": [
+///         {
+///             "id": "6d1c2b3a-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+///             "message_id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///             "reason": "ephemeral",
+///             "sent_at": "2022-12-09T10:30:00.000000",
+///             "purged_at": "2022-12-09T10:31:05.000000"
+///         }
+///     ]
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    pagination: Query<Pagination>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::ChatRead)?;
+    let connection = state.get_pg_connection()?;
+    if !user.is_group_admin(&connection, &group_id.to_string())? {
+        return Err(ShopError::NoPermission(
+            "No permission for that action".to_string(),
+        ));
+    }
+    let purges = PurgeRecord::list(&connection, &group_id.to_string(), &pagination)?;
+    Ok(HttpResponse::Ok().json(purges))
+}
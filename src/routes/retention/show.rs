@@ -0,0 +1,45 @@
+use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
+use crate::models::retention::RetentionPolicy;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Shows how long messages of group are kept, allowed for group members. `mode` is
+/// `forever`, `days` (messages are purged `days` after they were sent) or `ephemeral`
+/// (messages are purged `seconds` after someone else than sender read them).
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "mode": "ephemeral",
+///     "seconds": 60
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::ChatRead)?;
+    let connection = state.get_pg_connection()?;
+    if user
+        .group_role(&connection, &group_id.to_string())?
+        .is_none()
+    {
+        return Err(ShopError::NoPermission(
+            "No permission for that action".to_string(),
+        ));
+    }
+    Ok(HttpResponse::Ok().json(RetentionPolicy::get(&connection, &group_id.to_string())?))
+}
@@ -0,0 +1,59 @@
+use crate::errors::ShopError;
+use crate::models::lobby::Lobby;
+use crate::models::messages::RetentionChanged;
+use crate::models::retention::RetentionPolicy;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix::Addr;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Changes how long messages of group are kept, allowed for group admins and owner.
+/// Messages which are already past new limit are purged by next run of retention job.
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * mode: [String] - `forever`, `days` or `ephemeral`
+/// * days: [i32] - for `days` mode, from 1 to
+///   [MAX_RETENTION_DAYS](crate::models::retention::MAX_RETENTION_DAYS)
+/// * seconds: [i32] - for `ephemeral` mode, from 1 to
+///   [MAX_EPHEMERAL_SECONDS](crate::models::retention::MAX_EPHEMERAL_SECONDS)
+/// ```
+/// {
+///     "mode": "days",
+///     "days": 30
+/// }
+/// ```
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is stored policy in [Json] format
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    policy: Json<RetentionPolicy>,
+    srv: Data<Addr<Lobby>>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    if !user.is_group_admin(&connection, &group_id.to_string())? {
+        return Err(ShopError::NoPermission(
+            "No permission for changing retention of that group!".to_string(),
+        ));
+    }
+    policy.validate()?;
+    policy.set(&connection, &group_id.to_string())?;
+    srv.do_send(RetentionChanged {
+        room_id: *group_id,
+        policy: *policy,
+    });
+    Ok(HttpResponse::Ok().json(policy.into_inner()))
+}
@@ -0,0 +1,33 @@
+use crate::errors::ShopError;
+use crate::models::two_factor::{TwoFactorCode, UserTotp};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Activates two-factor authentication of currently logged in (self) user,
+/// after he confirms enrollment with code from his authenticator app
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * code: [String] - current 6 digit TOTP code
+///
+/// # HTTP response
+/// ## Header
+/// * Success code: 200
+///
+/// Error code: 208 if already enabled, 400, 403 if code is invalid, 404 if enrollment
+/// was not started, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    body: Json<TwoFactorCode>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    UserTotp::activate(&connection, &user.id, &body.code)?;
+    Ok(HttpResponse::Ok().finish())
+}
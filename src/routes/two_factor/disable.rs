@@ -0,0 +1,36 @@
+use crate::errors::ShopError;
+use crate::models::two_factor::{TwoFactorDisable, UserTotp};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Disables two-factor authentication of currently logged in (self) user,
+/// removing his secret and recovery codes
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * password: [String] - current password, confirming the action
+///
+/// Users created by single sign-on have no password, they leave it out and must have
+/// logged in through `/oidc/callback` within last few minutes instead
+///
+/// # HTTP response
+/// ## Header
+/// * Success code: 200
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    body: Json<TwoFactorDisable>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    user.confirm_identity(&connection, body.password.as_deref())?;
+    UserTotp::disable(&connection, &user.id)?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,33 @@
+use crate::errors::ShopError;
+use crate::models::two_factor::UserTotp;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Starts two-factor authentication enrollment of currently logged in (self) user.
+/// Calling it again before activation replaces secret and recovery codes.
+///
+/// # HTTP request
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// ## Header
+/// * Success code: 200
+/// ## Body
+/// * Response is in [Json](actix_web::web::Json) format, recovery codes are shown only once
+/// ```
+/// {
+///     "secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+///     "otpauth_uri": "otpauth://totp/web_chat:test_user?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=web_chat&algorithm=SHA1&digits=6&period=30",
+///     "recovery_codes": ["abcd-efgh", ...]
+/// }
+/// ```
+/// Error code: 208 if already enabled, 400, 403, 500
+pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    let enrollment = UserTotp::enroll(&connection, &user)?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
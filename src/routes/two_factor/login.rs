@@ -0,0 +1,65 @@
+use crate::{
+    errors::ShopError,
+    jwt,
+    models::{two_factor::TwoFactorLogin, user::User},
+    utils::{client_ip, AppState},
+};
+use actix_web::{
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+
+/// Second step of login for users with two-factor authentication enabled
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Body
+/// * pending_token: [String] - token returned from `/login`
+/// * code: [String] - current 6 digit TOTP code or unused recovery code
+///
+/// # HTTP response
+/// ##Header
+/// * Success code: 200
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * Response is in [Json] format
+/// ```
+/// {
+///     "id": "f7169845-4de5-470e-bb76-7117d4620d8c"
+///     "username": "test_user"
+/// }
+/// ```
+/// Error code: 400, 403, 429, 500
+///
+/// Wrong codes are throttled and locked out same way as wrong passwords, see [crate::lockout].
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    body: Json<TwoFactorLogin>,
+) -> Result<HttpResponse, ShopError> {
+    let claims = jwt::verify_pending(&body.pending_token)?;
+    let guard = &state.static_data.login_guard;
+    let ip = client_ip(&req);
+    let connection = state.get_pg_connection()?;
+    let username = User::get_username(&connection, &claims.sub)?;
+    let attempt = guard.check(&username, &ip)?;
+    match User::complete_two_factor(&connection, &claims, &body.code) {
+        Ok((valid, token)) => {
+            attempt.succeeded();
+            Ok(HttpResponse::Ok().append_header(("jwt", token)).json(valid))
+        }
+        Err(ShopError::NoPermission(reason)) => {
+            for lockout in attempt.failed() {
+                println!(
+                    "Locked out {} {} after {} failed two-factor codes",
+                    lockout.kind.as_str(),
+                    lockout.subject,
+                    lockout.failures
+                );
+                lockout.record(&connection)?;
+            }
+            Err(ShopError::NoPermission(reason))
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,5 @@
+//! Two-factor authentication route handling module
+pub mod activate;
+pub mod disable;
+pub mod enroll;
+pub mod login;
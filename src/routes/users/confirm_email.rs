@@ -0,0 +1,33 @@
+use crate::errors::ShopError;
+use crate::models::notification::{EmailConfirmation, NotificationSettings};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Confirms e-mail address of currently logged in (self) user, which was set by
+/// `PUT /self/notifications`, notifications and password reset tokens are sent to it since
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * token: [String] - confirmation token sent to new address
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is stored settings in [Json] format, same as of `GET /self/notifications`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    confirmation: Json<EmailConfirmation>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    NotificationSettings::confirm_email(&connection, &user.id, &confirmation.token)?;
+    let settings = NotificationSettings::get(&connection, &user.id)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
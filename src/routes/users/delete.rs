@@ -0,0 +1,37 @@
+use crate::errors::ShopError;
+use crate::models::account::{self, AccountDeletion};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Deletes account of currently logged in (self) user
+///
+/// Owned groups are handed over to another member (admins first), or deleted if
+/// there are no other members. Messages are replaced with tombstones.
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * password: [String] - current password, confirming deletion
+///
+/// Users created by single sign-on have no password, they leave it out and must have
+/// logged in through `/oidc/callback` within last few minutes instead
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    confirmation: Json<AccountDeletion>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    user.confirm_identity(&connection, confirmation.password.as_deref())?;
+    account::delete(&connection, &user)?;
+    Ok(HttpResponse::Ok().json("Successfully deleted account!"))
+}
@@ -0,0 +1,65 @@
+use crate::errors::ShopError;
+use crate::models::account::AccountExport;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Exports all personal data of currently logged in (self) user as downloadable json file
+///
+/// # HTTP request
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is `web_chat-export-{username}.json` attachment
+/// ```
+/// {
+///     "exported_at": "2022-08-26T10:30:00.000000",
+///     "user": {
+///         "id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///         "username": "test_user",
+///         "display_name": null,
+///         "avatar_url": null,
+///         "bio": null,
+///         "status_text": null,
+///         "status_emoji": null
+///     },
+///     "memberships": [
+///         {
+///             "group_id": "9780f090-82a7-47dc-a64a-c4b1ad3c978d",
+///             "group_name": "group_1",
+///             "role": "owner",
+///             "joined_at": "2022-08-01T12:00:00.000000",
+///             "owner": true
+///         }
+///     ],
+///     "messages": [
+///         {
+///             "id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///             "group_id": "9780f090-82a7-47dc-a64a-c4b1ad3c978d",
+///             "sender_id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///             "body": "Hello!",
+///             "created_at": "2022-08-01T12:01:00.000000",
+///             "deleted_at": null
+///         }
+///     ]
+/// }
+/// ```
+/// Error code: 403, 500
+pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    let export = AccountExport::build(&connection, &user.id)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "web_chat-export-{}.json",
+                user.username
+            ))],
+        })
+        .json(export))
+}
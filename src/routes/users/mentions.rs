@@ -0,0 +1,52 @@
+use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
+use crate::models::mention::MentionInfo;
+use crate::models::pagination::Pagination;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{Data, Query};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Mentions inbox of currently logged in (self) user, newest first. `kind` is `user`
+/// for `@username`, `here` for `@here` and `all` for `@all`.
+///
+/// # HTTP request
+/// ## Query
+/// * page: [i64] - page number, starting from 1 (default 1)
+/// * per_page: [i64] - page size, at most 100 (default 50)
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "page": 1,
+///     "per_page": 50,
+///     "total": 1,
+///     "items": [
+///         {
+///             "id": "0c5b3a7e-6a3f-4c1e-9d8e-1f2a3b4c5d6e",
+///             "kind": "user",
+///             "group_id": "9780f090-82a7-47dc-a64a-c4b1ad3c978d",
+///             "message_id": "1f6b6a9e-4ad1-4a55-9a0b-2d3b1e8f2b11",
+///             "sender_id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///             "sender_username": "test_user",
+///             "body": "@other_user have a look",
+///             "created_at": "2022-11-11T10:30:00.000000"
+///         }
+///     ]
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    pagination: Query<Pagination>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::UsersRead)?;
+    let connection = state.get_pg_connection()?;
+    let mentions = MentionInfo::list(&connection, &user.id, &pagination)?;
+    Ok(HttpResponse::Ok().json(mentions))
+}
@@ -0,0 +1,10 @@
+//! User and profile route handling module
+pub mod confirm_email;
+pub mod delete;
+pub mod export;
+pub mod mentions;
+pub mod notifications;
+pub mod password;
+pub mod profile;
+pub mod show;
+pub mod update_notifications;
@@ -0,0 +1,37 @@
+use crate::errors::ShopError;
+use crate::models::bot::ApiScope;
+use crate::models::notification::NotificationSettings;
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Gets global notification settings of currently logged in (self) user,
+/// settings of single groups are returned by `GET /self`. `pending_email` is address
+/// waiting for confirmation, it replaces `email` once confirmed.
+///
+/// # HTTP request
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "dnd_start": "22:00:00",
+///     "dnd_end": "07:00:00",
+///     "utc_offset_minutes": 60,
+///     "email": "user@example.com",
+///     "push_token": null,
+///     "digest_minutes": 10,
+///     "pending_email": null
+/// }
+/// ```
+/// Error code: 403, 500
+pub async fn handle(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged_with_scope(&req, ApiScope::UsersRead)?;
+    let connection = state.get_pg_connection()?;
+    let settings = NotificationSettings::get(&connection, &user.id)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
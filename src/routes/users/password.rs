@@ -0,0 +1,42 @@
+use crate::errors::ShopError;
+use crate::models::user::{PasswordChange, User};
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use validator::Validate;
+
+/// Changes password of currently logged in (self) user and revokes all his other sessions
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * old_password: [String] - current password
+///
+/// Users created by single sign-on have no password, they leave `old_password` out and
+/// must have logged in through `/oidc/callback` within last few minutes instead
+/// * new_password: [String] - minimum 8 characters long
+///
+/// # HTTP response
+/// ## Header
+/// * jwt: [String] - new JWT autorization token, previously issued tokens are no longer valid
+/// ## Body
+/// * Success code: 200
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    change: Json<PasswordChange>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    change.validate()?;
+    let (user, token) = user.change_password(
+        &connection,
+        change.old_password.as_deref(),
+        &change.new_password,
+    )?;
+    Ok(HttpResponse::Ok().append_header(("jwt", token)).json(user))
+}
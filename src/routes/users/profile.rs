@@ -0,0 +1,37 @@
+use crate::errors::ShopError;
+use crate::models::{profile::ProfileUpdate, user::User};
+use crate::utils::AppState;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use validator::Validate;
+
+/// Updates profile of currently logged in (self) user
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// All fields are optional, missing fields are left unchanged and empty strings clear the field
+/// * display_name: [String] - maximum 64 characters long
+/// * avatar_url: [String] - http(s) url of avatar image
+/// * bio: [String] - maximum 1000 characters long
+/// * status_text: [String] - maximum 128 characters long
+/// * status_emoji: [String] - maximum 32 characters long
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is updated profile in [Json] format, same as `GET /users/{user_id}`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    update: Json<ProfileUpdate>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    update.validate()?;
+    let profile = update.into_inner().apply(&connection, &user.id)?;
+    Ok(HttpResponse::Ok().json(profile))
+}
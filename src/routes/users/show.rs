@@ -0,0 +1,39 @@
+use crate::errors::ShopError;
+use crate::models::{bot::ApiScope, profile::UserProfile, user::User};
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Gets public profile of user with provided id
+///
+/// # HTTP request
+/// URL param {user_id} - user id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "id": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///     "username": "test_user",
+///     "display_name": "Test User",
+///     "avatar_url": "https://example.com/avatar.png",
+///     "bio": "Hello there",
+///     "status_text": "On vacation",
+///     "status_emoji": "🌴"
+/// }
+/// ```
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    user_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    User::is_logged_with_scope(&req, ApiScope::UsersRead)?;
+    let connection = state.get_pg_connection()?;
+    let profile = UserProfile::get(&connection, &user_id.to_string())?;
+    Ok(HttpResponse::Ok().json(profile))
+}
@@ -0,0 +1,77 @@
+use crate::errors::ShopError;
+use crate::models::notification::{NotificationSettings, NotificationSettingsUpdate};
+use crate::models::user::User;
+use crate::utils::AppState;
+use actix_web::web::{self, Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use validator::Validate;
+
+/// Changes global notification settings of currently logged in (self) user
+///
+/// # HTTP request
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * dnd_start: [Option] of time - start of daily do not disturb window, in local time
+/// * dnd_end: [Option] of time - end of the window, it may be earlier than start
+///   when window crosses midnight, window is off when either of them is missing
+/// * utc_offset_minutes: [i32] - offset of local time from UTC, from -720 to 840
+/// * email: [Option] of [String] - address for e-mail notifications, new address is used
+///   only after it is confirmed with token sent to it, see `POST /self/email/confirm`
+/// * push_token: [Option] of [String] - device token for push notifications
+/// * digest_minutes: [i32] - how long notifications about missed messages are collected
+///   into single digest, from 0 to 1440, default 10
+/// * password: [Option] of [String] - current password, required when `email` changes
+///
+/// Users created by single sign-on have no password, to change `email` they leave it out
+/// and must have logged in through `/oidc/callback` within last few minutes instead
+/// ```
+/// {
+///     "dnd_start": "22:00:00",
+///     "dnd_end": "07:00:00",
+///     "utc_offset_minutes": 60,
+///     "email": "user@example.com",
+///     "push_token": null,
+///     "digest_minutes": 10,
+///     "password": "password1"
+/// }
+/// ```
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is stored settings in [Json] format, same as of `GET /self/notifications`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    update: Json<NotificationSettingsUpdate>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let NotificationSettingsUpdate { settings, password } = update.into_inner();
+    settings.validate()?;
+    let db = state.static_data.db.clone();
+    let notifier = state.static_data.notifier.clone();
+    // notifiers block while delivering confirmation token
+    let stored = web::block(move || {
+        let connection = db.get()?;
+        let email_changed =
+            NotificationSettings::get(&connection, &user.id)?.email != settings.email;
+        if email_changed {
+            user.confirm_identity(&connection, password.as_deref())?;
+        }
+        settings.set(&connection, &user.id)?;
+        if email_changed {
+            NotificationSettings::change_email(
+                &connection,
+                notifier.as_ref(),
+                &user,
+                settings.email.as_deref(),
+            )?;
+        }
+        NotificationSettings::get(&connection, &user.id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(stored))
+}
@@ -0,0 +1,59 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::user::User;
+use crate::models::webhook::{NewWebhook, Webhook};
+use crate::utils::AppState;
+use crate::webhooks::{target, WebhookPolicy};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Registers webhook receiving events of group, allowed for group admins and owner.
+/// Payloads are signed with returned secret, see [crate::webhooks].
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// Request must be in [Json] format
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+/// ## Body
+/// * url: [String] - http(s) URL receiving `POST` requests, its host must resolve to public
+///   addresses only, see [target]
+/// * events: [Vec] of [String] - any of `message`, `join`, `leave`, `group_update`
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json] format, `secret` is shown only once
+/// ```
+/// {
+///     "id": "5b0e3f0c-4f4e-4a4b-8f7a-6f1c6f7e2a11",
+///     "group_id": "0c3a4a7e-58a4-4b0b-9a43-4bd2e7c6b0e1",
+///     "url": "https://example.com/hooks/chat",
+///     "created_by": "f7169845-4de5-470e-bb76-7117d4620d8c",
+///     "created_at": "2022-09-30T10:00:00",
+///     "events": ["join", "message"],
+///     "secret": "3f1d2a7b..."
+/// }
+/// ```
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+    new_webhook: Json<NewWebhook>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    new_webhook.validate()?;
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let policy = WebhookPolicy::from_env();
+    target::resolve(&new_webhook.url, policy.allow_private_targets).await?;
+    let webhook = Webhook::create(
+        &connection,
+        &group_id.to_string(),
+        &user,
+        new_webhook.into_inner(),
+    )?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
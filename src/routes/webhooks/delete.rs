@@ -0,0 +1,33 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::user::User;
+use crate::models::webhook::Webhook;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Deletes webhook of group together with its delivery log, allowed for group admins and owner
+///
+/// # HTTP request
+/// URL params {group_id} - group id, {webhook_id} - webhook id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+///
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let (group_id, webhook_id) = path.into_inner();
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let webhook = Webhook::get(&connection, &group_id.to_string(), &webhook_id.to_string())?;
+    Webhook::delete(&connection, &webhook)?;
+    Ok(HttpResponse::Ok().finish())
+}
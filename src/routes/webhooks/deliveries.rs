@@ -0,0 +1,61 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::pagination::Pagination;
+use crate::models::user::User;
+use crate::models::webhook::{Webhook, WebhookDelivery};
+use crate::utils::AppState;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Delivery log of webhook, newest first, allowed for group admins and owner.
+/// `status` is `pending` while attempts remain, `delivered` after 2xx response and `dead`
+/// when all attempts failed and delivery was moved to dead letters.
+///
+/// # HTTP request
+/// URL params {group_id} - group id, {webhook_id} - webhook id
+/// ## Query
+/// * page: [i64] - page number, starting from 1 (default 1)
+/// * per_page: [i64] - page size, at most 100 (default 50)
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format
+/// ```
+/// {
+///     "page": 1,
+///     "per_page": 50,
+///     "total": 1,
+///     "items": [
+///         {
+///             "id": "9a1c1f0e-0b8e-4d6e-9b7e-2f4f1c3d5e6a",
+///             "webhook_id": "5b0e3f0c-4f4e-4a4b-8f7a-6f1c6f7e2a11",
+///             "event": "message",
+///             "status": "pending",
+///             "attempts": 2,
+///             "next_attempt_at": "2022-09-30T10:00:30",
+///             "last_status_code": 502,
+///             "last_error": "Receiver responded with 502 Bad Gateway",
+///             "created_at": "2022-09-30T10:00:00",
+///             "delivered_at": null
+///         }
+///     ]
+/// }
+/// ```
+/// Error code: 400, 403, 404, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<(Uuid, Uuid)>,
+    pagination: Query<Pagination>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let (group_id, webhook_id) = path.into_inner();
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    let webhook = Webhook::get(&connection, &group_id.to_string(), &webhook_id.to_string())?;
+    let deliveries = WebhookDelivery::list(&connection, &webhook, &pagination)?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
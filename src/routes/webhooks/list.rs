@@ -0,0 +1,32 @@
+use super::require_admin;
+use crate::errors::ShopError;
+use crate::models::user::User;
+use crate::models::webhook::Webhook;
+use crate::utils::AppState;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Lists webhooks of group, allowed for group admins and owner
+///
+/// # HTTP request
+/// URL param {group_id} - group id
+/// ## Header
+/// * jwt: [String] - JWT autorization token
+///
+/// # HTTP response
+/// * Success code: 200
+/// * Response is in [Json](actix_web::web::Json) format, list of webhooks as returned on
+///   creation, without `secret`
+///
+/// Error code: 400, 403, 500
+pub async fn handle(
+    state: Data<AppState>,
+    req: HttpRequest,
+    group_id: Path<Uuid>,
+) -> Result<HttpResponse, ShopError> {
+    let user = User::is_logged(&req)?;
+    let connection = state.get_pg_connection()?;
+    require_admin(&connection, &user, &group_id.to_string())?;
+    Ok(HttpResponse::Ok().json(Webhook::list(&connection, &group_id.to_string())?))
+}
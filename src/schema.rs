@@ -1,8 +1,47 @@
+table! {
+    api_keys (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        name -> Varchar,
+        prefix -> Varchar,
+        key_hash -> Varchar,
+        scopes -> Text,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    bots (user_id) {
+        user_id -> Varchar,
+        owner_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    external_identities (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        issuer -> Varchar,
+        subject -> Varchar,
+        created_at -> Timestamp,
+        last_login_at -> Timestamp,
+    }
+}
+
 table! {
     groups (id) {
         id -> Varchar,
         owner_id -> Varchar,
         name -> Varchar,
+        topic -> Nullable<Varchar>,
+        slow_mode_seconds -> Int4,
+        last_seq -> Int8,
+        announcement_only -> Bool,
+        retention_mode -> Varchar,
+        retention_value -> Int4,
     }
 }
 
@@ -11,6 +50,160 @@ table! {
         id -> Varchar,
         user_id -> Varchar,
         group_id -> Varchar,
+        role -> Varchar,
+        joined_at -> Timestamp,
+        muted_until -> Nullable<Timestamp>,
+        last_seen_seq -> Int8,
+        notify_level -> Varchar,
+        notify_muted_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    integrations (id) {
+        id -> Varchar,
+        group_id -> Varchar,
+        name -> Varchar,
+        avatar_url -> Nullable<Varchar>,
+        token_hash -> Varchar,
+        created_by -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    lockout_events (id) {
+        id -> Varchar,
+        subject_kind -> Varchar,
+        subject -> Varchar,
+        failures -> Int4,
+        locked_until -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    mentions (id) {
+        id -> Varchar,
+        message_id -> Varchar,
+        group_id -> Varchar,
+        user_id -> Varchar,
+        kind -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    message_purges (id) {
+        id -> Varchar,
+        group_id -> Varchar,
+        message_id -> Varchar,
+        reason -> Varchar,
+        sent_at -> Timestamp,
+        purged_at -> Timestamp,
+    }
+}
+
+table! {
+    messages (id) {
+        id -> Varchar,
+        group_id -> Varchar,
+        sender_id -> Nullable<Varchar>,
+        body -> Nullable<Text>,
+        created_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        integration_id -> Nullable<Varchar>,
+        attachments -> Nullable<Text>,
+        seq -> Nullable<Int8>,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    notification_outbox (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        group_id -> Varchar,
+        message_id -> Varchar,
+        reason -> Varchar,
+        title -> Text,
+        body -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+        delivered_channels -> Varchar,
+    }
+}
+
+table! {
+    notification_settings (user_id) {
+        user_id -> Varchar,
+        dnd_start -> Nullable<Time>,
+        dnd_end -> Nullable<Time>,
+        utc_offset_minutes -> Int4,
+        email -> Nullable<Varchar>,
+        push_token -> Nullable<Varchar>,
+        digest_minutes -> Int4,
+        pending_email -> Nullable<Varchar>,
+        email_token_hash -> Nullable<Varchar>,
+        email_token_expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    pins (id) {
+        id -> Varchar,
+        group_id -> Varchar,
+        message_id -> Varchar,
+        pinned_by -> Nullable<Varchar>,
+        pinned_at -> Timestamp,
+    }
+}
+
+table! {
+    profiles (user_id) {
+        user_id -> Varchar,
+        display_name -> Nullable<Varchar>,
+        avatar_url -> Nullable<Varchar>,
+        bio -> Nullable<Text>,
+        status_text -> Nullable<Varchar>,
+        status_emoji -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    user_totp (user_id) {
+        user_id -> Varchar,
+        secret -> Varchar,
+        enabled_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
     }
 }
 
@@ -19,10 +212,104 @@ table! {
         id -> Varchar,
         username -> Varchar,
         password -> Varchar,
+        token_version -> Int4,
+    }
+}
+
+table! {
+    webhook_dead_letters (id) {
+        id -> Varchar,
+        delivery_id -> Varchar,
+        webhook_id -> Varchar,
+        event -> Varchar,
+        payload -> Text,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        message_id -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Varchar,
+        webhook_id -> Varchar,
+        event -> Varchar,
+        payload -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_status_code -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        message_id -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Varchar,
+        group_id -> Varchar,
+        url -> Varchar,
+        secret -> Varchar,
+        events -> Text,
+        created_by -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
+joinable!(api_keys -> users (user_id));
+joinable!(bots -> users (user_id));
+joinable!(external_identities -> users (user_id));
 joinable!(groups_users -> groups (group_id));
 joinable!(groups_users -> users (user_id));
+joinable!(integrations -> groups (group_id));
+joinable!(integrations -> users (created_by));
+joinable!(mentions -> groups (group_id));
+joinable!(mentions -> messages (message_id));
+joinable!(mentions -> users (user_id));
+joinable!(message_purges -> groups (group_id));
+joinable!(message_purges -> messages (message_id));
+joinable!(messages -> groups (group_id));
+joinable!(messages -> integrations (integration_id));
+joinable!(messages -> users (sender_id));
+joinable!(notification_outbox -> groups (group_id));
+joinable!(notification_outbox -> messages (message_id));
+joinable!(notification_outbox -> users (user_id));
+joinable!(notification_settings -> users (user_id));
+joinable!(password_resets -> users (user_id));
+joinable!(pins -> groups (group_id));
+joinable!(pins -> messages (message_id));
+joinable!(pins -> users (pinned_by));
+joinable!(profiles -> users (user_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(user_totp -> users (user_id));
+joinable!(webhook_dead_letters -> webhooks (webhook_id));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
+joinable!(webhooks -> groups (group_id));
+joinable!(webhooks -> users (created_by));
 
-allow_tables_to_appear_in_same_query!(groups, groups_users, users,);
+allow_tables_to_appear_in_same_query!(
+    api_keys,
+    bots,
+    external_identities,
+    groups,
+    groups_users,
+    integrations,
+    lockout_events,
+    mentions,
+    message_purges,
+    messages,
+    notification_outbox,
+    notification_settings,
+    password_resets,
+    pins,
+    profiles,
+    recovery_codes,
+    user_totp,
+    users,
+    webhook_dead_letters,
+    webhook_deliveries,
+    webhooks,
+);
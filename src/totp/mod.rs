@@ -0,0 +1,123 @@
+//! Time-based one-time passwords (RFC 6238), compatible with common authenticator apps
+//!
+//! Codes are 6 digits, HMAC-SHA1 based, with 30 seconds time step.
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Length of time step in seconds
+pub const STEP_SECONDS: i64 = 30;
+/// Number of digits in code
+pub const DIGITS: u32 = 6;
+/// Number of steps before and after current one that are still accepted, for clock drift
+pub const ALLOWED_DRIFT: i64 = 1;
+/// Number of random bytes in secret
+const SECRET_BYTES: usize = 20;
+
+const ALPHABET: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// Generates new random secret, base32 encoded
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32::encode(ALPHABET, &bytes)
+}
+
+/// Time step of provided unix timestamp
+pub fn step_at(timestamp: i64) -> i64 {
+    timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// Computes code for provided time step (HOTP of step counter, RFC 4226)
+pub fn code_at(secret: &str, step: i64) -> Option<String> {
+    let key = base32::decode(ALPHABET, secret)?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Verifies code at provided unix timestamp, allowing [ALLOWED_DRIFT] steps of clock drift
+/// # Returns
+/// * time step which matched the code, so it can be remembered and not accepted again
+pub fn verify(secret: &str, code: &str, timestamp: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let current = step_at(timestamp);
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .find(|step| code_at(secret, *step).as_deref() == Some(code))
+}
+
+/// Builds `otpauth://` URI, which authenticator apps read from QR code
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = percent_encode(issuer),
+        account = percent_encode(account),
+        secret = secret,
+        digits = DIGITS,
+        period = STEP_SECONDS
+    )
+}
+
+/// Encodes everything except unreserved characters of RFC 3986
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "12345678901234567890" from RFC 6238 test vectors, base32 encoded
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_rfc_6238_vectors() {
+        // last 6 digits of 8 digit SHA1 vectors
+        assert_eq!(code_at(RFC_SECRET, step_at(59)).unwrap(), "287082");
+        assert_eq!(code_at(RFC_SECRET, step_at(1111111109)).unwrap(), "081804");
+        assert_eq!(code_at(RFC_SECRET, step_at(1234567890)).unwrap(), "005924");
+        assert_eq!(code_at(RFC_SECRET, step_at(2000000000)).unwrap(), "279037");
+    }
+
+    #[test]
+    fn test_verify_allows_drift() {
+        let now = 1234567890;
+        let previous = code_at(RFC_SECRET, step_at(now) - 1).unwrap();
+        assert_eq!(verify(RFC_SECRET, &previous, now), Some(step_at(now) - 1));
+        let too_old = code_at(RFC_SECRET, step_at(now) - 2).unwrap();
+        assert_eq!(verify(RFC_SECRET, &too_old, now), None);
+        assert_eq!(verify(RFC_SECRET, "12345", now), None);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("web chat", "test_user", "SECRET");
+        assert_eq!(
+            uri,
+            "otpauth://totp/web%20chat:test_user?secret=SECRET&issuer=web%20chat&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
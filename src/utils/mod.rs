@@ -1,14 +1,44 @@
 //! App state and database pool connection
-use crate::{embedded_migrations::run_with_output, errors::ShopError};
+use crate::{
+    embedded_migrations::run_with_output,
+    errors::ShopError,
+    lockout::{LockoutPolicy, LoginGuard},
+    metrics::Metrics,
+    models::{
+        delivery::DedupeLimits, outbound::QueueLimits, replay::ReplayLimits, ws::FrameLimits,
+    },
+    notifier::{self, Notifier},
+    oidc::OidcClient,
+    ratelimit::MessagePolicy,
+};
+use actix_web::{dev::ServiceRequest, HttpRequest};
 use diesel::{r2d2::ConnectionManager, Connection, PgConnection};
 use dotenv::dotenv;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+pub mod token;
+
 pub type PgPooledConnection = r2d2::PooledConnection<ConnectionManager<diesel::PgConnection>>;
 pub type PgPoolConnection = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 pub struct StaticData {
     pub db: PgPoolConnection,
+    pub notifier: Arc<dyn Notifier>,
+    pub login_guard: LoginGuard,
+    /// Single sign-on provider, [None] when not configured
+    pub oidc: Option<OidcClient>,
+    /// Limits of messages sent over websocket
+    pub message_policy: MessagePolicy,
+    /// Size limits of websocket frames
+    pub frame_limits: FrameLimits,
+    /// Limits of events waiting for slow websocket clients
+    pub queue_limits: QueueLimits,
+    /// Limits of events replayed to reconnected websocket clients
+    pub replay_limits: ReplayLimits,
+    /// How long ids of messages sent by clients are remembered
+    pub dedupe_limits: DedupeLimits,
+    pub metrics: Arc<Metrics>,
 }
 
 #[derive(Clone)]
@@ -17,6 +47,24 @@ pub struct AppState {
 }
 
 impl AppState {
+    /// Creates app state around provided pool, other services are configured from .env file
+    pub fn new(db: PgPoolConnection) -> Self {
+        AppState {
+            static_data: Arc::new(StaticData {
+                db,
+                notifier: notifier::from_env(),
+                login_guard: LoginGuard::new(LockoutPolicy::from_env()),
+                oidc: OidcClient::from_env()
+                    .unwrap_or_else(|e| panic!("Invalid single sign-on configuration: {}", e)),
+                message_policy: MessagePolicy::from_env(),
+                frame_limits: FrameLimits::from_env(),
+                queue_limits: QueueLimits::from_env(),
+                replay_limits: ReplayLimits::from_env(),
+                dedupe_limits: DedupeLimits::from_env(),
+                metrics: Arc::default(),
+            }),
+        }
+    }
     /// Function for getting static reference to database
     pub fn get_pg_connection(&self) -> Result<PgPooledConnection, ShopError> {
         Ok(self.static_data.db.get()?)
@@ -25,11 +73,7 @@ impl AppState {
 
 /// Creating db pool and running migrations if necessary
 pub fn initialize() -> AppState {
-    let db_pool = get_connection_pool();
-
-    let state = AppState {
-        static_data: Arc::new(StaticData { db: db_pool }),
-    };
+    let state = AppState::new(get_connection_pool());
     let connection = state
         .get_pg_connection()
         .expect("Failed to retrieve DB connection from pool");
@@ -41,7 +85,8 @@ pub fn initialize() -> AppState {
 pub fn establish_connection() -> PgConnection {
     dotenv().ok();
     let database_url = dotenv::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
+    PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
 /// Function for returning connection pool, reading from .env file
@@ -53,3 +98,27 @@ pub fn get_connection_pool() -> PgPoolConnection {
         .build(manager)
         .expect("Failed to create database connection pool.")
 }
+
+/// Function for getting IP address of client.
+/// `X-Forwarded-For` and `Forwarded` headers are used only when `TRUST_FORWARDED_HEADERS`
+/// is set to `true` (app is behind reverse proxy), because clients can spoof them.
+pub fn client_ip(req: &HttpRequest) -> String {
+    resolve_ip(req.connection_info().realip_remote_addr(), req.peer_addr())
+}
+
+/// The same as [client_ip], for requests seen by middleware
+pub fn service_client_ip(req: &ServiceRequest) -> String {
+    resolve_ip(req.connection_info().realip_remote_addr(), req.peer_addr())
+}
+
+fn resolve_ip(forwarded: Option<&str>, peer: Option<SocketAddr>) -> String {
+    let trust_forwarded = dotenv::var("TRUST_FORWARDED_HEADERS")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+    if trust_forwarded {
+        if let Some(ip) = forwarded {
+            return ip.to_string();
+        }
+    }
+    peer.map(|addr| addr.ip().to_string()).unwrap_or_default()
+}
@@ -0,0 +1,18 @@
+//! Random secret tokens, stored in database only as hashes
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Number of random bytes in generated token
+pub const TOKEN_BYTES: usize = 32;
+
+/// Generates new random secret token, hex encoded
+pub fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes token with SHA-256 for storing and looking it up in database
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
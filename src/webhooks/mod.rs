@@ -0,0 +1,405 @@
+//! Delivery of group events to registered webhooks
+//!
+//! Events are queued into `webhook_deliveries` table by [WebhookDelivery::enqueue] and sent
+//! by background worker started with [start]. Every request is JSON `POST` with headers:
+//! * `X-Webhook-Id` - id of delivery, the same for all attempts
+//! * `X-Webhook-Event` - event name, e.g. `message`
+//! * `X-Webhook-Timestamp` - unix timestamp of attempt
+//! * `X-Webhook-Signature` - `sha256=` followed by hex encoded HMAC-SHA256 of
+//!   `<timestamp>.<body>`, keyed with secret of webhook
+//!
+//! Receivers should compare the signature in constant time and reject old timestamps.
+//! Attempts which fail or get non 2xx response are retried with exponential backoff, after
+//! last attempt delivery is moved to `webhook_dead_letters` table. Redirects are not
+//! followed and only public addresses are contacted, see [target]. Several server instances
+//! may run the worker, each delivery is claimed by only one of them.
+use crate::errors::ShopError;
+use crate::models::webhook::{DueDelivery, WebhookDelivery, STATUS_DEAD};
+use crate::utils::AppState;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+pub mod target;
+
+pub const ID_HEADER: &str = "x-webhook-id";
+pub const EVENT_HEADER: &str = "x-webhook-event";
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Limits of webhook delivery, read from .env file
+#[derive(Debug, Clone)]
+pub struct WebhookPolicy {
+    /// Attempts of single delivery before it is moved to dead letters
+    pub max_attempts: u32,
+    /// Delay after first failed attempt, doubled with every next one
+    pub base_backoff: Duration,
+    /// Upper limit of delay between attempts
+    pub max_backoff: Duration,
+    /// Time limit of single request
+    pub timeout: Duration,
+    /// How often worker looks for due deliveries
+    pub poll_interval: Duration,
+    /// Deliveries attempted in single poll
+    pub batch_size: i64,
+    /// Webhooks may point to loopback and private addresses, meant for development only
+    pub allow_private_targets: bool,
+}
+
+impl WebhookPolicy {
+    /// Reads policy from `WEBHOOK_MAX_ATTEMPTS` (default 8), `WEBHOOK_BASE_BACKOFF_IN_SECONDS`
+    /// (10), `WEBHOOK_MAX_BACKOFF_IN_SECONDS` (3600), `WEBHOOK_TIMEOUT_IN_SECONDS` (10),
+    /// `WEBHOOK_POLL_INTERVAL_IN_MILLIS` (1000), `WEBHOOK_BATCH_SIZE` (50) and
+    /// `WEBHOOK_ALLOW_PRIVATE_TARGETS` (false)
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: u64| {
+            dotenv::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        WebhookPolicy {
+            max_attempts: read("WEBHOOK_MAX_ATTEMPTS", 8).max(1) as u32,
+            base_backoff: Duration::from_secs(read("WEBHOOK_BASE_BACKOFF_IN_SECONDS", 10)),
+            max_backoff: Duration::from_secs(read("WEBHOOK_MAX_BACKOFF_IN_SECONDS", 3600)),
+            timeout: Duration::from_secs(read("WEBHOOK_TIMEOUT_IN_SECONDS", 10)),
+            poll_interval: Duration::from_millis(read("WEBHOOK_POLL_INTERVAL_IN_MILLIS", 1000)),
+            batch_size: read("WEBHOOK_BATCH_SIZE", 50) as i64,
+            allow_private_targets: dotenv::var("WEBHOOK_ALLOW_PRIVATE_TARGETS")
+                .map(|value| value == "true")
+                .unwrap_or(false),
+        }
+    }
+
+    /// Checks if delivery should be moved to dead letters after `attempts` failed ones
+    pub fn gives_up(&self, attempts: u32) -> bool {
+        attempts >= self.max_attempts
+    }
+
+    /// Delay before next attempt after `attempts` failed ones
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Signature of payload sent in [SIGNATURE_HEADER]
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Starts worker delivering queued events, it runs as long as the server
+pub fn start(state: AppState) {
+    let policy = WebhookPolicy::from_env();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(policy.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = deliver_due(&state, &policy).await {
+                println!("Webhook worker failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Attempts every due delivery once
+async fn deliver_due(state: &AppState, policy: &WebhookPolicy) -> Result<(), ShopError> {
+    let due = {
+        let connection = state.get_pg_connection()?;
+        // attempts of whole batch have to finish before other workers may claim it again
+        let lease = policy.timeout * (policy.batch_size.max(1) as u32 + 1);
+        WebhookDelivery::claim_due(&connection, policy.batch_size, lease)?
+    };
+    for mut due in due {
+        process(policy, &mut due).await;
+        let connection = state.get_pg_connection()?;
+        due.delivery.save(&connection)?;
+        if due.delivery.status == STATUS_DEAD {
+            println!("Webhook delivery {} moved to dead letters", due.delivery.id);
+        }
+    }
+    Ok(())
+}
+
+/// Attempts delivery once and applies result to it, delivery has to be saved afterwards
+async fn process(policy: &WebhookPolicy, due: &mut DueDelivery) -> Outcome {
+    let outcome = deliver(policy, due).await;
+    let now = Utc::now().naive_utc();
+    match &outcome {
+        Outcome::Delivered(status_code) => due.delivery.succeeded(*status_code, now),
+        Outcome::Failed { status_code, error } => {
+            due.delivery.failed(policy, *status_code, error, now);
+        }
+    }
+    outcome
+}
+
+/// Result of single attempt of delivery
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Receiver responded with 2xx status code
+    Delivered(u16),
+    /// Receiver could not be reached or responded with other status code
+    Failed {
+        status_code: Option<u16>,
+        error: String,
+    },
+}
+
+/// Attempts delivery once
+async fn deliver(policy: &WebhookPolicy, due: &DueDelivery) -> Outcome {
+    match attempt(policy, due).await {
+        Ok(status) if status.is_success() => Outcome::Delivered(status.as_u16()),
+        Ok(status) => Outcome::Failed {
+            status_code: Some(status.as_u16()),
+            error: format!("Receiver responded with {}", status),
+        },
+        Err(e) => Outcome::Failed {
+            status_code: None,
+            error: e.to_string(),
+        },
+    }
+}
+
+/// Sends single signed request, to address of webhook host checked right before it
+async fn attempt(
+    policy: &WebhookPolicy,
+    due: &DueDelivery,
+) -> Result<reqwest::StatusCode, ShopError> {
+    let target = target::resolve(&due.url, policy.allow_private_targets).await?;
+    let mut client = reqwest::Client::builder()
+        .timeout(policy.timeout)
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(domain) = target.url.domain() {
+        // connect to checked address, host could resolve differently on next lookup
+        client = client.resolve(domain, target.addr);
+    }
+    let timestamp = Utc::now().timestamp();
+    let response = client
+        .build()?
+        .post(target.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(ID_HEADER, &due.delivery.id)
+        .header(EVENT_HEADER, &due.delivery.event)
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(
+            SIGNATURE_HEADER,
+            sign(&due.secret, timestamp, &due.delivery.payload),
+        )
+        .body(due.delivery.payload.clone())
+        .send()
+        .await?;
+    Ok(response.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::webhook::{WebhookDelivery, STATUS_DELIVERED, STATUS_PENDING};
+    use chrono::NaiveDateTime;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// Headers and body of request received by [receiver]
+    type Received = (HashMap<String, String>, String);
+
+    /// Local receiver responding with `statuses` in order, the last one is repeated
+    fn receiver(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.to_string())
+                        }
+                        None => break,
+                    };
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                log.lock()
+                    .unwrap()
+                    .push((headers, String::from_utf8(body).unwrap()));
+                let status = statuses[i.min(statuses.len() - 1)];
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn due(url: String) -> DueDelivery {
+        let at = NaiveDateTime::from_timestamp(0, 0);
+        DueDelivery {
+            delivery: WebhookDelivery {
+                id: "delivery".to_string(),
+                webhook_id: "webhook".to_string(),
+                event: "message".to_string(),
+                payload: r#"{"event":"message"}"#.to_string(),
+                status: "pending".to_string(),
+                attempts: 0,
+                next_attempt_at: at,
+                last_status_code: None,
+                last_error: None,
+                created_at: at,
+                delivered_at: None,
+                message_id: None,
+            },
+            url,
+            secret: "secret".to_string(),
+        }
+    }
+
+    fn policy(max_attempts: u32) -> WebhookPolicy {
+        WebhookPolicy {
+            max_attempts,
+            base_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_secs(1),
+            batch_size: 10,
+            allow_private_targets: true,
+        }
+    }
+
+    /// Processes delivery with worker until it is no longer pending, without saving it
+    /// # Returns
+    /// * outcomes of attempts
+    fn run(policy: &WebhookPolicy, due: &mut DueDelivery) -> Vec<Outcome> {
+        actix_web::rt::System::new().block_on(async {
+            let mut outcomes = Vec::new();
+            while due.delivery.status == STATUS_PENDING {
+                let before = Utc::now().naive_utc();
+                outcomes.push(process(policy, due).await);
+                if due.delivery.status == STATUS_PENDING {
+                    let backoff = policy.backoff(due.delivery.attempts as u32);
+                    assert!(
+                        due.delivery.next_attempt_at
+                            >= before + chrono::Duration::from_std(backoff).unwrap()
+                    );
+                }
+            }
+            outcomes
+        })
+    }
+
+    #[test]
+    fn test_delivery_is_signed_and_retried() {
+        let (url, received) = receiver(vec![500, 204]);
+        let mut due = due(url);
+        let outcomes = run(&policy(3), &mut due);
+        assert_eq!(due.delivery.status, STATUS_DELIVERED);
+        assert_eq!(due.delivery.attempts, 2);
+        assert_eq!(due.delivery.last_status_code, Some(204));
+        assert_eq!(due.delivery.last_error, None);
+        assert!(due.delivery.delivered_at.is_some());
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Failed {
+                    status_code: Some(500),
+                    error: "Receiver responded with 500 Internal Server Error".to_string(),
+                },
+                Outcome::Delivered(204),
+            ]
+        );
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        for (headers, body) in received.iter() {
+            assert_eq!(body, r#"{"event":"message"}"#);
+            assert_eq!(headers[ID_HEADER], "delivery");
+            assert_eq!(headers[EVENT_HEADER], "message");
+            let timestamp = headers[TIMESTAMP_HEADER].parse().unwrap();
+            assert_eq!(headers[SIGNATURE_HEADER], sign("secret", timestamp, body));
+        }
+    }
+
+    #[test]
+    fn test_delivery_is_dead_lettered() {
+        let (url, received) = receiver(vec![503]);
+        let mut dead = due(url);
+        let outcomes = run(&policy(2), &mut dead);
+        assert_eq!(dead.delivery.status, STATUS_DEAD);
+        assert_eq!(dead.delivery.attempts, 2);
+        assert_eq!(dead.delivery.last_status_code, Some(503));
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(received.lock().unwrap().len(), 2);
+        // private receivers are refused unless explicitly allowed
+        let (url, received) = receiver(vec![200]);
+        let mut strict = policy(1);
+        strict.allow_private_targets = false;
+        let mut refused = due(url);
+        let outcomes = run(&strict, &mut refused);
+        assert_eq!(refused.delivery.status, STATUS_DEAD);
+        assert_eq!(refused.delivery.last_status_code, None);
+        assert!(matches!(
+            outcomes[0],
+            Outcome::Failed {
+                status_code: None,
+                ..
+            }
+        ));
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_signature() {
+        // echo -n '1664532000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1_664_532_000, r#"{"a":1}"#),
+            "sha256=4995a7a77644fab081bfdcef6581f3f4258b7983f119e7647bf358903790f912"
+        );
+        assert_ne!(
+            sign("secret", 1_664_532_001, r#"{"a":1}"#),
+            sign("secret", 1_664_532_000, r#"{"a":1}"#)
+        );
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = WebhookPolicy {
+            max_attempts: 5,
+            base_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(1),
+            poll_interval: Duration::from_secs(1),
+            batch_size: 10,
+            allow_private_targets: false,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(10));
+        assert_eq!(policy.backoff(2), Duration::from_secs(20));
+        assert_eq!(policy.backoff(3), Duration::from_secs(40));
+        assert_eq!(policy.backoff(4), Duration::from_secs(60));
+        assert_eq!(policy.backoff(40), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,163 @@
+//! Checks of webhook URLs, so webhooks cannot be used to reach internal services
+//!
+//! Loopback, private, link-local and other non public addresses are rejected both when
+//! webhook is registered and before every delivery, because host name may resolve
+//! to different address later. Delivery then connects to the checked address only.
+//! Local receivers can be allowed with `WEBHOOK_ALLOW_PRIVATE_TARGETS=true` for development.
+use crate::errors::ShopError;
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// Checked URL of webhook together with address which requests should be sent to
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: Url,
+    pub addr: SocketAddr,
+}
+
+/// Checks if IPv4 address is reachable from public internet
+fn is_public_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "this network" 0.0.0.0/8, shared address space 100.64.0.0/10 and 240.0.0.0/4
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || a >= 240)
+}
+
+/// Checks if IPv6 address is reachable from public internet
+fn is_public_v6(ip: &Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    if segments[..5] == [0; 5] && segments[5] == 0xffff {
+        // IPv4-mapped ::ffff:a.b.c.d
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(&Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local fc00::/7 and link-local fe80::/10
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80)
+}
+
+/// Checks if address is reachable from public internet
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+/// Checks scheme and host of URL, without resolving host name
+/// # Returns
+/// ## On success
+/// * parsed URL: [Url]
+/// ## On faliure
+/// * error: [ShopError::ParseError] for URL which is not http(s), or which points to
+///   `localhost` or non public address
+pub fn parse(url: &str, allow_private: bool) -> Result<Url, ShopError> {
+    let url = Url::parse(url).map_err(|e| ShopError::ParseError(e.to_string()))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(ShopError::ParseError(
+            "Webhook URL must use http or https".to_string(),
+        ));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| ShopError::ParseError("Webhook URL must have host".to_string()))?
+        .trim_end_matches('.')
+        .to_lowercase();
+    if allow_private {
+        return Ok(url);
+    }
+    let ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>();
+    let private = match ip {
+        Ok(ip) => !is_public(&ip),
+        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+    };
+    if private {
+        return Err(ShopError::ParseError(
+            "Webhook URL must not point to local or private address".to_string(),
+        ));
+    }
+    Ok(url)
+}
+
+/// Parses URL and resolves its host, every address it resolves to must be public
+/// # Returns
+/// ## On success
+/// * checked target: [Target]
+/// ## On faliure
+/// * error: [ShopError::ParseError] for invalid or private target,
+///   [ShopError::ConnectionError] if host cannot be resolved
+pub async fn resolve(url: &str, allow_private: bool) -> Result<Target, ShopError> {
+    let url = parse(url, allow_private)?;
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = actix_web::web::block(move || {
+        (host.trim_start_matches('[').trim_end_matches(']'), port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+    })
+    .await?
+    .map_err(|e| ShopError::ConnectionError(format!("Couldn't resolve webhook host: {}", e)))?;
+    if !allow_private && addrs.iter().any(|addr| !is_public(&addr.ip())) {
+        return Err(ShopError::ParseError(
+            "Webhook URL must not point to local or private address".to_string(),
+        ));
+    }
+    let addr = *addrs
+        .first()
+        .ok_or_else(|| ShopError::ConnectionError("Webhook host has no address".to_string()))?;
+    Ok(Target { url, addr })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_private_hosts() {
+        assert!(parse("https://example.com/hook", false).is_ok());
+        assert!(parse("ftp://example.com/hook", false).is_err());
+        assert!(parse("http://localhost:8080/hook", false).is_err());
+        assert!(parse("http://api.localhost/hook", false).is_err());
+        assert!(parse("http://127.0.0.1/hook", false).is_err());
+        assert!(parse("http://[::1]/hook", false).is_err());
+        assert!(parse("http://169.254.169.254/latest/meta-data", false).is_err());
+        assert!(parse("http://127.0.0.1/hook", true).is_ok());
+    }
+}